@@ -1,59 +1,215 @@
 use rt::{
     rt::RayTracer,
-    scene::canvas::Canvas,
-    utils::{material::IMaterial, matrix::Mat, vec3::Vec3},
-    world::{
-        camera::Camera, light::Light, shapes::sphere::Sphere, transform::Transformable, w::World,
+    scene::{
+        filter,
+        framebuffer::{ExrPixel, FrameBuffer},
+        loader::{self, SceneError},
+        renderer::Renderer,
+        sampler,
+        tonemap::{self, Transfer},
     },
 };
-use std::f32::consts::PI;
+use std::{env, path::Path, process};
+
+const USAGE: &str = "usage: rt <scene> [options]
+
+options:
+  -o, --output <file>     output image, format from the extension (ppm, png, pfm, exr) [default: out.ppm]
+      --png16             write 16 bit samples when the output is a png
+      --exr-float         write 32 bit floats instead of halfs when the output is an exr
+  -m, --tonemap <op>      clamp, exposure[:k], reinhard, reinhard-extended[:white],
+                          aces or uncharted2[:white] [default: clamp]
+      --linear            store 8 bit outputs without the sRGB curve
+  -s, --size <w>x<h>      override the camera resolution
+  -d, --depth <n>         maximum reflection/refraction depth [default: 10]
+  -t, --threads <n>       render threads [default: available cores]
+  -n, --samples <n>       rays per pixel [default: 1]
+      --sampler <name>    grid, jittered, halton or sobol [default: grid]
+      --seed <n>          seed of the random samplers [default: 0]
+  -f, --filter <f>        box, tent, gaussian, mitchell or lanczos, with an optional
+                          :radius in pixels [default: box:0.5]
+  -q, --quiet             do not print render statistics
+  -h, --help              print this message";
+
+const FORMATS: [&str; 4] = ["ppm", "png", "pfm", "exr"];
+
+// exit codes
+const USAGE_ERROR: i32 = 1;
+const PARSE_ERROR: i32 = 2;
+const IO_ERROR: i32 = 3;
+
+struct Options {
+    scene: String,
+    output: String,
+    size: Option<(u32, u32)>,
+    depth: Option<usize>,
+    threads: Option<usize>,
+    samples: usize,
+    quiet: bool,
+    png16: bool,
+    exr_float: bool,
+    tonemap: String,
+    linear: bool,
+    sampler: String,
+    seed: u64,
+    filter: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        scene: String::new(),
+        output: "out.ppm".to_string(),
+        size: None,
+        depth: None,
+        threads: None,
+        samples: 1,
+        quiet: false,
+        png16: false,
+        exr_float: false,
+        tonemap: "clamp".to_string(),
+        linear: false,
+        sampler: "grid".to_string(),
+        seed: 0,
+        filter: "box".to_string(),
+    };
+    let mut scene = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || {
+            it.next()
+                .ok_or_else(|| format!("missing value for `{}`", arg))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-o" | "--output" => opts.output = value()?.clone(),
+            "-s" | "--size" => opts.size = Some(parse_size(value()?)?),
+            "-d" | "--depth" => opts.depth = Some(parse_number(arg, value()?)?),
+            "-t" | "--threads" => opts.threads = Some(parse_number(arg, value()?)?),
+            "-n" | "--samples" => opts.samples = parse_number(arg, value()?)?,
+            "-q" | "--quiet" => opts.quiet = true,
+            "--png16" => opts.png16 = true,
+            "--exr-float" => opts.exr_float = true,
+            "-m" | "--tonemap" => opts.tonemap = value()?.clone(),
+            "--linear" => opts.linear = true,
+            "--sampler" => opts.sampler = value()?.clone(),
+            "-f" | "--filter" => opts.filter = value()?.clone(),
+            "--seed" => opts.seed = parse_number(arg, value()?)? as u64,
+            a if a.starts_with('-') => return Err(format!("unknown option `{}`", a)),
+            a if scene.is_none() => scene = Some(a.to_string()),
+            a => return Err(format!("unexpected argument `{}`", a)),
+        }
+    }
+    opts.scene = scene.ok_or("missing scene file")?;
+    if opts.samples == 0 {
+        return Err("`--samples` must be at least 1".to_string());
+    }
+    if !FORMATS.iter().any(|e| has_extension(&opts.output, e)) {
+        return Err(format!("unsupported output format `{}`", opts.output));
+    }
+    Ok(opts)
+}
+
+fn parse_number(name: &str, v: &str) -> Result<usize, String> {
+    v.parse()
+        .map_err(|_| format!("invalid value `{}` for `{}`", v, name))
+}
+
+fn parse_size(v: &str) -> Result<(u32, u32), String> {
+    let err = || format!("invalid size `{}`, expected <width>x<height>", v);
+    let (w, h) = v.split_once('x').ok_or_else(err)?;
+    match (w.parse(), h.parse()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(err()),
+    }
+}
+
+fn has_extension(path: &str, ext: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
 
 fn main() {
-    let camera = Camera::new(
-        1000,
-        1000,
-        PI * 0.33,
-        Mat::view_transformation(
-            &Vec3::new(0.0, 1.5, -5.0),
-            &Vec3::new(0.0, 1.0, 0.0),
-            &Vec3::new(0.0, 1.0, 0.0),
-        ),
-    );
-
-    let mut canvas = Canvas::new(camera.width, camera.height);
-
-    let lights = vec![
-        Light::new(Vec3::new(-10.0, 10.0, -10.0), Vec3::new(1.0, 0.5, 1.0)),
-        Light::new(Vec3::new(-10.5, 1.0, -10.75), Vec3::from_float(1.0)),
-    ];
-
-    let spheres = vec![
-        Sphere::default()
-            .color(0.0, 1.0, 1.0)
-            .diffuse(0.7)
-            .reflective(0.5)
-            .specular(1.0)
-            .translation(-0.5, 1.0, 0.5)
-            .scaling(1.0, 1.0, 1.0),
-        Sphere::default()
-            .color(1.0, 0.2, 1.0)
-            .diffuse(0.7)
-            .translation(0.5, -0.0, -0.5)
-            .scaling(0.5, 0.2, 0.5),
-        Sphere::default()
-            .color(1.0, 1.0, 0.0)
-            .diffuse(0.7)
-            .specular(1.0)
-            .translation(-1.5, 1.0, -0.5)
-            .scaling(0.33, 0.33, 0.33),
-    ];
-
-    let rt = RayTracer::new(World::new(camera, lights, spheres));
-    canvas.for_each(|pixel, x, y| {
-        let ray = rt.world().camera.get_ray(x, y);
-        let color = rt.trace(&ray, 0);
-        color.apply(pixel)
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("rt: {}\n\n{}", e, USAGE);
+        process::exit(USAGE_ERROR);
+    });
+
+    let tone_map = tonemap::parse(&opts.tonemap).unwrap_or_else(|e| {
+        eprintln!("rt: {}\n\n{}", e, USAGE);
+        process::exit(USAGE_ERROR);
+    });
+
+    let sampler = sampler::parse(&opts.sampler, opts.seed).unwrap_or_else(|e| {
+        eprintln!("rt: {}\n\n{}", e, USAGE);
+        process::exit(USAGE_ERROR);
     });
 
-    canvas.export_ppm("file.ppm").ok();
+    let filter = filter::parse(&opts.filter).unwrap_or_else(|e| {
+        eprintln!("rt: {}\n\n{}", e, USAGE);
+        process::exit(USAGE_ERROR);
+    });
+
+    let world = loader::load(&opts.scene).unwrap_or_else(|e| {
+        let code = match e {
+            SceneError::Parse(_) => PARSE_ERROR,
+            SceneError::Io(_) => IO_ERROR,
+        };
+        eprintln!("rt: {}: {}", opts.scene, e);
+        process::exit(code);
+    });
+
+    let mut rt = RayTracer::new(world);
+    if let Some((w, h)) = opts.size {
+        rt.update_size(w, h);
+    }
+    if let Some(depth) = opts.depth {
+        rt.max_depth = depth;
+    }
+    rt.output.tone_map = tone_map;
+    if !opts.linear {
+        rt.output.transfer = Transfer::Srgb;
+    }
+    let camera = &rt.world().camera;
+    let mut fb = FrameBuffer::new(camera.width, camera.height);
+
+    let mut renderer = match opts.threads {
+        Some(n) => Renderer::new(n),
+        None => Renderer::default(),
+    };
+    renderer.sampler = sampler.into();
+    renderer.filter = filter.into();
+    let stats = renderer.samples(opts.samples).render_hdr(&rt, &mut fb);
+
+    let out = &opts.output;
+    let written = if has_extension(out, "pfm") {
+        fb.export_pfm(out)
+    } else if has_extension(out, "exr") {
+        let pixel = if opts.exr_float {
+            ExrPixel::Float
+        } else {
+            ExrPixel::Half
+        };
+        fb.export_exr(out, pixel)
+    } else if has_extension(out, "png") && opts.png16 {
+        fb.export_png16(out, &rt.output)
+    } else {
+        let canvas = fb.to_canvas(&rt.output);
+        if has_extension(out, "png") {
+            canvas.export_png(out)
+        } else {
+            canvas.export_ppm(out)
+        }
+    };
+    if let Err(e) = written {
+        eprintln!("rt: {}: {}", opts.output, e);
+        process::exit(IO_ERROR);
+    }
+    if !opts.quiet {
+        eprintln!("{}", stats);
+    }
 }
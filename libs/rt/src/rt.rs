@@ -1,23 +1,39 @@
 use crate::{
-    utils::{comp::Comp, ray::Ray, vec3::Vec3},
-    world::{light::Light, w::World},
+    scene::tonemap::Output,
+    utils::{
+        comp::Comp,
+        ray::{Differentials, Ray},
+        stats,
+        vec3::{Float, Vec3},
+    },
+    world::{
+        light::{Light, LightSample},
+        w::{Intersection, World},
+    },
 };
 
 pub struct RayTracer {
     pub world: World,
+    // bounces allowed for reflected and refracted rays
+    pub max_depth: usize,
+    // how radiance ends up on a canvas
+    pub output: Output,
 }
 
 impl RayTracer {
     pub fn new(world: World) -> Self {
-        Self { world }
+        Self {
+            world,
+            max_depth: 10,
+            output: Output::default(),
+        }
     }
 
-    fn is_shadow(&self, ray: &Ray, comp: &Comp) -> bool {
-        self.world
-            .intersect(ray, Vec::new())
-            .iter()
-            .filter(|i| !std::ptr::eq(i.sp, comp.intersection.sp))
-            .any(|i| i.t < 0.0)
+    // whether something sits between `point` and the light
+    fn is_shadow(&self, point: &Vec3, sample: &LightSample) -> bool {
+        stats::count_ray();
+        let ray = Ray::new(point.clone(), sample.dir.clone());
+        self.world.is_occluded(&ray, sample.distance)
     }
 
     pub fn update_size(&mut self, width: u32, height: u32) {
@@ -26,22 +42,59 @@ impl RayTracer {
 
     pub fn trace(&self, ray: &Ray, depth: usize) -> Vec3 {
         let bg = Vec3::zero();
-        if depth > 10 {
+        if depth > self.max_depth {
             return bg;
         }
-        let container = Vec::with_capacity(self.world.spheres.len());
+        stats::count_ray();
+        let container = Vec::with_capacity(self.world.shapes().len());
         let intersections = self.world.intersect(ray, container);
-        if let Some(nearest) = intersections.first() {
-            let comps = Comp::prepare_comp(ray, nearest);
-            let mut surface = self.reflected_color(&comps, depth);
+        if let Some(nearest) = Intersection::hit(&intersections) {
+            let comps = Comp::prepare_comp(ray, nearest, &intersections);
+            let mut surface = Vec3::zero();
             for light in self.world.lights.iter() {
-                surface = surface + self.shade_hit(&comps, light);
+                surface = surface + self.shade_hit(&comps, light.as_ref());
+            }
+            let reflected = self.reflected_color(&comps, depth);
+            let refracted = self.refracted_color(&comps, depth);
+
+            let m = &comps.finish;
+            if m.reflective > 0.0 && m.transparency > 0.0 {
+                let reflectance = comps.schlick();
+                return surface + reflected * reflectance + refracted * (1.0 - reflectance);
             }
-            return surface;
+            return surface + reflected + refracted;
         }
         bg
     }
 
+    /// Color seen through pixel `(x, y)` of the camera.
+    pub fn color_at(&self, x: u32, y: u32) -> Vec3 {
+        let ray = self.world.camera.get_ray(x, y);
+        self.trace(&ray, 0)
+    }
+
+    /// Same as `color_at` for a point inside the pixel, `(0.0, 0.0)` being
+    /// its corner.
+    pub fn color_at_offset(&self, x: u32, y: u32, dx: Float, dy: Float) -> Vec3 {
+        self.color_at_sample(x, y, (dx, dy), (0.5, 0.5))
+    }
+
+    /// `color_at_offset` with the ray leaving from the point `lens` of the
+    /// unit square mapped onto the camera aperture.
+    pub fn color_at_sample(
+        &self,
+        x: u32,
+        y: u32,
+        (dx, dy): (Float, Float),
+        (u, v): (Float, Float),
+    ) -> Vec3 {
+        let ray = self
+            .world
+            .camera
+            .get_lens_ray(x as Float + dx, y as Float + dy, u, v);
+        self.trace(&ray, 0)
+    }
+
     pub fn world(&self) -> &World {
         &self.world
     }
@@ -50,40 +103,438 @@ impl RayTracer {
         &mut self.world
     }
 
-    fn shade_hit(&self, c: &Comp, light: &Light) -> Vec3 {
-        let mut specular = Vec3::zero();
-        let mut diff = Vec3::zero();
-
-        let m = &c.intersection.sp.get_material();
-        let color = &m.color * &light.intensity;
-        let ray = light.ray_at(&c.hitp);
-        let light_dot = (-&ray.dir).dot(&c.normalv);
+    fn shade_hit(&self, c: &Comp, light: &dyn Light) -> Vec3 {
+        let m = &c.finish;
+        // the offset point keeps flat patterns such as checkers on a plane
+        // from flickering between two cells
+        let base = c
+            .intersection
+            .sp
+            .filtered_color_at(&c.over_point, &c.dpdx, &c.dpdy);
+        let ambient = &base * light.intensity() * m.ambient;
 
-        let intersected_with_light = light_dot >= 0.0;
-        if intersected_with_light && !self.is_shadow(&ray, c) {
-            diff = &color * m.diffuse * light_dot;
+        // diffuse and specular averaged over the samples, partly hidden
+        // area lights give penumbrae
+        let samples = light.samples(&c.over_point);
+        let mut lit = Vec3::zero();
+        for s in samples.iter() {
+            let light_dot = s.dir.dot(&c.normalv);
+            if light_dot < 0.0 || self.is_shadow(&c.over_point, s) {
+                continue;
+            }
+            let color = &base * &s.intensity;
+            lit = lit + &color * m.diffuse * light_dot;
 
-            let reflect = ray.dir.reflect(&c.normalv);
+            let reflect = (-&s.dir).reflect(&c.normalv);
             let reflect_dot = reflect.dot(&c.eyev);
 
             if reflect_dot > 0.0 {
                 let factor = reflect_dot.powf(m.shininess);
-                specular = &light.intensity * m.specular * factor;
+                lit = lit + &s.intensity * m.specular * factor;
             }
         }
-        (&color * m.ambient) + diff + specular
+        ambient + lit / samples.len() as Float
     }
 
     fn reflected_color(&self, comp: &Comp, depth: usize) -> Vec3 {
-        let nearest = comp.intersection;
-        let material = nearest.sp.get_material();
-        if material.reflective > 0.0 && depth < 10 {
-            self.trace(
-                &Ray::new(comp.hitp.clone(), comp.reflectv.clone()),
-                depth + 1,
-            ) * material.reflective
+        let reflective = comp.finish.reflective;
+        if reflective > 0.0 && depth < self.max_depth {
+            // the neighbours bounce off the same flat mirror, curvature
+            // does not spread them any further
+            // leaves from above the surface like shadow rays, so a hit point
+            // rounded below it does not see the surface again
+            let mut ray = Ray::new(comp.over_point.clone(), comp.reflectv.clone());
+            if let Some(d) = &comp.diff {
+                ray = ray.with_differentials(Differentials {
+                    rx_org: &comp.over_point + &comp.dpdx,
+                    rx_dir: d.rx_dir.reflect(&comp.normalv),
+                    ry_org: &comp.over_point + &comp.dpdy,
+                    ry_dir: d.ry_dir.reflect(&comp.normalv),
+                });
+            }
+            self.trace(&ray, depth + 1) * reflective
         } else {
             Vec3::zero()
         }
     }
+
+    fn refracted_color(&self, comp: &Comp, depth: usize) -> Vec3 {
+        let transparency = comp.finish.transparency;
+        if transparency == 0.0 || depth >= self.max_depth {
+            return Vec3::zero();
+        }
+        let n_ratio = comp.n1 / comp.n2;
+        let Some(dir) = refract(&comp.eyev, &comp.normalv, n_ratio) else {
+            return Vec3::zero();
+        };
+        let mut ray = Ray::new(comp.under_point.clone(), dir);
+        if let Some(d) = &comp.diff {
+            let rx = refract(&-&d.rx_dir, &comp.normalv, n_ratio);
+            let ry = refract(&-&d.ry_dir, &comp.normalv, n_ratio);
+            if let (Some(rx_dir), Some(ry_dir)) = (rx, ry) {
+                ray = ray.with_differentials(Differentials {
+                    rx_org: &comp.under_point + &comp.dpdx,
+                    rx_dir,
+                    ry_org: &comp.under_point + &comp.dpdy,
+                    ry_dir,
+                });
+            }
+        }
+        self.trace(&ray, depth + 1) * transparency
+    }
+}
+
+// Snell's law for light arriving from `eyev`, `None` on total internal
+// reflection
+fn refract(eyev: &Vec3, normalv: &Vec3, n_ratio: Float) -> Option<Vec3> {
+    let eyev = eyev.norm();
+    let cos_i = eyev.dot(normalv);
+    let sin2_t = n_ratio * n_ratio * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(normalv * (n_ratio * cos_i - cos_t) - eyev * n_ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        scene::texture::{Filter, MipMap, Texture},
+        utils::{
+            material::{IMaterial, Param, Varying},
+            matrix::Mat,
+            noise::Perlin,
+        },
+        world::{
+            camera::Camera,
+            light::{AreaLight, DirectionalLight, PointLight, SpotLight},
+            pattern::{Checker, ImageTexture},
+            shapes::{plane::Plane, shape::Shape, sphere::Sphere},
+            transform::Transformable,
+            uv::UvMap,
+        },
+    };
+
+    fn world(shapes: Vec<Box<dyn Shape>>) -> RayTracer {
+        let camera = Camera::new(10, 10, 1.0, Mat::identity(4));
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            Vec3::new(-10.0, 10.0, -10.0),
+            Vec3::from_float(1.0),
+        ))];
+        RayTracer::new(World::new(camera, lights, shapes))
+    }
+
+    #[test]
+    fn test_refracted_color_opaque() {
+        let rt = world(vec![Box::new(Sphere::default())]);
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = rt.world().intersect(&r, Vec::new());
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert_eq!(rt.refracted_color(&c, 0), Vec3::zero());
+    }
+
+    #[test]
+    fn test_refracted_color_total_internal_reflection() {
+        let f = std::f32::consts::FRAC_1_SQRT_2;
+        let rt = world(vec![Box::new(
+            Sphere::default().transparency(1.0).refractive_index(1.5),
+        )]);
+        let r = Ray::new(Vec3::new(0.0, 0.0, f), Vec3::new(0.0, 1.0, 0.0));
+        let xs = rt.world().intersect(&r, Vec::new());
+        let c = Comp::prepare_comp(&r, &xs[1], &xs);
+
+        assert_eq!(rt.refracted_color(&c, 0), Vec3::zero());
+    }
+
+    #[test]
+    fn test_refracted_color_max_depth() {
+        let rt = world(vec![Box::new(
+            Sphere::default().transparency(1.0).refractive_index(1.5),
+        )]);
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = rt.world().intersect(&r, Vec::new());
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert_eq!(rt.refracted_color(&c, 10), Vec3::zero());
+    }
+
+    #[test]
+    fn test_trace_transparent_floor() {
+        let f = std::f32::consts::FRAC_1_SQRT_2;
+        let mut w = World::default();
+        w.add_shape(Box::new(
+            Plane::default()
+                .transparency(0.5)
+                .refractive_index(1.5)
+                .translation(0.0, -1.0, 0.0),
+        ));
+        w.add_shape(Box::new(
+            Sphere::default()
+                .color(1.0, 0.0, 0.0)
+                .ambient(0.5)
+                .translation(0.0, -3.5, -0.5),
+        ));
+        let rt = RayTracer::new(w);
+        let r = Ray::new(Vec3::new(0.0, 0.0, -3.0), Vec3::new(0.0, -f, f));
+        let c = rt.trace(&r, 5);
+
+        assert_eq!(c, Vec3::new(0.93642, 0.68642, 0.68642));
+    }
+
+    #[test]
+    fn test_is_shadow() {
+        let rt = RayTracer::new(World::default());
+        let cases = [
+            // nothing on the line between the point and the light
+            (Vec3::new(0.0, 10.0, 0.0), false),
+            // the spheres sit between the point and the light
+            (Vec3::new(10.0, -10.0, 10.0), true),
+            // the spheres are beyond the light
+            (Vec3::new(-20.0, 20.0, -20.0), false),
+            // the spheres are behind the point
+            (Vec3::new(-2.0, 2.0, -2.0), false),
+        ];
+        for (p, shadowed) in cases {
+            let sample = &rt.world.lights[0].samples(&p)[0];
+            assert_eq!(rt.is_shadow(&p, sample), shadowed, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_shade_hit_in_shadow() {
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            Vec3::new(0.0, 0.0, -10.0),
+            Vec3::from_float(1.0),
+        ))];
+        let spheres: Vec<Box<dyn Shape>> = vec![
+            Box::new(Sphere::default()),
+            Box::new(Sphere::default().translation(0.0, 0.0, 10.0)),
+        ];
+        let camera = Camera::new(10, 10, 1.0, Mat::identity(4));
+        let rt = RayTracer::new(World::new(camera, lights, spheres));
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = vec![Intersection::new(rt.world.shapes()[1].as_ref(), 4.0)];
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert_eq!(
+            rt.shade_hit(&c, rt.world.lights[0].as_ref()),
+            Vec3::from_float(0.1)
+        );
+    }
+
+    #[test]
+    fn test_shade_hit_no_acne() {
+        // the hit point is on the lit side, the sphere must not shadow it
+        let rt = RayTracer::new(World::default());
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = rt.world().intersect(&r, Vec::new());
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert_eq!(
+            rt.shade_hit(&c, rt.world.lights[0].as_ref()),
+            Vec3::new(0.38066, 0.47583, 0.2855)
+        );
+    }
+
+    #[test]
+    fn test_reflection_leaves_above_surface() {
+        let rt = world(vec![Box::new(Plane::default().reflective(1.0))]);
+        let r = Ray::new(Vec3::new(0.0, 1.0, -1.0), Vec3::new(0.0, -1.0, 1.0).norm());
+        let xs = rt.world().intersect(&r, Vec::new());
+        let mut c = Comp::prepare_comp(&r, &xs[0], &xs);
+        // a hit point rounded a hair under the floor and a bent normal
+        // sending the reflection out almost along it
+        c.hitp.y = -1e-6;
+        c.reflectv = Vec3::new(0.0, 0.001, 1.0).norm();
+
+        // nothing but sky up there, the floor must not see itself
+        assert_eq!(rt.reflected_color(&c, 0), Vec3::zero());
+    }
+
+    #[test]
+    fn test_checkered_floor() {
+        let rt = world(vec![Box::new(
+            Plane::default()
+                .pattern(Checker::new(Vec3::from_float(1.0), Vec3::zero()))
+                .specular(0.0),
+        )]);
+        let down = |x: Float, z: Float| {
+            let r = Ray::new(Vec3::new(x, 1.0, z), Vec3::new(0.0, -1.0, 0.0));
+            rt.trace(&r, 0)
+        };
+
+        // the hit sits right on y = 0, the cells must not flicker there
+        assert!(down(0.5, 0.5).x > 0.5);
+        assert_eq!(down(1.5, 0.5), Vec3::zero());
+        assert_eq!(down(-0.5, 0.5), Vec3::zero());
+        assert!(down(-0.5, -0.5).x > 0.5);
+    }
+
+    // floor covered in stripes 0.02 units wide, lit by ambient light only so
+    // the shade is the texture itself, seen from a meter up
+    fn striped_floor(mipmap: bool) -> RayTracer {
+        let pixels = (0..64)
+            .map(|i| Vec3::from_float((i % 2) as Float))
+            .collect();
+        let t = Texture::new(8, 8, pixels).filter(Filter::Nearest);
+        let mips = if mipmap {
+            MipMap::new(t)
+        } else {
+            MipMap::single(t)
+        };
+        let stripes = ImageTexture::new(Arc::new(mips), UvMap::Planar).scaling(0.16, 1.0, 0.16);
+        let mut rt = world(vec![Box::new(
+            Plane::default()
+                .pattern(stripes)
+                .ambient(1.0)
+                .diffuse(0.0)
+                .specular(0.0),
+        )]);
+        rt.world.camera = Camera::new(
+            100,
+            100,
+            std::f32::consts::FRAC_PI_3,
+            Mat::view_transformation(
+                &Vec3::new(0.0, 1.0, 0.0),
+                &Vec3::new(0.0, 0.0, 10.0),
+                &Vec3::new(0.0, 1.0, 0.0),
+            ),
+        );
+        rt
+    }
+
+    #[test]
+    fn test_distant_texture_filtered() {
+        // a pixel around z = 10 spans several stripes
+        let sharp = striped_floor(false).color_at(50, 50).x;
+        let filtered = striped_floor(true).color_at(50, 50).x;
+
+        assert!(sharp == 0.0 || sharp == 1.0);
+        assert!((filtered - 0.5).abs() < 0.1, "{}", filtered);
+        // close to the camera one stripe still fills the pixel
+        let near = striped_floor(true).color_at(50, 99).x;
+        assert!(!(0.1..=0.9).contains(&near), "{}", near);
+    }
+
+    #[test]
+    fn test_reflection_keeps_differentials() {
+        // the striped floor seen in a mirror facing the camera
+        let mut rt = striped_floor(true);
+        rt.world.add_shape(Box::new(
+            Plane::default()
+                .reflective(1.0)
+                .ambient(0.0)
+                .diffuse(0.0)
+                .specular(0.0)
+                .rotation_x(std::f32::consts::FRAC_PI_2)
+                .translation(0.0, 0.0, 5.0),
+        ));
+        let c = rt.color_at(50, 45).x;
+        assert!((c - 0.5).abs() < 0.1, "{}", c);
+    }
+
+    // floor with a small ball floating above the origin
+    fn floor_scene() -> RayTracer {
+        world(vec![
+            Box::new(Plane::default()),
+            Box::new(
+                Sphere::default()
+                    .translation(0.0, 1.0, 0.0)
+                    .scaling(0.5, 0.5, 0.5),
+            ),
+        ])
+    }
+
+    // shading of the floor at `(x, 0, 0)`, seen from above
+    fn shade_floor(rt: &RayTracer, x: Float, light: &dyn Light) -> Vec3 {
+        let r = Ray::new(Vec3::new(x, 1.0, -5.0), Vec3::new(0.0, -1.0, 5.0).norm());
+        let xs = rt.world().intersect(&r, Vec::new());
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+        rt.shade_hit(&c, light)
+    }
+
+    // the same without the ball, nothing casts a shadow
+    fn lit_floor(x: Float, light: &dyn Light) -> Vec3 {
+        shade_floor(&world(vec![Box::new(Plane::default())]), x, light)
+    }
+
+    #[test]
+    fn test_varying_reflective() {
+        // the noise pins the mirror's reflectance to zero, it shades like
+        // the plain floor and shows nothing of the ball it faces
+        let scene = |floor: Plane| {
+            let ball = Sphere::default().translation(0.3, 1.0, 5.0);
+            world(vec![Box::new(floor), Box::new(ball)])
+        };
+        let r = Ray::new(Vec3::new(0.3, 1.0, -5.0), Vec3::new(0.0, -1.0, 5.0).norm());
+        let plain = scene(Plane::default()).trace(&r, 0);
+        let mirror = Plane::default().reflective(1.0);
+        assert_ne!(scene(mirror).trace(&r, 0), plain);
+
+        let flat = Varying::new(Param::Reflective, Arc::new(Perlin::new(1)), 0.0, 0.0);
+        let mirror = Plane::default().reflective(1.0).vary(flat);
+        assert_eq!(scene(mirror).trace(&r, 0), plain);
+    }
+
+    #[test]
+    fn test_area_light_penumbra() {
+        let rt = floor_scene();
+        let light = AreaLight::rect(
+            Vec3::new(0.0, 3.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            4,
+            Vec3::new(0.0, 0.0, 2.0),
+            4,
+            Vec3::from_float(1.0),
+        )
+        .jitter(false);
+        let dark = shade_floor(&rt, 0.0, &light);
+        let soft = shade_floor(&rt, 1.2, &light);
+
+        // ambient only in the umbra, untouched far from the ball
+        assert_eq!(dark, Vec3::from_float(0.1));
+        assert_eq!(shade_floor(&rt, 6.0, &light), lit_floor(6.0, &light));
+        assert!(soft.x > dark.x && soft.x < lit_floor(1.2, &light).x);
+
+        // a point light in the middle of the area light does not see the
+        // sphere from there and lights the point fully
+        let point = PointLight::new(Vec3::new(0.0, 3.0, 0.0), Vec3::from_float(1.0));
+        let full = shade_floor(&rt, 1.2, &point);
+        assert_eq!(full, lit_floor(1.2, &point));
+        assert!(soft.x < full.x);
+    }
+
+    #[test]
+    fn test_light_types_shadows() {
+        let rt = floor_scene();
+        let down = Vec3::new(0.0, -1.0, 0.0);
+
+        // the sun is beyond any distance, the ball still shades the floor
+        let sun = DirectionalLight::new(down.clone(), Vec3::from_float(1.0));
+        assert_eq!(shade_floor(&rt, 0.0, &sun), Vec3::from_float(0.1));
+        assert_eq!(shade_floor(&rt, 2.0, &sun), lit_floor(2.0, &sun));
+
+        // a light between the ball and the floor is not blocked by it
+        let low = PointLight::new(Vec3::new(0.0, 0.3, 0.0), Vec3::from_float(1.0));
+        assert_eq!(shade_floor(&rt, 0.0, &low), lit_floor(0.0, &low));
+
+        let quarter = std::f32::consts::FRAC_PI_4;
+        let spot = SpotLight::new(
+            Vec3::new(3.0, 2.0, 0.0),
+            down,
+            quarter / 2.0,
+            quarter,
+            Vec3::from_float(1.0),
+        );
+        let inside = shade_floor(&rt, 3.0, &spot);
+        let outside = shade_floor(&rt, 6.0, &spot);
+        assert_eq!(inside, lit_floor(3.0, &spot));
+        assert_eq!(outside, Vec3::from_float(0.1));
+        assert!(inside.x > 0.9);
+    }
 }
@@ -1,7 +1,9 @@
-use std::{fs::File, io::Write, path::Path, usize};
+use std::{fs, fs::File, io::Write, path::Path};
 
 use crate::utils::vec3::Vec3;
 
+use super::png;
+
 #[derive(Debug, Clone)]
 pub struct Canvas {
     pub width: u32,
@@ -52,8 +54,49 @@ impl Canvas {
         Ok(())
     }
 
+    pub fn export_png(&self, filename: &str) -> std::io::Result<()> {
+        fs::write(
+            filename,
+            png::encode_rgb8(self.width, self.height, &self.pixels),
+        )
+    }
+
+    /// Same picture with 16 bit samples, the canvas only holds 8 bits so
+    /// they are scaled up rather than refined. `FrameBuffer::export_png16`
+    /// fills the extra bits from the radiance.
+    pub fn export_png16(&self, filename: &str) -> std::io::Result<()> {
+        let wide: Vec<u16> = self.pixels.iter().map(|&c| c as u16 * 257).collect();
+        fs::write(filename, png::encode_rgb16(self.width, self.height, &wide))
+    }
+
     pub fn as_rgba8(&self) -> &[u8] {
         &self.pixels
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_png() {
+        let mut canvas = Canvas::new(6, 3);
+        canvas.for_each(|p, x, y| Vec3::new(x as f32 / 5.0, y as f32 / 2.0, 0.5).apply(p));
+        let dir = std::env::temp_dir();
+        let path8 = dir.join(format!("rt_canvas8_{}.png", std::process::id()));
+        let path16 = dir.join(format!("rt_canvas16_{}.png", std::process::id()));
+        canvas.export_png(path8.to_str().unwrap()).unwrap();
+        canvas.export_png16(path16.to_str().unwrap()).unwrap();
+
+        let img8 = png::decode(&fs::read(&path8).unwrap()).unwrap();
+        let img16 = png::decode(&fs::read(&path16).unwrap()).unwrap();
+        fs::remove_file(path8).ok();
+        fs::remove_file(path16).ok();
+
+        let expected: Vec<u16> = canvas.as_rgba8().iter().map(|&c| c as u16).collect();
+        assert_eq!((img8.width, img8.height, img8.depth), (6, 3, 8));
+        assert_eq!(img8.samples, expected);
+        assert_eq!(img16.depth, 16);
+        assert_eq!(img16.samples[3], canvas.as_rgba8()[3] as u16 * 257);
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt::Debug;
+
+use crate::utils::vec3::Float;
+
+/// Reconstruction filter used when splatting samples into a `FrameBuffer`.
+/// Distances are in pixels from the pixel center, a sample reaches every
+/// pixel whose center is within `radius` on both axes.
+pub trait Filter: Debug + Send + Sync {
+    fn radius(&self) -> Float;
+
+    /// Weight of a sample `(dx, dy)` away from the pixel center, may be
+    /// negative for filters with lobes.
+    fn eval(&self, dx: Float, dy: Float) -> Float;
+}
+
+/// Flat weight, with radius 0.5 every sample lands in its own pixel only
+/// and the result is the plain average.
+#[derive(Debug, Clone, Copy)]
+pub struct BoxFilter {
+    pub radius: Float,
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        Self { radius: 0.5 }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> Float {
+        self.radius
+    }
+
+    fn eval(&self, dx: Float, dy: Float) -> Float {
+        (dx.abs() <= self.radius && dy.abs() <= self.radius) as u8 as Float
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tent {
+    pub radius: Float,
+}
+
+impl Default for Tent {
+    fn default() -> Self {
+        Self { radius: 1.0 }
+    }
+}
+
+impl Filter for Tent {
+    fn radius(&self) -> Float {
+        self.radius
+    }
+
+    fn eval(&self, dx: Float, dy: Float) -> Float {
+        let tent = |d: Float| (self.radius - d.abs()).max(0.0);
+        tent(dx) * tent(dy)
+    }
+}
+
+/// Gaussian shifted down so it reaches zero at the radius.
+#[derive(Debug, Clone, Copy)]
+pub struct Gaussian {
+    pub radius: Float,
+    pub alpha: Float,
+}
+
+impl Default for Gaussian {
+    fn default() -> Self {
+        Self {
+            radius: 1.5,
+            alpha: 2.0,
+        }
+    }
+}
+
+impl Filter for Gaussian {
+    fn radius(&self) -> Float {
+        self.radius
+    }
+
+    fn eval(&self, dx: Float, dy: Float) -> Float {
+        let edge = (-self.alpha * self.radius * self.radius).exp();
+        let g = |d: Float| ((-self.alpha * d * d).exp() - edge).max(0.0);
+        g(dx) * g(dy)
+    }
+}
+
+/// Mitchell–Netravali cubic, `b = c = 1/3` is the pair the paper recommends.
+#[derive(Debug, Clone, Copy)]
+pub struct Mitchell {
+    pub radius: Float,
+    pub b: Float,
+    pub c: Float,
+}
+
+impl Default for Mitchell {
+    fn default() -> Self {
+        Self {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+}
+
+impl Mitchell {
+    // the cubic is defined over [-2, 2]
+    fn cubic(&self, x: Float) -> Float {
+        let (b, c) = (self.b, self.c);
+        let x = x.abs();
+        let v = if x < 1.0 {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b)
+        } else if x < 2.0 {
+            (-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c)
+        } else {
+            0.0
+        };
+        v / 6.0
+    }
+}
+
+impl Filter for Mitchell {
+    fn radius(&self) -> Float {
+        self.radius
+    }
+
+    fn eval(&self, dx: Float, dy: Float) -> Float {
+        let scale = 2.0 / self.radius;
+        self.cubic(dx * scale) * self.cubic(dy * scale)
+    }
+}
+
+/// Sinc windowed by a wider sinc, `radius` is the number of lobes.
+#[derive(Debug, Clone, Copy)]
+pub struct Lanczos {
+    pub radius: Float,
+}
+
+impl Default for Lanczos {
+    fn default() -> Self {
+        Self { radius: 2.0 }
+    }
+}
+
+fn sinc(x: Float) -> Float {
+    let x = x * std::f32::consts::PI;
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        x.sin() / x
+    }
+}
+
+impl Filter for Lanczos {
+    fn radius(&self) -> Float {
+        self.radius
+    }
+
+    fn eval(&self, dx: Float, dy: Float) -> Float {
+        let l = |d: Float| {
+            if d.abs() >= self.radius {
+                0.0
+            } else {
+                sinc(d) * sinc(d / self.radius)
+            }
+        };
+        l(dx) * l(dy)
+    }
+}
+
+/// Filter from a `name[:radius]` spec such as `gaussian` or `mitchell:1.5`.
+pub fn parse(spec: &str) -> Result<Box<dyn Filter>, String> {
+    let (name, radius) = match spec.split_once(':') {
+        Some((n, r)) => match r.parse::<Float>() {
+            Ok(v) if v > 0.0 => (n, Some(v)),
+            _ => return Err(format!("invalid filter radius `{}`", r)),
+        },
+        None => (spec, None),
+    };
+    let filter: Box<dyn Filter> = match name {
+        "box" => Box::new(BoxFilter {
+            radius: radius.unwrap_or(0.5),
+        }),
+        "tent" => Box::new(Tent {
+            radius: radius.unwrap_or(1.0),
+        }),
+        "gaussian" => Box::new(Gaussian {
+            radius: radius.unwrap_or(1.5),
+            ..Default::default()
+        }),
+        "mitchell" => Box::new(Mitchell {
+            radius: radius.unwrap_or(2.0),
+            ..Default::default()
+        }),
+        "lanczos" => Box::new(Lanczos {
+            radius: radius.unwrap_or(2.0),
+        }),
+        _ => return Err(format!("unknown filter `{}`", name)),
+    };
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> Vec<Box<dyn Filter>> {
+        ["box", "tent", "gaussian", "mitchell", "lanczos"]
+            .iter()
+            .map(|s| parse(s).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_zero_outside_radius() {
+        for f in all() {
+            let r = f.radius();
+            assert!(f.eval(0.0, 0.0) > 0.0, "{:?}", f);
+            assert_eq!(f.eval(r + 0.01, 0.0), 0.0, "{:?}", f);
+            assert_eq!(f.eval(0.0, -r - 0.01), 0.0, "{:?}", f);
+            assert_eq!(f.eval(0.3, -0.2), f.eval(-0.3, 0.2), "{:?}", f);
+        }
+    }
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(Tent::default().eval(0.5, 0.0), 0.5);
+        assert_eq!(Gaussian::default().eval(1.5, 0.0), 0.0);
+        // the Mitchell cubic sums to one over integer offsets
+        let m = Mitchell::default();
+        let sum: Float = (-2..=2).map(|i| m.cubic(i as Float + 0.3)).sum();
+        assert!((sum - 1.0).abs() < 1e-5);
+        assert!(m.eval(1.5, 0.0) < 0.0);
+        assert!(Lanczos::default().eval(1.5, 0.0) < 0.0);
+        assert!(Lanczos::default().eval(1.0, 0.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("tent:2").unwrap().radius(), 2.0);
+        assert_eq!(parse("sinc").unwrap_err(), "unknown filter `sinc`");
+        assert!(parse("box:-1").is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use std::{fs, io};
+
+use crate::utils::vec3::{Float, Vec3};
+
+use super::{canvas::Canvas, filter::Filter, png, tonemap::ToneMap};
+
+/// Sample type of the channels in an OpenEXR file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExrPixel {
+    Half,
+    Float,
+}
+
+/// Linear radiance per pixel, kept as a weighted sum of samples so values
+/// above 1.0 survive until they are tone mapped or written out.
+#[derive(Debug, Clone)]
+pub struct FrameBuffer {
+    pub width: u32,
+    pub height: u32,
+    sums: Vec<Vec3>,
+    weights: Vec<Float>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let n = (width * height) as usize;
+        Self {
+            width,
+            height,
+            sums: vec![Vec3::zero(); n],
+            weights: vec![0.0; n],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    /// Adds a sample with weight 1.
+    pub fn add(&mut self, x: u32, y: u32, color: &Vec3) {
+        self.add_weighted(x, y, color, 1.0);
+    }
+
+    pub fn add_weighted(&mut self, x: u32, y: u32, color: &Vec3, weight: Float) {
+        let i = self.index(x, y);
+        self.sums[i] = &self.sums[i] + &(color * weight);
+        self.weights[i] += weight;
+    }
+
+    /// Adds a sample taken at `(x, y)` in pixel units to every pixel the
+    /// filter reaches, pixel centers sit at `+0.5`.
+    pub fn splat(&mut self, x: Float, y: Float, color: &Vec3, filter: &dyn Filter) {
+        let r = filter.radius();
+        let x0 = (x - 0.5 - r).ceil().max(0.0) as i64;
+        let y0 = (y - 0.5 - r).ceil().max(0.0) as i64;
+        let x1 = ((x - 0.5 + r).floor() as i64).min(self.width as i64 - 1);
+        let y1 = ((y - 0.5 + r).floor() as i64).min(self.height as i64 - 1);
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let w = filter.eval(px as Float + 0.5 - x, py as Float + 0.5 - y);
+                if w != 0.0 {
+                    self.add_weighted(px as u32, py as u32, color, w);
+                }
+            }
+        }
+    }
+
+    /// Adds the accumulated samples of `other`, placed with its top left
+    /// corner at `(x, y)`. Pixels falling outside are dropped.
+    pub fn merge(&mut self, other: &FrameBuffer, x: i64, y: i64) {
+        for oy in 0..other.height {
+            for ox in 0..other.width {
+                let (tx, ty) = (x + ox as i64, y + oy as i64);
+                if tx < 0 || ty < 0 || tx >= self.width as i64 || ty >= self.height as i64 {
+                    continue;
+                }
+                let (i, j) = (self.index(tx as u32, ty as u32), other.index(ox, oy));
+                self.sums[i] = &self.sums[i] + &other.sums[j];
+                self.weights[i] += other.weights[j];
+            }
+        }
+    }
+
+    /// Replaces whatever was accumulated for the pixel.
+    pub fn set(&mut self, x: u32, y: u32, color: &Vec3) {
+        let i = self.index(x, y);
+        self.sums[i].set(color);
+        self.weights[i] = 1.0;
+    }
+
+    /// Weighted average of the samples, black if there are none.
+    pub fn pixel(&self, x: u32, y: u32) -> Vec3 {
+        let i = self.index(x, y);
+        if self.weights[i] == 0.0 {
+            return Vec3::zero();
+        }
+        &self.sums[i] / self.weights[i]
+    }
+
+    pub fn clear(&mut self) {
+        self.sums
+            .iter_mut()
+            .for_each(|s| s.set_scalar(0.0, 0.0, 0.0));
+        self.weights.iter_mut().for_each(|w| *w = 0.0);
+    }
+
+    pub fn to_canvas(&self, op: &dyn ToneMap) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height);
+        self.tone_map_into(op, &mut canvas);
+        canvas
+    }
+
+    /// Writes the tone mapped picture into `canvas`, which must have the same
+    /// size.
+    pub fn tone_map_into(&self, op: &dyn ToneMap, canvas: &mut Canvas) {
+        assert_eq!((canvas.width, canvas.height), (self.width, self.height));
+        for y in 0..self.height {
+            for x in 0..self.width {
+                canvas.write_at(x, y, &op.map(&self.pixel(x, y)));
+            }
+        }
+    }
+
+    /// PNG with 16 bit samples, tone mapped by `op` straight from the
+    /// radiance so the extra bits keep the steps an 8 bit canvas rounds away.
+    pub fn to_png16(&self, op: &dyn ToneMap) -> Vec<u8> {
+        let mut samples = Vec::with_capacity((3 * self.width * self.height) as usize);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let c = op.map(&self.pixel(x, y));
+                for v in [c.x, c.y, c.z] {
+                    samples.push((v.clamp(0.0, 1.0) * 65535.0).round() as u16);
+                }
+            }
+        }
+        png::encode_rgb16(self.width, self.height, &samples)
+    }
+
+    pub fn export_png16(&self, filename: &str, op: &dyn ToneMap) -> io::Result<()> {
+        fs::write(filename, self.to_png16(op))
+    }
+
+    /// Portable float map, little endian with rows from the bottom up.
+    pub fn to_pfm(&self) -> Vec<u8> {
+        let mut out = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let c = self.pixel(x, y);
+                for v in [c.x, c.y, c.z] {
+                    out.extend(v.to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    pub fn export_pfm(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.to_pfm())
+    }
+
+    /// Single part scanline OpenEXR without compression.
+    pub fn to_exr(&self, pixel: ExrPixel) -> Vec<u8> {
+        let (kind, size) = match pixel {
+            ExrPixel::Half => (1i32, 2),
+            ExrPixel::Float => (2i32, 4),
+        };
+        let mut out = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+        let mut channels = Vec::new();
+        // channels have to be sorted by name
+        for name in ["B", "G", "R"] {
+            channels.extend(name.as_bytes());
+            channels.push(0);
+            channels.extend(kind.to_le_bytes());
+            // pLinear and reserved bytes, then x and y sampling
+            channels.extend([0, 0, 0, 0]);
+            channels.extend(1i32.to_le_bytes());
+            channels.extend(1i32.to_le_bytes());
+        }
+        channels.push(0);
+        let mut window = Vec::new();
+        for v in [0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend(v.to_le_bytes());
+        }
+        attribute(&mut out, "channels", "chlist", &channels);
+        attribute(&mut out, "compression", "compression", &[0]);
+        attribute(&mut out, "dataWindow", "box2i", &window);
+        attribute(&mut out, "displayWindow", "box2i", &window);
+        attribute(&mut out, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut out, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(&mut out, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        out.push(0);
+
+        // offset table, one scanline per block
+        let line = 3 * size * self.width as usize;
+        let first = out.len() + 8 * self.height as usize;
+        for y in 0..self.height as usize {
+            out.extend(((first + y * (8 + line)) as u64).to_le_bytes());
+        }
+        for y in 0..self.height {
+            out.extend((y as i32).to_le_bytes());
+            out.extend((line as i32).to_le_bytes());
+            let row: Vec<Vec3> = (0..self.width).map(|x| self.pixel(x, y)).collect();
+            for channel in [|c: &Vec3| c.z, |c: &Vec3| c.y, |c: &Vec3| c.x] {
+                for c in row.iter() {
+                    let v = channel(c);
+                    match pixel {
+                        ExrPixel::Half => out.extend(f32_to_f16(v).to_le_bytes()),
+                        ExrPixel::Float => out.extend(v.to_le_bytes()),
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    pub fn export_exr(&self, filename: &str, pixel: ExrPixel) -> io::Result<()> {
+        fs::write(filename, self.to_exr(pixel))
+    }
+}
+
+fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend(name.as_bytes());
+    out.push(0);
+    out.extend(kind.as_bytes());
+    out.push(0);
+    out.extend((value.len() as i32).to_le_bytes());
+    out.extend(value);
+}
+
+/// IEEE 754 half precision, rounding to nearest even. Values too large for
+/// a half become infinity.
+pub fn f32_to_f16(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        if e < -10 {
+            return sign;
+        }
+        // subnormal, shift the implicit bit in
+        let m = mantissa | 0x80_0000;
+        let shift = (14 - e) as u32;
+        let half = m >> shift;
+        let rest = m & ((1 << shift) - 1);
+        let midpoint = 1 << (shift - 1);
+        let round = (rest > midpoint || (rest == midpoint && half & 1 == 1)) as u32;
+        return sign | (half + round) as u16;
+    }
+    let half = ((e as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let round = (rest > 0x1000 || (rest == 0x1000 && half & 1 == 1)) as u32;
+    // a carry out of the mantissa correctly bumps the exponent
+    sign | (half + round) as u16
+}
+
+pub fn f16_to_f32(h: u16) -> f32 {
+    let sign = ((h as u32) & 0x8000) << 16;
+    let exp = ((h >> 10) & 0x1f) as u32;
+    let mantissa = (h & 0x3ff) as u32;
+    let bits = match (exp, mantissa) {
+        (0, 0) => sign,
+        (0, m) => {
+            // normalize the subnormal
+            let shift = m.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | ((m << shift) & 0x3ff) << 13
+        }
+        (0x1f, m) => sign | 0x7f80_0000 | (m << 13),
+        (e, m) => sign | ((e + 112) << 23) | (m << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{
+        filter::{BoxFilter, Tent},
+        tonemap::Clamp,
+    };
+
+    fn gradient() -> FrameBuffer {
+        let mut fb = FrameBuffer::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                fb.set(x, y, &Vec3::new(x as Float * 2.0, y as Float, 0.25));
+            }
+        }
+        fb
+    }
+
+    #[test]
+    fn test_accumulates_samples() {
+        let mut fb = FrameBuffer::new(2, 2);
+        fb.add(1, 0, &Vec3::new(4.0, 0.0, 1.0));
+        fb.add_weighted(1, 0, &Vec3::new(1.0, 3.0, 1.0), 3.0);
+
+        assert_eq!(fb.pixel(1, 0), Vec3::new(1.75, 2.25, 1.0));
+        assert_eq!(fb.pixel(0, 0), Vec3::zero());
+        fb.clear();
+        assert_eq!(fb.pixel(1, 0), Vec3::zero());
+    }
+
+    #[test]
+    fn test_splat_and_merge() {
+        let mut fb = FrameBuffer::new(4, 4);
+        fb.splat(1.5, 1.5, &Vec3::from_float(2.0), &BoxFilter::default());
+        assert_eq!(fb.pixel(1, 1), Vec3::from_float(2.0));
+        assert_eq!(fb.pixel(2, 1), Vec3::zero());
+
+        // a wide sample near the border spreads over the pixels it covers
+        let mut tile = FrameBuffer::new(3, 3);
+        tile.splat(1.0, 1.0, &Vec3::from_float(1.0), &Tent { radius: 1.0 });
+        assert_eq!(tile.weights[tile.index(0, 1)], 0.25);
+        assert_eq!(tile.weights[tile.index(1, 1)], 0.25);
+        assert_eq!(tile.weights[tile.index(2, 1)], 0.0);
+
+        fb.merge(&tile, 2, 0);
+        fb.merge(&tile, -1, 3);
+        assert_eq!(fb.pixel(2, 1), Vec3::from_float(1.0));
+        assert_eq!(fb.weights[fb.index(3, 1)], 0.25);
+        assert_eq!(fb.weights[fb.index(0, 3)], 0.25);
+        assert_eq!(fb.weights[fb.index(0, 0)], 0.0);
+    }
+
+    #[test]
+    fn test_clamp_matches_canvas() {
+        let fb = gradient();
+        let canvas = fb.to_canvas(&Clamp);
+        let mut expected = Canvas::new(3, 2);
+        expected.for_each(|p, x, y| fb.pixel(x, y).apply(p));
+
+        assert_eq!(canvas.as_rgba8(), expected.as_rgba8());
+    }
+
+    #[test]
+    fn test_png16() {
+        // a ramp finer than 8 bits can tell apart
+        let mut fb = FrameBuffer::new(4, 1);
+        for x in 0..4 {
+            fb.set(x, 0, &Vec3::from_float(0.51 + x as Float / 1024.0));
+        }
+        let img = png::decode(&fb.to_png16(&Clamp)).unwrap();
+        let canvas = fb.to_canvas(&Clamp);
+
+        assert_eq!((img.width, img.height, img.depth), (4, 1, 16));
+        for x in 0..4 {
+            let v = 0.51 + x as Float / 1024.0;
+            assert_eq!(img.samples[3 * x], (v * 65535.0).round() as u16);
+        }
+        // all four steps land on one 8 bit level
+        assert!(img.samples.windows(4).step_by(3).all(|w| w[0] < w[3]));
+        assert!(canvas.as_rgba8().iter().all(|&c| c == 130));
+    }
+
+    #[test]
+    fn test_pfm() {
+        let pfm = gradient().to_pfm();
+        let header = b"PF\n3 2\n-1.0\n";
+        let float = |i: usize| {
+            let at = header.len() + 4 * i;
+            f32::from_le_bytes(pfm[at..at + 4].try_into().unwrap())
+        };
+
+        assert!(pfm.starts_with(header));
+        assert_eq!(pfm.len(), header.len() + 3 * 2 * 3 * 4);
+        // the first row stored is the bottom one
+        assert_eq!(float(1), 1.0);
+        assert_eq!(float(2 * 3), 4.0);
+        assert_eq!(float(3 * 3 + 1), 0.0);
+    }
+
+    #[test]
+    fn test_exr() {
+        let fb = gradient();
+        for (pixel, size) in [(ExrPixel::Half, 2), (ExrPixel::Float, 4)] {
+            let exr = fb.to_exr(pixel);
+            let header_end = exr.len() - 2 * (8 + 8 + 3 * 3 * size);
+            let offset = |y: usize| {
+                let at = header_end + 8 * y;
+                u64::from_le_bytes(exr[at..at + 8].try_into().unwrap()) as usize
+            };
+            let sample = |y: usize, channel: usize, x: usize| {
+                let at = offset(y) + 8 + (channel * 3 + x) * size;
+                match pixel {
+                    ExrPixel::Half => f16_to_f32(u16::from_le_bytes([exr[at], exr[at + 1]])),
+                    ExrPixel::Float => f32::from_le_bytes(exr[at..at + 4].try_into().unwrap()),
+                }
+            };
+
+            assert_eq!(&exr[..4], &[0x76, 0x2f, 0x31, 0x01]);
+            assert_eq!(exr[header_end - 1], 0);
+            assert_eq!(offset(0), header_end + 16);
+            // channels are B, G, R
+            assert_eq!(sample(0, 2, 2), 4.0);
+            assert_eq!(sample(1, 1, 0), 1.0);
+            assert_eq!(sample(1, 0, 1), 0.25);
+        }
+    }
+
+    #[test]
+    fn test_half_conversion() {
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 0x0001);
+        assert_eq!(f32_to_f16(1e-9), 0);
+        // halfway between 1.0 and the next half rounds to even
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 2048.0), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 / 2048.0), 0x3c02);
+
+        for h in [0x0001u16, 0x03ff, 0x0400, 0x3555, 0x7bff, 0xbc00] {
+            assert_eq!(f32_to_f16(f16_to_f32(h)), h);
+        }
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+    }
+}
@@ -0,0 +1,1669 @@
+//! Text scene description. A scene is a list of blocks:
+//!
+//! ```text
+//! # comments run to the end of the line
+//! camera {
+//!     size 800 600
+//!     fov 1.047            # radians, between 0 and pi
+//!     from 0 1.5 -5
+//!     to 0 1 0
+//!     up 0 1 0
+//!     aperture 0.1         # lens radius, 0 is a pinhole
+//!     focal_distance 5
+//!     blades 6 0.2         # polygonal aperture and its rotation
+//!     projection perspective   # or orthographic <width>, fisheye <fov>,
+//!                              # equirectangular
+//! }
+//!
+//! material glass {
+//!     color 1 1 1
+//!     transparency 0.9
+//!     refractive_index 1.5
+//! }
+//!
+//! light {
+//!     position -10 10 -10
+//!     intensity 1 1 1
+//!     rect 2 0 0 0 0 2 4 4 # area light, edges u and v then samples along
+//!                          # each, or `sphere <radius> <samples>`
+//!     jitter off           # sample cell centers, on by default
+//!     inverse_square on    # falloff with the distance, off by default
+//! }
+//!
+//! spotlight {
+//!     position 0 5 0
+//!     direction 0 -1 0
+//!     cone 0.3 0.5         # inner and outer half angles in radians
+//!     intensity 1 1 1      # inverse_square works here too
+//! }
+//!
+//! sun {
+//!     direction 1 -1 0     # where the light goes
+//!     intensity 1 1 1
+//! }
+//!
+//! sphere {
+//!     material glass       # start from a named material
+//!     diffuse 0.2          # then override single fields
+//!     translate 0 1 0      # transforms apply like the builder chain
+//!     scale 0.5 0.5 0.5
+//! }
+//!
+//! plane {
+//!     pattern checker {    # stripe, gradient, ring, checker, radial_gradient,
+//!                          # or blend to average two patterns
+//!         colors 1 1 1 0 0 0
+//!         scale 0.5 0.5 0.5    # pattern transforms come on top of the shape's
+//!     }
+//! }
+//!
+//! sphere {
+//!     pattern perturb {    # moves the lookup point along Perlin noise
+//!         scale_noise 0.2
+//!         seed 3
+//!         pattern ring {   # `pattern` and `color` fill one cell each
+//!             pattern stripe { colors 1 0 0 1 1 1 scale 0.1 0.1 0.1 }
+//!             color 0 0 0
+//!         }
+//!     }
+//! }
+//!
+//! sphere {
+//!     pattern noise {      # blends the two cells by a noise field
+//!         noise worley edge    # perlin, simplex or worley with f1, f2 or edge
+//!         fractal fbm 4    # optional fbm, turbulence or ridged octaves
+//!         seed 7
+//!         colors 0.1 0.1 0.1 0.9 0.8 0.7
+//!     }
+//! }
+//!
+//! sphere {
+//!     pattern image {      # PNG or PPM, relative to the scene file
+//!         file "earth.png"
+//!         map spherical    # planar, cylindrical or cube (a 4 by 3 cross)
+//!         filter bilinear  # or nearest
+//!         wrap repeat      # clamp or mirror
+//!         srgb on          # decode sRGB pixels to linear
+//!         mipmap on        # filter by the pixel footprint, on by default
+//!         anisotropy 8     # most samples along stretched footprints
+//!     }
+//! }
+//!
+//! plane {
+//!     bump {               # heights from the same keys as `pattern noise`,
+//!         strength 0.05    # or from the brightness of a nested `pattern`
+//!         noise simplex
+//!         fractal fbm 3
+//!         scale 0.5 0.5 0.5
+//!     }
+//! }
+//!
+//! sphere {
+//!     vary reflective {    # ambient, diffuse, specular, shininess,
+//!         range 0.1 0.6    # reflective or transparency follows the noise
+//!         noise perlin     # of the same keys as `pattern noise`, remapped
+//!         scale 2 2 2      # from its range onto `range`
+//!     }
+//! }
+//!
+//! sphere {
+//!     normal_map {         # tangent space normals, same keys as
+//!         file "rock.png"  # `pattern image`
+//!         map spherical
+//!         strength 1
+//!     }
+//! }
+//! ```
+//!
+//! Shapes are `sphere`, `plane`, `cube`, `cylinder` and `cone` (`minimum`
+//! and `maximum` heights, unbounded by default, and `closed on` for end
+//! caps), `triangle` (`points` followed by 9 numbers) and `mesh`
+//! (`file "model.obj"`, relative to the scene file, which must have faces
+//! and no malformed `v`, `vn`, `vt` or `f` lines).
+
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    scene::texture::{Filter, MipMap, Texture, Wrap},
+    utils::{
+        material::{Material, Param, Varying},
+        matrix::Mat,
+        noise::{Cellular, Fractal, FractalKind, Noise, Perlin, Simplex, Worley},
+        vec3::{Float, Vec3},
+    },
+    world::{
+        bump::{Bump, BumpMap, Height, NormalMap},
+        camera::{Aperture, Camera, Projection},
+        light::{AreaLight, DirectionalLight, Light, LightShape, PointLight, SpotLight},
+        pattern::{
+            Blend, Checker, Gradient, ImageTexture, NoisePattern, Pattern, Perturb, RadialGradient,
+            Ring, Solid, Stripe,
+        },
+        shapes::{
+            cone::Cone, cube::Cube, cylinder::Cylinder, plane::Plane, shape::Shape, sphere::Sphere,
+            triangle::Triangle,
+        },
+        transform::Transformable,
+        uv::UvMap,
+        w::World,
+    },
+};
+
+use super::obj::ObjFile;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{}", e),
+            SceneError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+impl From<ParseError> for SceneError {
+    fn from(e: ParseError) -> Self {
+        SceneError::Parse(e)
+    }
+}
+
+/// Reads and parses a scene file, meshes are looked up next to it.
+pub fn load(path: impl AsRef<Path>) -> Result<World, SceneError> {
+    let path = path.as_ref();
+    let src = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    parse_in(&src, dir)
+}
+
+/// Parses a scene that does not reference any file.
+pub fn parse(src: &str) -> Result<World, SceneError> {
+    parse_in(src, Path::new("."))
+}
+
+pub fn parse_in(src: &str, dir: &Path) -> Result<World, SceneError> {
+    let tokens = lex(src)?;
+    let eof = src
+        .lines()
+        .enumerate()
+        .last()
+        .map_or((1, 1), |(i, l)| (i + 1, l.chars().count() + 1));
+    Parser {
+        tokens,
+        pos: 0,
+        eof,
+        dir: dir.to_path_buf(),
+        materials: HashMap::new(),
+        camera: None,
+        lights: Vec::new(),
+        shapes: Vec::new(),
+    }
+    .scene()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Num(Float),
+    Str(String),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    line: usize,
+    col: usize,
+}
+
+fn lex(src: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    for (l, text) in src.lines().enumerate() {
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let (line, col) = (l + 1, i + 1);
+            let err = |message: String| ParseError { line, col, message };
+            if c == '#' {
+                break;
+            }
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            let tok = match c {
+                '{' => {
+                    i += 1;
+                    Tok::Open
+                }
+                '}' => {
+                    i += 1;
+                    Tok::Close
+                }
+                '"' => {
+                    i += 1;
+                    while i < chars.len() && chars[i] != '"' {
+                        i += 1;
+                    }
+                    if i == chars.len() {
+                        return Err(err("unterminated string".to_string()));
+                    }
+                    i += 1;
+                    Tok::Str(chars[start + 1..i - 1].iter().collect())
+                }
+                _ => {
+                    while i < chars.len()
+                        && !chars[i].is_whitespace()
+                        && !matches!(chars[i], '{' | '}' | '"' | '#')
+                    {
+                        i += 1;
+                    }
+                    let word: String = chars[start..i].iter().collect();
+                    if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') {
+                        let n = word
+                            .parse()
+                            .map_err(|_| err(format!("invalid number `{}`", word)))?;
+                        Tok::Num(n)
+                    } else if word.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        Tok::Word(word)
+                    } else {
+                        return Err(err(format!("unexpected `{}`", word)));
+                    }
+                }
+            };
+            tokens.push(Token { tok, line, col });
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    eof: (usize, usize),
+    dir: PathBuf,
+    materials: HashMap<String, Material>,
+    camera: Option<Camera>,
+    lights: Vec<Box<dyn Light>>,
+    shapes: Vec<Box<dyn Shape>>,
+}
+
+/// Everything a pattern, bump or normal map block may set, they are built
+/// once the block ends.
+struct PatternDef {
+    // what goes in the cells, either plain colors or nested patterns
+    parts: Vec<Arc<dyn Pattern>>,
+    t: Mat,
+    scale: Float,
+    seed: u64,
+    noise: String,
+    cellular: Cellular,
+    fractal: Option<(FractalKind, u32)>,
+    file: Option<String>,
+    map: UvMap,
+    filter: Filter,
+    wrap: Wrap,
+    srgb: bool,
+    mipmap: bool,
+    anisotropy: Option<Float>,
+    strength: Float,
+    range: (Float, Float),
+}
+
+impl Default for PatternDef {
+    fn default() -> Self {
+        Self {
+            parts: Vec::new(),
+            t: Mat::identity(4),
+            scale: 0.1,
+            seed: 0,
+            noise: String::from("perlin"),
+            cellular: Cellular::F1,
+            fractal: None,
+            file: None,
+            map: UvMap::default(),
+            filter: Filter::default(),
+            wrap: Wrap::default(),
+            srgb: false,
+            mipmap: true,
+            anisotropy: None,
+            strength: 1.0,
+            range: (0.0, 1.0),
+        }
+    }
+}
+
+impl PatternDef {
+    fn noise_field(&self) -> Arc<dyn Noise> {
+        let mut field: Arc<dyn Noise> = match self.noise.as_str() {
+            "simplex" => Arc::new(Simplex::new(self.seed)),
+            "worley" => Arc::new(Worley {
+                seed: self.seed,
+                feature: self.cellular,
+            }),
+            _ => Arc::new(Perlin::new(self.seed)),
+        };
+        if let Some((kind, octaves)) = self.fractal {
+            field = Arc::new(Fractal::new(field, kind, octaves));
+        }
+        field
+    }
+}
+
+const PATTERNS: [&str; 9] = [
+    "stripe",
+    "gradient",
+    "ring",
+    "checker",
+    "radial_gradient",
+    "blend",
+    "perturb",
+    "noise",
+    "image",
+];
+
+// whether `key` means anything in a `pattern_def` block of `kind`, besides
+// the transforms every one of them takes
+fn pattern_key(kind: &str, key: &str) -> bool {
+    let image = matches!(kind, "image" | "normal_map");
+    match key {
+        "noise" | "fractal" => matches!(kind, "noise" | "bump" | "vary"),
+        "seed" => matches!(kind, "noise" | "bump" | "perturb" | "vary"),
+        "range" => kind == "vary",
+        "scale_noise" => kind == "perturb",
+        "file" | "map" | "filter" | "wrap" | "srgb" | "mipmap" | "anisotropy" => image,
+        "strength" => matches!(kind, "bump" | "normal_map"),
+        "pattern" => !image && kind != "vary",
+        "colors" | "color" => !image && !matches!(kind, "bump" | "vary"),
+        _ => false,
+    }
+}
+
+/// Everything a shape block may set, the shape is built once the block ends.
+struct ShapeDef {
+    m: Material,
+    t: Mat,
+    minimum: Float,
+    maximum: Float,
+    closed: bool,
+    points: Option<[Vec3; 3]>,
+    file: Option<String>,
+}
+
+impl Parser {
+    fn error_at(&self, pos: usize, message: String) -> ParseError {
+        let (line, col) = self.tokens.get(pos).map_or(self.eof, |t| (t.line, t.col));
+        ParseError { line, col, message }
+    }
+
+    fn next(&mut self) -> Result<Tok, ParseError> {
+        let t = self
+            .tokens
+            .get(self.pos)
+            .map(|t| t.tok.clone())
+            .ok_or_else(|| self.error_at(self.pos, "unexpected end of file".to_string()))?;
+        self.pos += 1;
+        Ok(t)
+    }
+
+    fn word(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Tok::Word(w) => Ok(w),
+            t => Err(self.error_at(self.pos - 1, format!("expected a name, found {}", t))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Tok::Str(s) => Ok(s),
+            t => Err(self.error_at(self.pos - 1, format!("expected a string, found {}", t))),
+        }
+    }
+
+    fn number(&mut self) -> Result<Float, ParseError> {
+        match self.next()? {
+            Tok::Num(n) => Ok(n),
+            t => Err(self.error_at(self.pos - 1, format!("expected a number, found {}", t))),
+        }
+    }
+
+    // sample counts and the like, at least 1
+    fn count(&mut self) -> Result<usize, ParseError> {
+        let n = self.number()?;
+        if n < 1.0 || n.fract() != 0.0 {
+            return Err(self.error_at(self.pos - 1, format!("expected a count, found {}", n)));
+        }
+        Ok(n as usize)
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ParseError> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    // non zero vector, normalized
+    fn direction(&mut self) -> Result<Vec3, ParseError> {
+        let at = self.pos;
+        let v = self.vec3()?;
+        if v.mag() == 0.0 {
+            return Err(self.error_at(at, "direction must not be zero".to_string()));
+        }
+        Ok(v.norm())
+    }
+
+    fn switch(&mut self) -> Result<bool, ParseError> {
+        let at = self.pos;
+        match self.word()?.as_str() {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            w => Err(self.error_at(at, format!("expected on or off, found `{}`", w))),
+        }
+    }
+
+    fn open(&mut self) -> Result<(), ParseError> {
+        match self.next()? {
+            Tok::Open => Ok(()),
+            t => Err(self.error_at(self.pos - 1, format!("expected `{{`, found {}", t))),
+        }
+    }
+
+    /// Calls `property` with every key of a `{ ... }` block and the position
+    /// of the key, for error reporting.
+    fn block(
+        &mut self,
+        mut property: impl FnMut(&mut Self, &str, usize) -> Result<(), ParseError>,
+    ) -> Result<(), ParseError> {
+        self.open()?;
+        loop {
+            let pos = self.pos;
+            match self.next()? {
+                Tok::Close => return Ok(()),
+                Tok::Word(key) => property(self, &key, pos)?,
+                t => return Err(self.error_at(pos, format!("expected a property, found {}", t))),
+            }
+        }
+    }
+
+    fn scene(mut self) -> Result<World, SceneError> {
+        while self.pos < self.tokens.len() {
+            let pos = self.pos;
+            let kind = self.word()?;
+            match kind.as_str() {
+                "camera" => self.camera(pos)?,
+                "material" => {
+                    let name = self.word()?;
+                    let mut m = Material::default();
+                    self.block(|p, key, pos| {
+                        if !p.material_property(&mut m, key)? {
+                            return Err(
+                                p.error_at(pos, format!("unknown material property `{}`", key))
+                            );
+                        }
+                        Ok(())
+                    })?;
+                    self.materials.insert(name, m);
+                }
+                "light" => self.light()?,
+                "spotlight" => self.spotlight()?,
+                "sun" => self.sun()?,
+                "sphere" | "plane" | "cube" | "cylinder" | "cone" | "triangle" | "mesh" => {
+                    self.shape(&kind, pos)?
+                }
+                _ => {
+                    return Err(self
+                        .error_at(pos, format!("unknown block `{}`", kind))
+                        .into())
+                }
+            }
+        }
+        let camera = self
+            .camera
+            .take()
+            .ok_or_else(|| self.error_at(self.pos, "scene has no camera".to_string()))?;
+        Ok(World::new(camera, self.lights, self.shapes))
+    }
+
+    fn camera(&mut self, start: usize) -> Result<(), ParseError> {
+        if self.camera.is_some() {
+            return Err(self.error_at(start, "camera defined twice".to_string()));
+        }
+        let (mut width, mut height, mut fov) = (800, 600, std::f32::consts::FRAC_PI_3);
+        let mut from = Vec3::new(0.0, 0.0, -5.0);
+        let mut to = Vec3::zero();
+        let mut up = Vec3::new(0.0, 1.0, 0.0);
+        let (mut aperture_radius, mut focal_distance) = (0.0, 1.0);
+        let mut aperture = Aperture::Circle;
+        let mut projection = Projection::Perspective;
+        self.block(|p, key, pos| {
+            match key {
+                "size" => {
+                    width = p.count()? as u32;
+                    height = p.count()? as u32;
+                }
+                "fov" => {
+                    fov = p.number()?;
+                    if fov <= 0.0 || fov >= std::f32::consts::PI {
+                        return Err(p.error_at(pos + 1, "fov must be between 0 and pi".to_string()));
+                    }
+                }
+                "from" => from = p.vec3()?,
+                "to" => to = p.vec3()?,
+                "up" => up = p.vec3()?,
+                "aperture" => aperture_radius = p.number()?.max(0.0),
+                "focal_distance" => {
+                    focal_distance = p.number()?;
+                    if focal_distance <= 0.0 {
+                        return Err(p.error_at(pos, "focal distance must be positive".to_string()));
+                    }
+                }
+                "blades" => {
+                    let blades = p.number()?;
+                    if blades < 3.0 {
+                        return Err(p.error_at(pos, "an aperture needs 3 blades".to_string()));
+                    }
+                    aperture = Aperture::Polygon {
+                        blades: blades as u32,
+                        rotation: p.number()?,
+                    };
+                }
+                "projection" => {
+                    let at = p.pos;
+                    projection = match p.word()?.as_str() {
+                        "perspective" => Projection::Perspective,
+                        "orthographic" => Projection::Orthographic { width: p.number()? },
+                        "fisheye" => Projection::Fisheye { fov: p.number()? },
+                        "equirectangular" => Projection::Equirectangular,
+                        other => {
+                            return Err(p.error_at(at, format!("unknown projection `{}`", other)))
+                        }
+                    };
+                }
+                _ => return Err(p.error_at(pos, format!("unknown camera property `{}`", key))),
+            }
+            Ok(())
+        })?;
+        let view = Mat::view_transformation(&from, &to, &up);
+        if view.determinant() == 0.0 {
+            return Err(self.error_at(start, "camera view is degenerate".to_string()));
+        }
+        let mut camera = Camera::new(width, height, fov, view);
+        camera.aperture_radius = aperture_radius;
+        camera.focal_distance = focal_distance;
+        camera.aperture = aperture;
+        camera.projection = projection;
+        self.camera = Some(camera);
+        Ok(())
+    }
+
+    // a point light, or an area light once it has a `rect` or `sphere`
+    fn light(&mut self) -> Result<(), ParseError> {
+        let mut point = PointLight::new(Vec3::zero(), Vec3::from_float(1.0));
+        let mut shape = None;
+        let mut jitter = None;
+        self.block(|p, key, pos| {
+            match key {
+                "position" => point.position = p.vec3()?,
+                "intensity" => point.intensity = p.vec3()?,
+                "rect" => {
+                    let (u, v) = (p.vec3()?, p.vec3()?);
+                    let (usteps, vsteps) = (p.count()?, p.count()?);
+                    shape = Some(LightShape::Rect {
+                        u,
+                        v,
+                        usteps,
+                        vsteps,
+                    });
+                }
+                "sphere" => {
+                    let radius = p.number()?;
+                    shape = Some(LightShape::Sphere {
+                        radius,
+                        samples: p.count()?,
+                    });
+                }
+                "jitter" => jitter = Some((pos, p.switch()?)),
+                "inverse_square" => point.inverse_square = p.switch()?,
+                _ => return Err(p.error_at(pos, format!("unknown light property `{}`", key))),
+            }
+            Ok(())
+        })?;
+        let light: Box<dyn Light> = match (shape, jitter) {
+            (None, None) => Box::new(point),
+            (None, Some((pos, _))) => {
+                let message = "`jitter` needs a `rect` or `sphere` light".to_string();
+                return Err(self.error_at(pos, message));
+            }
+            (Some(shape), jitter) => {
+                let area = AreaLight::new(point.position, shape, point.intensity)
+                    .jitter(jitter.is_none_or(|(_, on)| on))
+                    .inverse_square(point.inverse_square);
+                Box::new(area)
+            }
+        };
+        self.lights.push(light);
+        Ok(())
+    }
+
+    fn spotlight(&mut self) -> Result<(), ParseError> {
+        let quarter = std::f32::consts::FRAC_PI_4;
+        let mut light = SpotLight::new(
+            Vec3::zero(),
+            Vec3::new(0.0, -1.0, 0.0),
+            quarter / 2.0,
+            quarter,
+            Vec3::from_float(1.0),
+        );
+        self.block(|p, key, pos| {
+            match key {
+                "position" => light.position = p.vec3()?,
+                "direction" => light.direction = p.direction()?,
+                "intensity" => light.intensity = p.vec3()?,
+                "cone" => {
+                    let (inner, outer) = (p.number()?, p.number()?);
+                    if inner < 0.0 || outer < inner {
+                        return Err(
+                            p.error_at(pos, "cone angles must be 0 <= inner <= outer".to_string())
+                        );
+                    }
+                    (light.inner, light.outer) = (inner, outer);
+                }
+                "inverse_square" => light.inverse_square = p.switch()?,
+                _ => return Err(p.error_at(pos, format!("unknown spotlight property `{}`", key))),
+            }
+            Ok(())
+        })?;
+        self.lights.push(Box::new(light));
+        Ok(())
+    }
+
+    fn sun(&mut self) -> Result<(), ParseError> {
+        let mut light = DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0), Vec3::from_float(1.0));
+        self.block(|p, key, pos| {
+            match key {
+                "direction" => light.direction = p.direction()?,
+                "intensity" => light.intensity = p.vec3()?,
+                _ => return Err(p.error_at(pos, format!("unknown sun property `{}`", key))),
+            }
+            Ok(())
+        })?;
+        self.lights.push(Box::new(light));
+        Ok(())
+    }
+
+    fn pattern_def(&mut self, kind: &str, start: usize) -> Result<PatternDef, ParseError> {
+        let mut def = PatternDef::default();
+        self.block(|p, key, pos| {
+            if p.transform_property(&mut def.t, key)? {
+                return Ok(());
+            }
+            if !pattern_key(kind, key) {
+                let what = match kind {
+                    "bump" | "normal_map" | "vary" => kind.to_string(),
+                    _ => format!("{} pattern", kind),
+                };
+                return Err(p.error_at(pos, format!("unknown {} property `{}`", what, key)));
+            }
+            match key {
+                "noise" => {
+                    let at = p.pos;
+                    def.noise = p.word()?;
+                    match def.noise.as_str() {
+                        "perlin" | "simplex" => (),
+                        "worley" => {
+                            def.cellular = match p.word()?.as_str() {
+                                "f1" => Cellular::F1,
+                                "f2" => Cellular::F2,
+                                "edge" => Cellular::Edge,
+                                w => {
+                                    return Err(p.error_at(
+                                        at,
+                                        format!("expected f1, f2 or edge, found `{}`", w),
+                                    ))
+                                }
+                            }
+                        }
+                        w => return Err(p.error_at(at, format!("unknown noise `{}`", w))),
+                    }
+                }
+                "fractal" => {
+                    let at = p.pos;
+                    let kind = match p.word()?.as_str() {
+                        "fbm" => FractalKind::Fbm,
+                        "turbulence" => FractalKind::Turbulence,
+                        "ridged" => FractalKind::Ridged,
+                        w => return Err(p.error_at(at, format!("unknown fractal `{}`", w))),
+                    };
+                    def.fractal = Some((kind, p.count()? as u32));
+                }
+                "file" => def.file = Some(p.string()?),
+                "map" => {
+                    def.map = match p.word()?.as_str() {
+                        "spherical" => UvMap::Spherical,
+                        "planar" => UvMap::Planar,
+                        "cylindrical" => UvMap::Cylindrical,
+                        "cube" => UvMap::Cube,
+                        w => return Err(p.error_at(pos + 1, format!("unknown map `{}`", w))),
+                    }
+                }
+                "filter" => {
+                    def.filter = match p.word()?.as_str() {
+                        "nearest" => Filter::Nearest,
+                        "bilinear" => Filter::Bilinear,
+                        w => return Err(p.error_at(pos + 1, format!("unknown filter `{}`", w))),
+                    }
+                }
+                "wrap" => {
+                    def.wrap = match p.word()?.as_str() {
+                        "repeat" => Wrap::Repeat,
+                        "clamp" => Wrap::Clamp,
+                        "mirror" => Wrap::Mirror,
+                        w => return Err(p.error_at(pos + 1, format!("unknown wrap `{}`", w))),
+                    }
+                }
+                "srgb" => def.srgb = p.switch()?,
+                "mipmap" => def.mipmap = p.switch()?,
+                "anisotropy" => def.anisotropy = Some(p.count()? as Float),
+                "strength" => def.strength = p.number()?,
+                "range" => def.range = (p.number()?, p.number()?),
+                "colors" => {
+                    let (a, b) = (p.vec3()?, p.vec3()?);
+                    def.parts.push(Arc::new(Solid::new(a)));
+                    def.parts.push(Arc::new(Solid::new(b)));
+                }
+                "color" => def.parts.push(Arc::new(Solid::new(p.vec3()?))),
+                "pattern" => def.parts.push(p.pattern()?),
+                "scale_noise" => def.scale = p.number()?,
+                "seed" => def.seed = p.count()? as u64,
+                _ => unreachable!("`pattern_key` lets through only the keys above"),
+            }
+            Ok(())
+        })?;
+        if def.t.determinant() == 0.0 {
+            return Err(self.error_at(start, "pattern transform is not invertible".to_string()));
+        }
+        Ok(def)
+    }
+
+    // the image texture a `pattern image` or `normal_map` block describes
+    fn image(&self, start: usize, def: PatternDef) -> Result<ImageTexture, ParseError> {
+        let file = def
+            .file
+            .ok_or_else(|| self.error_at(start, "image needs a `file`".to_string()))?;
+        let mut texture = Texture::load(self.dir.join(&file))
+            .map_err(|e| self.error_at(start, format!("cannot read `{}`: {}", file, e)))?
+            .filter(def.filter)
+            .wrap(def.wrap);
+        if def.srgb {
+            texture = texture.srgb();
+        }
+        let mut mips = if def.mipmap {
+            MipMap::new(texture)
+        } else {
+            MipMap::single(texture)
+        };
+        if let Some(a) = def.anisotropy {
+            mips.max_anisotropy = a;
+        }
+        let mut image = ImageTexture::new(Arc::new(mips), def.map);
+        image.apply_transform(&def.t);
+        Ok(image)
+    }
+
+    fn pattern(&mut self) -> Result<Arc<dyn Pattern>, ParseError> {
+        fn transformed(mut p: impl Pattern + Transformable + 'static, t: &Mat) -> Arc<dyn Pattern> {
+            p.apply_transform(t);
+            Arc::new(p)
+        }
+
+        let start = self.pos;
+        let kind = self.word()?;
+        if !PATTERNS.contains(&kind.as_str()) {
+            return Err(self.error_at(start, format!("unknown pattern `{}`", kind)));
+        }
+        let mut def = self.pattern_def(&kind, start)?;
+        if kind == "image" {
+            return Ok(Arc::new(self.image(start, def)?));
+        }
+        let needed = match kind.as_str() {
+            "perturb" => 1,
+            _ => 2,
+        };
+        let parts = &mut def.parts;
+        if parts.is_empty() && needed == 2 {
+            parts.push(Arc::new(Solid::new(Vec3::from_float(1.0))));
+            parts.push(Arc::new(Solid::new(Vec3::zero())));
+        }
+        if parts.len() != needed {
+            let what = if needed == 1 {
+                "one pattern"
+            } else {
+                "two patterns"
+            };
+            return Err(self.error_at(
+                start,
+                format!("{} needs {}, found {}", kind, what, parts.len()),
+            ));
+        }
+        let b = parts.pop().unwrap();
+        let a = parts.pop().unwrap_or_else(|| b.clone());
+        let t = &def.t;
+        Ok(match kind.as_str() {
+            "stripe" => transformed(Stripe::nested(a, b), t),
+            "gradient" => transformed(Gradient::nested(a, b), t),
+            "ring" => transformed(Ring::nested(a, b), t),
+            "checker" => transformed(Checker::nested(a, b), t),
+            "radial_gradient" => transformed(RadialGradient::nested(a, b), t),
+            "blend" => transformed(Blend::new(a, b), t),
+            "perturb" => transformed(Perturb::new(b, def.scale, def.seed), t),
+            "noise" => transformed(NoisePattern::nested(def.noise_field(), a, b), t),
+            _ => unreachable!("checked against `PATTERNS` above"),
+        })
+    }
+
+    // heights from a nested pattern if there is one, from the block's own
+    // noise otherwise
+    fn bump(&mut self) -> Result<Arc<dyn Bump>, ParseError> {
+        let start = self.pos;
+        let mut def = self.pattern_def("bump", start)?;
+        let height: Arc<dyn Pattern> = match def.parts.len() {
+            0 => {
+                let black = Vec3::zero();
+                let mut noise = NoisePattern::new(def.noise_field(), black, Vec3::from_float(1.0));
+                noise.apply_transform(&def.t);
+                Arc::new(noise)
+            }
+            1 => def.parts.pop().unwrap(),
+            n => return Err(self.error_at(start, format!("bump needs one pattern, found {}", n))),
+        };
+        Ok(Arc::new(BumpMap::new(
+            Height::Pattern(height),
+            def.strength,
+        )))
+    }
+
+    fn normal_map(&mut self) -> Result<Arc<dyn Bump>, ParseError> {
+        let start = self.pos;
+        let def = self.pattern_def("normal_map", start)?;
+        let strength = def.strength;
+        let mut map = NormalMap::new(self.image(start, def)?);
+        map.strength = strength;
+        Ok(Arc::new(map))
+    }
+
+    // a scalar material parameter driven by the block's noise
+    fn vary(&mut self) -> Result<Varying, ParseError> {
+        let start = self.pos;
+        let param = match self.word()?.as_str() {
+            "ambient" => Param::Ambient,
+            "diffuse" => Param::Diffuse,
+            "specular" => Param::Specular,
+            "shininess" => Param::Shininess,
+            "reflective" => Param::Reflective,
+            "transparency" => Param::Transparency,
+            w => return Err(self.error_at(start, format!("cannot vary `{}`", w))),
+        };
+        let def = self.pattern_def("vary", start)?;
+        let (lo, hi) = def.range;
+        let mut varying = Varying::new(param, def.noise_field(), lo, hi);
+        varying.apply_transform(&def.t);
+        Ok(varying)
+    }
+
+    fn material_property(&mut self, m: &mut Material, key: &str) -> Result<bool, ParseError> {
+        match key {
+            "color" => m.color = self.vec3()?,
+            "pattern" => m.pattern = Some(self.pattern()?),
+            "bump" => m.bump = Some(self.bump()?),
+            "normal_map" => m.bump = Some(self.normal_map()?),
+            "vary" => m.varying.push(Arc::new(self.vary()?)),
+            "ambient" => m.ambient = self.number()?,
+            "diffuse" => m.diffuse = self.number()?,
+            "specular" => m.specular = self.number()?,
+            "shininess" => m.shininess = self.number()?,
+            "reflective" => m.reflective = self.number()?,
+            "transparency" => m.transparency = self.number()?,
+            "refractive_index" => m.refractive_index = self.number()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn transform_property(&mut self, t: &mut Mat, key: &str) -> Result<bool, ParseError> {
+        match key {
+            "translate" => t.apply_translation(self.number()?, self.number()?, self.number()?),
+            "scale" => {
+                let s = self.vec3()?;
+                t.apply_transform(&Mat::identity(4).scaling(s.x, s.y, s.z));
+            }
+            "rotate_x" => t.apply_rotation_x(self.number()?),
+            "rotate_y" => t.apply_rotation_y(self.number()?),
+            "rotate_z" => t.apply_rotation_z(self.number()?),
+            "shear" => {
+                let mut s = [0.0; 6];
+                for v in s.iter_mut() {
+                    *v = self.number()?;
+                }
+                t.apply_transform(&Mat::identity(4).shearing(s[0], s[1], s[2], s[3], s[4], s[5]));
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn shape(&mut self, kind: &str, start: usize) -> Result<(), ParseError> {
+        let mut def = ShapeDef {
+            m: Material::default(),
+            t: Mat::identity(4),
+            minimum: Float::NEG_INFINITY,
+            maximum: Float::INFINITY,
+            closed: false,
+            points: None,
+            file: None,
+        };
+        self.block(|p, key, pos| {
+            if p.transform_property(&mut def.t, key)? || p.material_property(&mut def.m, key)? {
+                return Ok(());
+            }
+            match (kind, key) {
+                (_, "material") => {
+                    let name = p.word()?;
+                    def.m = p.materials.get(&name).cloned().ok_or_else(|| {
+                        p.error_at(pos + 1, format!("unknown material `{}`", name))
+                    })?;
+                }
+                ("cylinder" | "cone", "minimum") => def.minimum = p.number()?,
+                ("cylinder" | "cone", "maximum") => def.maximum = p.number()?,
+                ("cylinder" | "cone", "closed") => def.closed = p.switch()?,
+                ("triangle", "points") => def.points = Some([p.vec3()?, p.vec3()?, p.vec3()?]),
+                ("mesh", "file") => def.file = Some(p.string()?),
+                _ => return Err(p.error_at(pos, format!("unknown {} property `{}`", kind, key))),
+            }
+            Ok(())
+        })?;
+        if def.minimum >= def.maximum {
+            return Err(self.error_at(start, format!("{} `minimum` is not below `maximum`", kind)));
+        }
+        if def.t.determinant() == 0.0 {
+            return Err(self.error_at(start, format!("{} transform is not invertible", kind)));
+        }
+
+        let shape: Box<dyn Shape> = match kind {
+            "sphere" => {
+                let mut s = Sphere {
+                    m: def.m,
+                    ..Default::default()
+                };
+                s.set_transform(def.t);
+                Box::new(s)
+            }
+            "plane" => {
+                let mut s = Plane {
+                    m: def.m,
+                    ..Default::default()
+                };
+                s.set_transform(def.t);
+                Box::new(s)
+            }
+            "cube" => {
+                let mut s = Cube {
+                    m: def.m,
+                    ..Default::default()
+                };
+                s.set_transform(def.t);
+                Box::new(s)
+            }
+            "cylinder" => {
+                let mut s = Cylinder {
+                    m: def.m,
+                    minimum: def.minimum,
+                    maximum: def.maximum,
+                    closed: def.closed,
+                    ..Default::default()
+                };
+                s.set_transform(def.t);
+                Box::new(s)
+            }
+            "cone" => {
+                let mut s = Cone {
+                    m: def.m,
+                    minimum: def.minimum,
+                    maximum: def.maximum,
+                    closed: def.closed,
+                    ..Default::default()
+                };
+                s.set_transform(def.t);
+                Box::new(s)
+            }
+            "triangle" => {
+                let [a, b, c] = def
+                    .points
+                    .ok_or_else(|| self.error_at(start, "triangle needs `points`".to_string()))?;
+                let mut s = Triangle::new(a, b, c);
+                s.m = Arc::new(def.m);
+                s.set_transform(def.t);
+                Box::new(s)
+            }
+            _ => {
+                let file = def
+                    .file
+                    .ok_or_else(|| self.error_at(start, "mesh needs a `file`".to_string()))?;
+                let obj = ObjFile::load(self.dir.join(&file))
+                    .map_err(|e| self.error_at(start, format!("cannot read `{}`: {}", file, e)))?;
+                // statements the parser does not know, such as `mtllib`, are
+                // fine to skip, lost geometry is not
+                if let Some(w) = obj.warnings.iter().find(|w| w.is_geometry()) {
+                    let message = format!("`{}` line {}: {}", file, w.line, w.reason);
+                    return Err(self.error_at(start, message));
+                }
+                let mut mesh = obj.to_mesh();
+                if mesh.is_empty() {
+                    return Err(self.error_at(start, format!("`{}` has no faces", file)));
+                }
+                mesh.set_material(&def.m);
+                mesh.apply_transform(&def.t);
+                Box::new(mesh)
+            }
+        };
+        self.shapes.push(shape);
+        Ok(())
+    }
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tok::Word(w) => write!(f, "`{}`", w),
+            Tok::Num(n) => write!(f, "`{}`", n),
+            Tok::Str(s) => write!(f, "\"{}\"", s),
+            Tok::Open => write!(f, "`{{`"),
+            Tok::Close => write!(f, "`}}`"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ray::Ray;
+
+    const SCENE: &str = "
+camera {
+    size 40 30
+    fov 1.0
+    from 0 0 -5
+    to 0 0 0
+}
+
+material red { color 1 0 0 diffuse 0.5 }
+
+light { position -10 10 -10 }
+light { position 10 10 -10 intensity 0.5 0.5 0.5 }
+
+sphere {
+    material red
+    specular 0.1
+    translate 0 0 2
+}
+plane { translate 0 -1 0 reflective 0.5 }
+triangle { points 0 1 0  -1 0 0  1 0 0 }
+";
+
+    fn parse_error(src: &str) -> ParseError {
+        match parse(src) {
+            Err(SceneError::Parse(e)) => e,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("scene should not parse"),
+        }
+    }
+
+    #[test]
+    fn test_parse_scene() {
+        let w = parse(SCENE).unwrap();
+
+        assert_eq!(w.camera.width, 40);
+        assert_eq!(w.camera.height, 30);
+        assert_eq!(w.lights.len(), 2);
+        assert_eq!(w.lights[1].intensity(), &Vec3::from_float(0.5));
+        assert_eq!(w.shapes().len(), 3);
+
+        let m = w.shapes()[0].get_material();
+        assert_eq!(m.color, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(m.diffuse, 0.5);
+        assert_eq!(m.specular, 0.1);
+        assert_eq!(w.shapes()[1].get_material().reflective, 0.5);
+    }
+
+    #[test]
+    fn test_camera_lens() {
+        let w = parse("camera { aperture 0.2 focal_distance 4 blades 5 0.1 }").unwrap();
+
+        assert_eq!(w.camera.aperture_radius, 0.2);
+        assert_eq!(w.camera.focal_distance, 4.0);
+        assert_eq!(
+            w.camera.aperture,
+            Aperture::Polygon {
+                blades: 5,
+                rotation: 0.1
+            }
+        );
+        let e = parse_error("camera { blades 2 0 }");
+        assert_eq!(e.message, "an aperture needs 3 blades");
+    }
+
+    #[test]
+    fn test_camera_size_and_fov() {
+        let e = parse_error("camera {\n  size 640.5 480\n}");
+        assert_eq!((e.line, e.col), (2, 8));
+        assert_eq!(e.message, "expected a count, found 640.5");
+        let e = parse_error("camera { size 640 0 }");
+        assert_eq!(e.message, "expected a count, found 0");
+        for fov in ["0", "-1", "3.1416", "4"] {
+            let e = parse_error(&format!("camera {{\n  fov {}\n}}", fov));
+            assert_eq!((e.line, e.col), (2, 7), "{}", fov);
+            assert_eq!(e.message, "fov must be between 0 and pi", "{}", fov);
+        }
+        assert!(parse("camera { fov 3.1 }").is_ok());
+    }
+
+    #[test]
+    fn test_camera_projection() {
+        let w = parse("camera { projection orthographic 6 }").unwrap();
+        assert_eq!(w.camera.projection, Projection::Orthographic { width: 6.0 });
+
+        let e = parse_error("camera {\n  projection cubemap\n}");
+        assert_eq!((e.line, e.col), (2, 14));
+        assert_eq!(e.message, "unknown projection `cubemap`");
+    }
+
+    #[test]
+    fn test_area_lights() {
+        let w =
+            parse("camera {} light { position 0 5 0 rect 1 0 0 0 0 2 2 3 jitter off }").unwrap();
+        let samples = w.lights[0].samples(&Vec3::zero());
+        assert_eq!(samples.len(), 6);
+        assert_eq!(
+            &samples[0].dir * samples[0].distance,
+            Vec3::new(-0.25, 5.0, -2.0 / 3.0)
+        );
+
+        let w = parse("camera {} light { sphere 0.5 8 inverse_square on }").unwrap();
+        let samples = w.lights[0].samples(&Vec3::new(0.0, -2.0, 0.0));
+        assert_eq!(samples.len(), 8);
+        assert!(samples.iter().all(|s| s.intensity.x < 0.5));
+
+        let e = parse_error("light { sphere 1 0 }");
+        assert_eq!(e.message, "expected a count, found 0");
+        let e = parse_error("light { jitter maybe }");
+        assert_eq!(e.message, "expected on or off, found `maybe`");
+        let e = parse_error("light { position 0 1 0 jitter off }");
+        assert_eq!(e.message, "`jitter` needs a `rect` or `sphere` light");
+    }
+
+    #[test]
+    fn test_spot_and_sun() {
+        let w = parse(
+            "camera {}
+            spotlight { position 0 4 0 direction 0 -2 0 cone 0.2 0.4 intensity 2 2 2 inverse_square on }
+            sun { direction 0 0 3 intensity 0.5 0.5 0.5 }",
+        )
+        .unwrap();
+        let spot = &w.lights[0].samples(&Vec3::zero())[0];
+        assert_eq!(spot.intensity, Vec3::from_float(0.125));
+        assert_eq!(
+            w.lights[0].samples(&Vec3::new(4.0, 0.0, 0.0))[0].intensity,
+            Vec3::zero()
+        );
+        let sun = &w.lights[1].samples(&Vec3::zero())[0];
+        assert_eq!(sun.dir, Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(sun.distance, Float::INFINITY);
+        assert_eq!(w.lights[1].intensity(), &Vec3::from_float(0.5));
+
+        let e = parse_error("spotlight { cone 0.5 0.2 }");
+        assert_eq!(e.message, "cone angles must be 0 <= inner <= outer");
+        let e = parse_error("sun { direction 0 0 0 }");
+        assert_eq!(e.message, "direction must not be zero");
+    }
+
+    #[test]
+    fn test_pattern() {
+        let w = parse(
+            "camera {}
+            material floor { pattern checker { colors 1 0 0 0 0 1 scale 2 2 2 } }
+            plane { material floor }",
+        )
+        .unwrap();
+        let floor = &w.shapes()[0];
+        assert_eq!(
+            floor.color_at(&Vec3::new(1.0, 0.0, 1.0)),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            floor.color_at(&Vec3::new(3.0, 0.0, 1.0)),
+            Vec3::new(0.0, 0.0, 1.0)
+        );
+
+        let e = parse_error("camera {} sphere { pattern zebra {} }");
+        assert_eq!(e.message, "unknown pattern `zebra`");
+        let e = parse_error("camera {} sphere { pattern ring { scale 0 1 1 } }");
+        assert_eq!(e.message, "pattern transform is not invertible");
+    }
+
+    #[test]
+    fn test_nested_patterns() {
+        let w = parse(
+            "camera {}
+            sphere {
+                pattern blend {
+                    pattern checker { pattern stripe { colors 1 0 0 0 0 1 } color 0 0 0 }
+                    color 1 1 1
+                }
+            }
+            sphere { pattern perturb { scale_noise 0.5 seed 2 pattern ring {} } }",
+        )
+        .unwrap();
+        let sp = &w.shapes()[0];
+        assert_eq!(
+            sp.color_at(&Vec3::new(0.5, 0.0, 0.5)),
+            Vec3::new(1.0, 0.5, 0.5)
+        );
+        assert_eq!(
+            sp.color_at(&Vec3::new(1.5, 0.0, 0.5)),
+            Vec3::from_float(0.5)
+        );
+        assert_eq!(
+            sp.color_at(&Vec3::new(0.5, 1.0, 0.5)),
+            Vec3::from_float(0.5)
+        );
+        assert_eq!(
+            sp.color_at(&Vec3::new(2.5, 0.0, 0.5)),
+            Vec3::new(1.0, 0.5, 0.5)
+        );
+        assert!(w.shapes()[1].get_material().pattern.is_some());
+
+        let e = parse_error("camera {} sphere { pattern blend { color 1 1 1 } }");
+        assert_eq!(e.message, "blend needs two patterns, found 1");
+        let e = parse_error("camera {} sphere { pattern perturb {} }");
+        assert_eq!(e.message, "perturb needs one pattern, found 0");
+    }
+
+    #[test]
+    fn test_noise_pattern() {
+        let w = parse(
+            "camera {}
+            sphere { pattern noise { noise worley f1 seed 3 colors 0 0 0 1 1 1 } }
+            sphere { pattern noise { noise simplex fractal ridged 3 seed 3 } }",
+        )
+        .unwrap();
+        let cells = Worley::new(3);
+        let p = Vec3::new(0.3, -0.6, 0.2);
+        assert_eq!(
+            w.shapes()[0].color_at(&p),
+            Vec3::from_float(cells.remap(&p, 0.0, 1.0))
+        );
+        let ridged = Fractal::new(Arc::new(Simplex::new(3)), FractalKind::Ridged, 3);
+        // white to black by default
+        assert_eq!(
+            w.shapes()[1].color_at(&p),
+            Vec3::from_float(1.0 - ridged.remap(&p, 0.0, 1.0))
+        );
+
+        let e = parse_error("camera {} sphere { pattern noise { noise value } }");
+        assert_eq!(e.message, "unknown noise `value`");
+        let e = parse_error("camera {} sphere { pattern noise { noise worley f3 } }");
+        assert_eq!(e.message, "expected f1, f2 or edge, found `f3`");
+        let e = parse_error("camera {} sphere { pattern noise { fractal wavy 2 } }");
+        assert_eq!(e.message, "unknown fractal `wavy`");
+    }
+
+    #[test]
+    fn test_vary() {
+        let w = parse(
+            "camera {}
+            sphere {
+                reflective 0.9
+                vary reflective { range 0.2 0.6 noise simplex seed 5 scale 2 2 2 }
+                vary transparency { noise worley f2 fractal fbm 2 }
+            }",
+        )
+        .unwrap();
+        let m = w.shapes()[0].get_material();
+        assert_eq!(m.varying.len(), 2);
+        let p = Vec3::new(0.6, -0.8, 0.0);
+        let at = m.finish_at(&p);
+        let simplex = Simplex::new(5);
+        assert_eq!(
+            at.reflective,
+            simplex.remap(&Vec3::new(0.3, -0.4, 0.0), 0.2, 0.6)
+        );
+        let cells = Worley {
+            seed: 0,
+            feature: Cellular::F2,
+        };
+        let fbm = Fractal::new(Arc::new(cells), FractalKind::Fbm, 2);
+        assert_eq!(at.transparency, fbm.remap(&p, 0.0, 1.0));
+        assert_eq!(at.diffuse, m.diffuse);
+
+        let e = parse_error("camera {} sphere { vary color { } }");
+        assert_eq!(e.message, "cannot vary `color`");
+        let e = parse_error("camera {} sphere { vary diffuse { colors 1 1 1 0 0 0 } }");
+        assert_eq!(e.message, "unknown vary property `colors`");
+        let e = parse_error("camera {} plane { pattern noise { range 0 1 } }");
+        assert_eq!(e.message, "unknown noise pattern property `range`");
+    }
+
+    #[test]
+    fn test_bump_and_normal_map() {
+        let dir = std::env::temp_dir().join(format!("rt_loader_bump_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // every texel tilts toward +u
+        fs::write(dir.join("tilt.ppm"), "P3 1 1 100 85 50 85").unwrap();
+        fs::write(
+            dir.join("bump.scene"),
+            "camera {}
+            plane { bump { strength 0.5 pattern gradient { colors 0 0 0 1 1 1 } } }
+            plane { bump { strength 0.2 noise simplex seed 4 } }
+            plane { normal_map { file \"tilt.ppm\" map planar } }",
+        )
+        .unwrap();
+        let w = load(dir.join("bump.scene"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let w = w.unwrap();
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let p = Vec3::new(0.3, 0.0, 0.2);
+        let bent = |i: usize| {
+            let sp = w.shapes()[i].as_ref();
+            let bump = sp.get_material().bump.as_ref().unwrap();
+            bump.perturb(sp, &p, &up)
+        };
+        assert_eq!(bent(0), Vec3::new(-0.5, 1.0, 0.0).norm());
+        assert_ne!(bent(1), up);
+        assert_eq!(bent(2), Vec3::new(0.7, 0.7, 0.0).norm());
+
+        let e = parse_error("camera {} plane { bump { height 2 } }");
+        assert_eq!(e.message, "unknown bump property `height`");
+        let e = parse_error("camera {} plane { pattern stripe { strength 2 } }");
+        assert_eq!(e.message, "unknown stripe pattern property `strength`");
+        let e = parse_error("camera {} plane { normal_map { strength 2 } }");
+        assert_eq!(e.message, "image needs a `file`");
+    }
+
+    #[test]
+    fn test_transforms_applied() {
+        let w = parse(SCENE).unwrap();
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = w.intersect(&r, Vec::new());
+
+        assert_eq!(xs[0].t, 5.0);
+        assert_eq!(xs[1].t, 6.0);
+    }
+
+    #[test]
+    fn test_error_position() {
+        let e = parse_error("camera {\n    size 10 10\n    zoom 2\n}");
+
+        assert_eq!((e.line, e.col), (3, 5));
+        assert_eq!(e.message, "unknown camera property `zoom`");
+    }
+
+    #[test]
+    fn test_unknown_material() {
+        let e = parse_error("sphere { material\n  chrome }");
+
+        assert_eq!((e.line, e.col), (2, 3));
+        assert_eq!(e.message, "unknown material `chrome`");
+    }
+
+    #[test]
+    fn test_bad_number() {
+        let e = parse_error("light { position 1 2x 3 }");
+
+        assert_eq!((e.line, e.col), (1, 20));
+    }
+
+    #[test]
+    fn test_unexpected_eof() {
+        let e = parse_error("camera {\n  size 10");
+
+        assert_eq!((e.line, e.col), (2, 10));
+        assert_eq!(e.message, "unexpected end of file");
+    }
+
+    #[test]
+    fn test_missing_camera() {
+        let e = parse_error("sphere {}");
+
+        assert_eq!(e.message, "scene has no camera");
+    }
+
+    #[test]
+    fn test_shape_property_on_wrong_shape() {
+        let e = parse_error("camera {}\nsphere { minimum 2 }");
+
+        assert_eq!((e.line, e.col), (2, 10));
+        assert_eq!(e.message, "unknown sphere property `minimum`");
+    }
+
+    #[test]
+    fn test_cube() {
+        let w = parse("camera {}\ncube { scale 2 1 1 translate 0 0 3 }").unwrap();
+        let r = Ray::new(Vec3::new(1.5, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = w.intersect(&r, Vec::new());
+
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(
+            xs[0].sp.normal_at(&Vec3::new(1.5, 0.5, 2.0)),
+            Vec3::new(0.0, 0.0, -1.0)
+        );
+        let e = parse_error("camera {}\ncube { closed on }");
+        assert_eq!(e.message, "unknown cube property `closed`");
+    }
+
+    #[test]
+    fn test_cylinder_and_cone() {
+        let w = parse(
+            "camera {}
+            cylinder { minimum 1 maximum 2 closed on }
+            cone { minimum -1 maximum 0 translate 5 0 0 }",
+        )
+        .unwrap();
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let r = Ray::new(Vec3::new(0.5, 5.0, 0.0), down.clone());
+        let xs = w.intersect(&r, Vec::new());
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 3.0);
+        assert_eq!(xs[0].sp.normal_at(&Vec3::new(0.5, 2.0, 0.0)), -&down);
+
+        // open at the bottom, the ray only meets the side
+        let r = Ray::new(Vec3::new(5.5, 5.0, 0.0), down);
+        let xs = w.intersect(&r, Vec::new());
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 5.5);
+        let r = Ray::new(Vec3::new(3.0, -0.75, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let xs = w.intersect(&r, Vec::new());
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 1.25);
+
+        let e = parse_error("camera {}\ncone { minimum 1 maximum 1 }");
+        assert_eq!((e.line, e.col), (2, 1));
+        assert_eq!(e.message, "cone `minimum` is not below `maximum`");
+    }
+
+    #[test]
+    fn test_missing_file() {
+        match load("does/not/exist.scene") {
+            Err(SceneError::Io(_)) => {}
+            _ => panic!("expected an io error"),
+        }
+    }
+
+    #[test]
+    fn test_mesh_relative_to_scene() {
+        let dir = std::env::temp_dir().join(format!("rt_loader_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("quad.obj"),
+            "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3 4\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("quad.scene"),
+            "camera {}\nmesh { file \"quad.obj\" color 0 1 0 translate 0 0 1 }",
+        )
+        .unwrap();
+        let w = load(dir.join("quad.scene"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let w = w.unwrap();
+        let r = Ray::new(Vec3::new(0.5, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = w.intersect(&r, Vec::new());
+        assert_eq!(xs[0].t, 3.0);
+        assert_eq!(xs[0].sp.get_material().color, Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_broken_mesh() {
+        let dir = std::env::temp_dir().join(format!("rt_loader_obj_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("broken.obj"),
+            "mtllib quad.mtl\nv -1 1 0\nv -1 0 0\nv 1 0 0\nf 1 2 3\nf 1 3 4\n",
+        )
+        .unwrap();
+        fs::write(dir.join("passwd"), "root:x:0:0:root:/root:/bin/sh\n").unwrap();
+        let scene = |file: &str| {
+            fs::write(
+                dir.join("mesh.scene"),
+                format!("camera {{}}\n  mesh {{ file \"{}\" }}", file),
+            )
+            .unwrap();
+            match load(dir.join("mesh.scene")) {
+                Err(SceneError::Parse(e)) => e,
+                Err(e) => panic!("unexpected error {}", e),
+                Ok(_) => panic!("scene should not load"),
+            }
+        };
+        let broken = scene("broken.obj");
+        let passwd = scene("passwd");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((broken.line, broken.col), (2, 3));
+        assert_eq!(
+            broken.message,
+            "`broken.obj` line 6: vertex index 4 out of range"
+        );
+        assert_eq!(passwd.message, "`passwd` has no faces");
+    }
+
+    #[test]
+    fn test_pattern_keys_by_kind() {
+        for (src, message) in [
+            (
+                "pattern image { file \"a.png\" colors 1 1 1 0 0 0 }",
+                "unknown image pattern property `colors`",
+            ),
+            (
+                "pattern image { pattern stripe {} }",
+                "unknown image pattern property `pattern`",
+            ),
+            (
+                "pattern image { noise simplex }",
+                "unknown image pattern property `noise`",
+            ),
+            (
+                "pattern stripe { seed 3 }",
+                "unknown stripe pattern property `seed`",
+            ),
+            (
+                "pattern noise { file \"a.png\" }",
+                "unknown noise pattern property `file`",
+            ),
+            (
+                "bump { colors 1 1 1 0 0 0 }",
+                "unknown bump property `colors`",
+            ),
+            (
+                "normal_map { seed 2 }",
+                "unknown normal_map property `seed`",
+            ),
+            ("pattern zebra { seed 2 }", "unknown pattern `zebra`"),
+        ] {
+            let e = parse_error(&format!("camera {{}} plane {{ {} }}", src));
+            assert_eq!(e.message, message, "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_image_pattern() {
+        let dir = std::env::temp_dir().join(format!("rt_loader_image_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // one red column, one blue
+        fs::write(dir.join("split.ppm"), "P3 2 1 255 255 0 0 0 0 255").unwrap();
+        let scene = |pattern: &str| {
+            fs::write(
+                dir.join("image.scene"),
+                format!("camera {{}}\nplane {{ pattern image {{ {} }} }}", pattern),
+            )
+            .unwrap();
+            load(dir.join("image.scene"))
+        };
+        let w = scene("file \"split.ppm\" map planar filter nearest wrap clamp");
+        let missing = scene("file \"nope.ppm\"");
+        let bad_map = scene("file \"split.ppm\" map conical");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let w = w.unwrap();
+        let plane = &w.shapes()[0];
+        assert_eq!(
+            plane.color_at(&Vec3::new(0.25, 0.0, 0.5)),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            plane.color_at(&Vec3::new(1.75, 0.0, 0.5)),
+            Vec3::new(0.0, 0.0, 1.0)
+        );
+        match missing {
+            Err(SceneError::Parse(e)) => assert!(e.message.starts_with("cannot read `nope.ppm`")),
+            _ => panic!("expected a parse error"),
+        }
+        match bad_map {
+            Err(SceneError::Parse(e)) => assert_eq!(e.message, "unknown map `conical`"),
+            _ => panic!("expected a parse error"),
+        }
+    }
+}
@@ -1 +1,11 @@
 pub mod canvas;
+pub mod filter;
+pub mod framebuffer;
+pub mod loader;
+pub mod obj;
+pub mod png;
+pub mod renderer;
+pub mod sampler;
+pub mod texture;
+pub mod tonemap;
+pub mod zlib;
@@ -0,0 +1,379 @@
+use std::{fs, path::Path};
+
+use crate::{
+    utils::vec3::{Float, Vec3},
+    world::shapes::{
+        mesh::{Face, Mesh},
+        triangle::{SmoothTriangle, Triangle},
+    },
+};
+
+/// A statement the parser skipped, with the 1-based line it was on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjWarning {
+    pub line: usize,
+    // the statement's first word, `v` or `f` for geometry that went missing
+    pub keyword: String,
+    pub reason: String,
+}
+
+impl ObjWarning {
+    /// Whether a vertex, normal, texture coordinate or face was dropped, as
+    /// opposed to a statement this parser does not support.
+    pub fn is_geometry(&self) -> bool {
+        matches!(self.keyword.as_str(), "v" | "vn" | "vt" | "f")
+    }
+}
+
+/// Triangle made of indices into the vertex lists of the file, already
+/// resolved to 0-based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjFace {
+    pub vertices: [usize; 3],
+    pub uvs: Option<[usize; 3]>,
+    pub normals: Option<[usize; 3]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjGroup {
+    pub name: String,
+    pub faces: Vec<ObjFace>,
+}
+
+/// Parsed Wavefront OBJ file. Faces are fan triangulated, faces outside of
+/// any `g`/`o` statement land in a group named `default`.
+#[derive(Debug, Default)]
+pub struct ObjFile {
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(Float, Float)>,
+    pub groups: Vec<ObjGroup>,
+    pub warnings: Vec<ObjWarning>,
+}
+
+// one `v/vt/vn` corner of a face
+type Corner = (usize, Option<usize>, Option<usize>);
+
+impl ObjFile {
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn parse(src: &str) -> Self {
+        let mut obj = Self::default();
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Err(reason) = obj.statement(line) {
+                obj.warnings.push(ObjWarning {
+                    line: i + 1,
+                    keyword: line.split_whitespace().next().unwrap_or("").to_string(),
+                    reason,
+                });
+            }
+        }
+        obj
+    }
+
+    fn statement(&mut self, line: &str) -> Result<(), String> {
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        match keyword {
+            "v" => {
+                let v = floats(&args, 3)?;
+                self.vertices.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let v = floats(&args, 3)?;
+                self.normals.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = floats(&args, 2)?;
+                self.uvs.push((v[0], v[1]));
+            }
+            "f" => self.face(&args)?,
+            "g" | "o" => {
+                let name = if args.is_empty() {
+                    "default".to_string()
+                } else {
+                    args.join(" ")
+                };
+                self.groups.push(ObjGroup {
+                    name,
+                    faces: Vec::new(),
+                });
+            }
+            _ => return Err(format!("unsupported statement `{}`", keyword)),
+        }
+        Ok(())
+    }
+
+    fn face(&mut self, args: &[&str]) -> Result<(), String> {
+        if args.len() < 3 {
+            return Err(format!("face needs 3 vertices, got {}", args.len()));
+        }
+        let corners = args
+            .iter()
+            .map(|a| self.corner(a))
+            .collect::<Result<Vec<Corner>, String>>()?;
+
+        if self.groups.is_empty() {
+            self.groups.push(ObjGroup {
+                name: "default".to_string(),
+                faces: Vec::new(),
+            });
+        }
+        let group = self.groups.last_mut().unwrap();
+        for i in 1..corners.len() - 1 {
+            let c = [&corners[0], &corners[i], &corners[i + 1]];
+            let uvs = c.iter().map(|c| c.1).collect::<Option<Vec<usize>>>();
+            let normals = c.iter().map(|c| c.2).collect::<Option<Vec<usize>>>();
+            group.faces.push(ObjFace {
+                vertices: [c[0].0, c[1].0, c[2].0],
+                uvs: uvs.map(|u| [u[0], u[1], u[2]]),
+                normals: normals.map(|n| [n[0], n[1], n[2]]),
+            });
+        }
+        Ok(())
+    }
+
+    fn corner(&self, arg: &str) -> Result<Corner, String> {
+        let mut parts = arg.split('/');
+        let v = index(parts.next(), self.vertices.len(), "vertex")?
+            .ok_or_else(|| format!("missing vertex index in `{}`", arg))?;
+        let vt = index(parts.next(), self.uvs.len(), "texture")?;
+        let vn = index(parts.next(), self.normals.len(), "normal")?;
+        if parts.next().is_some() {
+            return Err(format!("malformed face vertex `{}`", arg));
+        }
+        Ok((v, vt, vn))
+    }
+
+    fn group_faces(&self, group: &ObjGroup) -> Vec<Face> {
+        group
+            .faces
+            .iter()
+            .map(|f| {
+                let [a, b, c] = f.vertices.map(|i| self.vertices[i].clone());
+                match f.normals {
+                    Some(n) => {
+                        let [na, nb, nc] = n.map(|i| self.normals[i].clone());
+                        Face::Smooth(SmoothTriangle::new(a, b, c, na, nb, nc))
+                    }
+                    None => Face::Flat(Triangle::new(a, b, c)),
+                }
+            })
+            .collect()
+    }
+
+    /// Every face of the file in a single mesh.
+    pub fn to_mesh(&self) -> Mesh {
+        let faces = self
+            .groups
+            .iter()
+            .flat_map(|g| self.group_faces(g))
+            .collect();
+        Mesh::new(faces)
+    }
+
+    /// One mesh per named group, groups without faces are skipped.
+    pub fn to_meshes(&self) -> Vec<(String, Mesh)> {
+        self.groups
+            .iter()
+            .filter(|g| !g.faces.is_empty())
+            .map(|g| (g.name.clone(), Mesh::new(self.group_faces(g))))
+            .collect()
+    }
+}
+
+fn floats(args: &[&str], min: usize) -> Result<Vec<Float>, String> {
+    if args.len() < min {
+        return Err(format!("expected {} numbers, got {}", min, args.len()));
+    }
+    args.iter()
+        .map(|a| {
+            a.parse::<Float>()
+                .map_err(|_| format!("invalid number `{}`", a))
+        })
+        .collect()
+}
+
+/// Resolves a 1-based (or negative, relative to the end) OBJ index.
+fn index(part: Option<&str>, len: usize, what: &str) -> Result<Option<usize>, String> {
+    let part = match part {
+        None | Some("") => return Ok(None),
+        Some(p) => p,
+    };
+    let i: i64 = part
+        .parse()
+        .map_err(|_| format!("invalid {} index `{}`", what, part))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if i == 0 || resolved < 0 || resolved >= len as i64 {
+        return Err(format!("{} index {} out of range", what, i));
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{utils::ray::Ray, world::shapes::shape::Shape};
+
+    #[test]
+    fn test_ignores_unrecognized_lines() {
+        let obj = ObjFile::parse(
+            "There was a young lady named Bright
+who traveled much faster than light.
+mtllib scene.mtl",
+        );
+
+        assert!(obj.vertices.is_empty());
+        assert_eq!(obj.warnings.len(), 3);
+        assert_eq!(obj.warnings[2].line, 3);
+        assert_eq!(obj.warnings[2].reason, "unsupported statement `mtllib`");
+        assert!(!obj.warnings[2].is_geometry());
+    }
+
+    #[test]
+    fn test_vertices() {
+        let obj = ObjFile::parse(
+            "v -1 1 0
+v -1.0000 0.5000 0.0000
+v 1 0 0
+v 1 1 0",
+        );
+
+        assert_eq!(obj.vertices.len(), 4);
+        assert_eq!(obj.vertices[1], Vec3::new(-1.0, 0.5, 0.0));
+        assert!(obj.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_faces() {
+        let obj = ObjFile::parse(
+            "v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+
+f 1 2 3
+f 1 3 4",
+        );
+        let faces = &obj.groups[0].faces;
+
+        assert_eq!(obj.groups[0].name, "default");
+        assert_eq!(faces[0].vertices, [0, 1, 2]);
+        assert_eq!(faces[1].vertices, [0, 2, 3]);
+    }
+
+    #[test]
+    fn test_polygon_fan() {
+        let obj = ObjFile::parse(
+            "v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+v 0 2 0
+f 1 2 3 4 5",
+        );
+        let faces = &obj.groups[0].faces;
+
+        assert_eq!(faces.len(), 3);
+        assert_eq!(faces[0].vertices, [0, 1, 2]);
+        assert_eq!(faces[1].vertices, [0, 2, 3]);
+        assert_eq!(faces[2].vertices, [0, 3, 4]);
+    }
+
+    #[test]
+    fn test_groups() {
+        let obj = ObjFile::parse(
+            "v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+g FirstGroup
+f 1 2 3
+o SecondGroup
+f 1 3 4",
+        );
+        let meshes = obj.to_meshes();
+
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].0, "FirstGroup");
+        assert_eq!(meshes[1].0, "SecondGroup");
+        assert_eq!(meshes[1].1.len(), 1);
+    }
+
+    #[test]
+    fn test_normals_and_uvs() {
+        let obj = ObjFile::parse(
+            "v 0 1 0
+v -1 0 0
+v 1 0 0
+vn -1 0 0
+vn 1 0 0
+vn 0 1 0
+vt 0.5 1
+f 1//3 2//1 3//2
+f 1/1/3 2/1/1 3/1/2",
+        );
+        let faces = &obj.groups[0].faces;
+
+        assert_eq!(obj.normals[2], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(faces[0].normals, Some([2, 0, 1]));
+        assert_eq!(faces[0].uvs, None);
+        assert_eq!(faces[1].uvs, Some([0, 0, 0]));
+        assert!(matches!(obj.to_mesh().faces[0], Face::Smooth(_)));
+    }
+
+    #[test]
+    fn test_negative_indices() {
+        let obj = ObjFile::parse(
+            "v -1 1 0
+v -1 0 0
+v 1 0 0
+f -3 -2 -1",
+        );
+
+        assert_eq!(obj.groups[0].faces[0].vertices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_bad_statements_reported() {
+        let obj = ObjFile::parse(
+            "v 1 2
+v 1 x 3
+v 0 0 0
+f 1 2 7
+f 1 1
+vt",
+        );
+
+        let lines: Vec<usize> = obj.warnings.iter().map(|w| w.line).collect();
+        assert_eq!(lines, vec![1, 2, 4, 5, 6]);
+        assert_eq!(obj.warnings[2].reason, "vertex index 2 out of range");
+        assert!(obj.warnings.iter().all(|w| w.is_geometry()));
+        assert!(obj.groups.is_empty());
+    }
+
+    #[test]
+    fn test_mesh_intersects() {
+        let obj = ObjFile::parse(
+            "v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+f 1 2 3 4",
+        );
+        let mesh = obj.to_mesh();
+        let r = Ray::new(Vec3::new(0.5, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut xs = Vec::new();
+        mesh.intersect(&r, &mut xs);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.0);
+    }
+}
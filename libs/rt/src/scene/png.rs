@@ -0,0 +1,298 @@
+use super::zlib;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |c, &b| {
+        CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+/// Decoded image. Samples are widened to `u16` but keep the range of
+/// `depth`, palettes are expanded to RGB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngImage {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u8,
+    pub channels: u8,
+    pub samples: Vec<u16>,
+}
+
+/// Encodes 8 bit RGB pixels.
+pub fn encode_rgb8(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    encode(width, height, 8, pixels)
+}
+
+/// Encodes 16 bit RGB pixels.
+pub fn encode_rgb16(width: u32, height: u32, pixels: &[u16]) -> Vec<u8> {
+    let bytes: Vec<u8> = pixels.iter().flat_map(|s| s.to_be_bytes()).collect();
+    encode(width, height, 16, &bytes)
+}
+
+fn encode(width: u32, height: u32, depth: u8, raw: &[u8]) -> Vec<u8> {
+    let bpp = 3 * depth as usize / 8;
+    let stride = width as usize * bpp;
+    assert_eq!(raw.len(), stride * height as usize, "pixel buffer size");
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend(width.to_be_bytes());
+    ihdr.extend(height.to_be_bytes());
+    // bit depth, truecolor, deflate, adaptive filtering, no interlace
+    ihdr.extend([depth, 2, 0, 0, 0]);
+
+    let mut filtered = Vec::with_capacity((stride + 1) * height as usize);
+    let empty = vec![0; stride];
+    for y in 0..height as usize {
+        let row = &raw[y * stride..(y + 1) * stride];
+        let prev = if y == 0 {
+            &empty[..]
+        } else {
+            &raw[(y - 1) * stride..y * stride]
+        };
+        filter_row(row, prev, bpp, &mut filtered);
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &ihdr);
+    write_chunk(&mut out, b"IDAT", &zlib::compress(&filtered));
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn predict(kind: u8, row: &[u8], prev: &[u8], i: usize, bpp: usize) -> u8 {
+    let a = if i >= bpp { row[i - bpp] } else { 0 };
+    let c = if i >= bpp { prev[i - bpp] } else { 0 };
+    let b = prev[i];
+    match kind {
+        1 => a,
+        2 => b,
+        3 => ((a as u16 + b as u16) / 2) as u8,
+        4 => paeth(a, b, c),
+        _ => 0,
+    }
+}
+
+/// Appends the filter type and the filtered row, picking the filter with the
+/// smallest sum of absolute differences like libpng does.
+fn filter_row(row: &[u8], prev: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    let filtered = |kind: u8| -> Vec<u8> {
+        (0..row.len())
+            .map(|i| row[i].wrapping_sub(predict(kind, row, prev, i, bpp)))
+            .collect()
+    };
+    let cost = |r: &[u8]| -> u64 { r.iter().map(|&b| (b as i8).unsigned_abs() as u64).sum() };
+    let (kind, best) = (0..5)
+        .map(|k| (k, filtered(k)))
+        .min_by_key(|(_, r)| cost(r))
+        .unwrap();
+    out.push(kind);
+    out.extend(best);
+}
+
+fn unfilter_row(kind: u8, row: &mut [u8], prev: &[u8], bpp: usize) -> Result<(), String> {
+    if kind > 4 {
+        return Err(format!("invalid filter type {}", kind));
+    }
+    for i in 0..row.len() {
+        row[i] = row[i].wrapping_add(predict(kind, row, prev, i, bpp));
+    }
+    Ok(())
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, String> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "truncated file".to_string())
+}
+
+/// Decodes non-interlaced PNG files of any color type at bit depths 8 and 16.
+pub fn decode(data: &[u8]) -> Result<PngImage, String> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err("not a PNG file".to_string());
+    }
+    let mut pos = SIGNATURE.len();
+    let (mut header, mut palette, mut idat) = (None, Vec::new(), Vec::new());
+    loop {
+        let len = read_u32(data, pos)? as usize;
+        let kind = data.get(pos + 4..pos + 8).ok_or("truncated file")?;
+        let body = data.get(pos + 8..pos + 8 + len).ok_or("truncated chunk")?;
+        if crc32(&data[pos + 4..pos + 8 + len]) != read_u32(data, pos + 8 + len)? {
+            return Err(format!(
+                "bad checksum in {} chunk",
+                String::from_utf8_lossy(kind)
+            ));
+        }
+        match kind {
+            b"IHDR" if body.len() == 13 => header = Some(body.to_vec()),
+            b"PLTE" => palette = body.to_vec(),
+            b"IDAT" => idat.extend(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + len;
+    }
+
+    let header = header.ok_or("missing IHDR chunk")?;
+    let (width, height) = (read_u32(&header, 0)?, read_u32(&header, 4)?);
+    let (depth, color, interlace) = (header[8], header[9], header[12]);
+    let channels = match color {
+        0 => 1,
+        2 => 3,
+        3 => 1,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("invalid color type {}", color)),
+    };
+    if depth != 8 && !(depth == 16 && color != 3) {
+        return Err(format!("unsupported bit depth {}", depth));
+    }
+    if interlace != 0 {
+        return Err("interlaced images are not supported".to_string());
+    }
+
+    let bpp = channels * depth as usize / 8;
+    let stride = width as usize * bpp;
+    let mut raw = zlib::decompress(&idat)?;
+    if raw.len() < (stride + 1) * height as usize {
+        return Err("not enough image data".to_string());
+    }
+    let mut pixels = Vec::with_capacity(stride * height as usize);
+    let mut prev = vec![0; stride];
+    for y in 0..height as usize {
+        let line = &mut raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let (kind, row) = line.split_first_mut().unwrap();
+        unfilter_row(*kind, row, &prev, bpp)?;
+        pixels.extend_from_slice(row);
+        prev.copy_from_slice(row);
+    }
+
+    let samples: Vec<u16> = if depth == 16 {
+        pixels
+            .chunks(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect()
+    } else if color == 3 {
+        let mut rgb = Vec::with_capacity(pixels.len() * 3);
+        for &i in pixels.iter() {
+            let entry = palette
+                .get(i as usize * 3..i as usize * 3 + 3)
+                .ok_or("palette index out of range")?;
+            rgb.extend(entry.iter().map(|&c| c as u16));
+        }
+        rgb
+    } else {
+        pixels.iter().map(|&b| b as u16).collect()
+    };
+
+    Ok(PngImage {
+        width,
+        height,
+        depth,
+        channels: if color == 3 { 3 } else { channels as u8 },
+        samples,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+    }
+
+    #[test]
+    fn test_round_trip_rgb8() {
+        let (w, h) = (19, 7);
+        let pixels: Vec<u8> = (0..w * h * 3).map(|i| (i * 7 % 251) as u8).collect();
+        let png = encode_rgb8(w, h, &pixels);
+        let img = decode(&png).unwrap();
+
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(
+            (img.width, img.height, img.depth, img.channels),
+            (w, h, 8, 3)
+        );
+        assert_eq!(
+            img.samples,
+            pixels.iter().map(|&b| b as u16).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_round_trip_rgb16() {
+        let (w, h) = (5, 4);
+        let pixels: Vec<u16> = (0..w * h * 3).map(|i| (i * 1031) as u16).collect();
+        let img = decode(&encode_rgb16(w, h, &pixels)).unwrap();
+
+        assert_eq!(img.depth, 16);
+        assert_eq!(img.samples, pixels);
+    }
+
+    #[test]
+    fn test_flat_image_compresses() {
+        let pixels = vec![200; 64 * 64 * 3];
+        let png = encode_rgb8(64, 64, &pixels);
+
+        assert!(png.len() < 300);
+        assert_eq!(decode(&png).unwrap().samples.len(), pixels.len());
+    }
+
+    #[test]
+    fn test_rejects_corruption() {
+        let mut png = encode_rgb8(2, 2, &[9; 12]);
+        png[20] ^= 0xff;
+
+        assert_eq!(decode(&png).unwrap_err(), "bad checksum in IHDR chunk");
+        assert!(decode(b"GIF89a").is_err());
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    rt::RayTracer,
+    utils::{
+        stats::{self, RenderStats},
+        vec3::Float,
+    },
+};
+
+use super::{
+    canvas::Canvas,
+    filter::{BoxFilter, Filter},
+    framebuffer::FrameBuffer,
+    sampler::{Grid, Sampler},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Splits the canvas into square tiles and traces them on a pool of threads.
+/// Samples are filtered into a buffer per tile and the buffers are merged in
+/// tile order, so the picture does not depend on the thread count.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    pub threads: usize,
+    pub tile_size: u32,
+    // rays per pixel
+    pub samples: usize,
+    pub sampler: Arc<dyn Sampler>,
+    // how samples are weighted into the pixels around them
+    pub filter: Arc<dyn Filter>,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(threads)
+    }
+}
+
+impl Renderer {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            tile_size: 32,
+            samples: 1,
+            sampler: Arc::new(Grid),
+            filter: Arc::new(BoxFilter::default()),
+        }
+    }
+
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    pub fn sampler(mut self, sampler: impl Sampler + 'static) -> Self {
+        self.sampler = Arc::new(sampler);
+        self
+    }
+
+    pub fn filter(mut self, filter: impl Filter + 'static) -> Self {
+        self.filter = Arc::new(filter);
+        self
+    }
+
+    pub fn tile_size(mut self, size: u32) -> Self {
+        self.tile_size = size.max(1);
+        self
+    }
+
+    pub fn tiles(&self, width: u32, height: u32) -> Vec<Tile> {
+        let s = self.tile_size.max(1);
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(s as usize) {
+            for x in (0..width).step_by(s as usize) {
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: s.min(width - x),
+                    height: s.min(height - y),
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Renders the whole canvas through `rt.output`.
+    pub fn render(&self, rt: &RayTracer, canvas: &mut Canvas) -> RenderStats {
+        let mut fb = FrameBuffer::new(canvas.width, canvas.height);
+        let stats = self.render_hdr(rt, &mut fb);
+        fb.tone_map_into(&rt.output, canvas);
+        stats
+    }
+
+    /// Renders linear radiance into `fb`, replacing what it held.
+    pub fn render_hdr(&self, rt: &RayTracer, fb: &mut FrameBuffer) -> RenderStats {
+        let start = Instant::now();
+        let mut stats = self.render_tiles(rt, fb);
+        stats.elapsed = start.elapsed();
+        stats
+    }
+
+    // the counts come back with the tiles, so renders running side by side
+    // keep their own
+    fn render_tiles(&self, rt: &RayTracer, fb: &mut FrameBuffer) -> RenderStats {
+        fb.clear();
+        let tiles = self.tiles(fb.width, fb.height);
+        let margin = (self.filter.radius() + 0.5).ceil() as i64;
+        let mut stats = RenderStats::default();
+        let mut merge =
+            |fb: &mut FrameBuffer, tile: &Tile, (splats, counts): (FrameBuffer, RenderStats)| {
+                fb.merge(&splats, tile.x as i64 - margin, tile.y as i64 - margin);
+                stats.add_counts(&counts);
+            };
+        if self.threads == 1 {
+            for tile in tiles.iter() {
+                merge(fb, tile, self.render_tile(rt, tile, margin));
+            }
+            return stats;
+        }
+
+        let next = AtomicUsize::new(0);
+        let (tx, rx) = mpsc::channel();
+        thread::scope(|s| {
+            for _ in 0..self.threads.min(tiles.len()) {
+                let tx = tx.clone();
+                let (tiles, next) = (&tiles, &next);
+                s.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(tile) = tiles.get(i) else {
+                        break;
+                    };
+                    if tx.send((i, self.render_tile(rt, tile, margin))).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+            // tiles overlap once filtered, merging them in order keeps the
+            // float sums identical whatever the thread count
+            let mut pending = BTreeMap::new();
+            let mut merged = 0;
+            for (i, splats) in rx {
+                pending.insert(i, splats);
+                while let Some(rendered) = pending.remove(&merged) {
+                    merge(fb, &tiles[merged], rendered);
+                    merged += 1;
+                }
+            }
+        });
+        stats
+    }
+
+    /// Samples of one tile splatted into a buffer that extends `margin`
+    /// pixels past the tile on every side, and what tracing them cost.
+    fn render_tile(&self, rt: &RayTracer, tile: &Tile, margin: i64) -> (FrameBuffer, RenderStats) {
+        // drop whatever the thread traced before this tile
+        stats::take();
+        let m = margin as u32;
+        let mut splats = FrameBuffer::new(tile.width + 2 * m, tile.height + 2 * m);
+        let filter = self.filter.as_ref();
+        // the samples split the pixel between them, each covers less of it
+        let footprint = 1.0 / (self.samples as Float).sqrt();
+        for y in tile.y..tile.y + tile.height {
+            for x in tile.x..tile.x + tile.width {
+                let pixel = self.sampler.samples(x, y, self.samples);
+                let lens = self.sampler.lens_samples(x, y, self.samples);
+                for ((dx, dy), uv) in pixel.into_iter().zip(lens) {
+                    let mut ray =
+                        rt.world
+                            .camera
+                            .get_lens_ray(x as Float + dx, y as Float + dy, uv.0, uv.1);
+                    ray.scale_differentials(footprint);
+                    let color = rt.trace(&ray, 0);
+                    let sx = (x - tile.x + m) as Float + dx;
+                    let sy = (y - tile.y + m) as Float + dy;
+                    splats.splat(sx, sy, &color, filter);
+                }
+            }
+        }
+        (splats, stats::take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        scene::{filter::Mitchell, sampler::Jittered},
+        utils::{material::IMaterial, matrix::Mat, vec3::Vec3},
+        world::{
+            camera::Camera,
+            light::{Light, PointLight},
+            shapes::{plane::Plane, shape::Shape, sphere::Sphere},
+            transform::Transformable,
+            w::World,
+        },
+    };
+
+    fn tracer(width: u32, height: u32) -> RayTracer {
+        let camera = Camera::new(
+            width,
+            height,
+            1.0,
+            Mat::view_transformation(
+                &Vec3::new(0.0, 1.5, -5.0),
+                &Vec3::new(0.0, 1.0, 0.0),
+                &Vec3::new(0.0, 1.0, 0.0),
+            ),
+        );
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            Vec3::new(-10.0, 10.0, -10.0),
+            Vec3::from_float(1.0),
+        ))];
+        let shapes: Vec<Box<dyn Shape>> = vec![
+            Box::new(Plane::default().reflective(0.3)),
+            Box::new(
+                Sphere::default()
+                    .color(1.0, 0.2, 0.2)
+                    .transparency(0.5)
+                    .refractive_index(1.5)
+                    .translation(0.0, 1.0, 0.0),
+            ),
+        ];
+        RayTracer::new(World::new(camera, lights, shapes))
+    }
+
+    #[test]
+    fn test_tiles_cover_canvas() {
+        let tiles = Renderer::new(1).tile_size(16).tiles(40, 20);
+        let area: u32 = tiles.iter().map(|t| t.width * t.height).sum();
+
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(area, 800);
+        assert_eq!(
+            tiles[2],
+            Tile {
+                x: 32,
+                y: 0,
+                width: 8,
+                height: 16
+            }
+        );
+    }
+
+    #[test]
+    fn test_threads_match_single_thread() {
+        let rt = tracer(37, 23);
+        let mut single = Canvas::new(37, 23);
+        single.for_each(|pixel, x, y| rt.color_at_offset(x, y, 0.5, 0.5).apply(pixel));
+        for threads in [1, 2, 5] {
+            let mut canvas = Canvas::new(37, 23);
+            Renderer::new(threads).tile_size(8).render(&rt, &mut canvas);
+            assert_eq!(canvas.as_rgba8(), single.as_rgba8());
+        }
+    }
+
+    #[test]
+    fn test_hdr_keeps_radiance() {
+        let mut rt = tracer(9, 9);
+        rt.world.lights = vec![Box::new(PointLight::new(
+            Vec3::new(-10.0, 10.0, -10.0),
+            Vec3::from_float(4.0),
+        ))];
+        let mut fb = FrameBuffer::new(9, 9);
+        Renderer::new(2).tile_size(4).render_hdr(&rt, &mut fb);
+
+        assert_eq!(fb.pixel(4, 4), rt.color_at_offset(4, 4, 0.5, 0.5));
+        assert!(fb.pixel(4, 4).x > 1.0);
+    }
+
+    #[test]
+    fn test_filters_match_across_threads() {
+        let rt = tracer(20, 14);
+        let render = |threads: usize, filter: Mitchell| {
+            let mut fb = FrameBuffer::new(20, 14);
+            Renderer::new(threads)
+                .samples(2)
+                .sampler(Jittered { seed: 1 })
+                .filter(filter)
+                .tile_size(6)
+                .render_hdr(&rt, &mut fb);
+            fb
+        };
+        let a = render(1, Mitchell::default());
+        let b = render(4, Mitchell::default());
+        let narrow = render(
+            1,
+            Mitchell {
+                radius: 0.5,
+                ..Default::default()
+            },
+        );
+
+        for (x, y) in [(0, 0), (7, 5), (19, 13), (12, 6)] {
+            assert_eq!(a.pixel(x, y).x.to_bits(), b.pixel(x, y).x.to_bits());
+        }
+        // a wider filter blurs the edges of the sphere
+        let diff = |f: &FrameBuffer| (f.pixel(10, 6) - f.pixel(11, 6)).mag();
+        assert!(diff(&a) != diff(&narrow));
+    }
+
+    #[test]
+    fn test_depth_of_field() {
+        let mut rt = tracer(24, 24);
+        let render = |rt: &RayTracer| {
+            let mut fb = FrameBuffer::new(24, 24);
+            Renderer::new(2)
+                .samples(16)
+                .sampler(Jittered { seed: 2 })
+                .render_hdr(rt, &mut fb);
+            fb
+        };
+        let sharp = render(&rt);
+        rt.world.camera.aperture_radius = 0.3;
+        // the sphere sits about 5 units away, focusing there keeps the
+        // middle row close to the pinhole render
+        rt.world.camera.focal_distance = 5.0;
+        let focused = render(&rt);
+        rt.world.camera.focal_distance = 1.5;
+        let blurred = render(&rt);
+
+        let error = |f: &FrameBuffer| {
+            (0..24)
+                .map(|x| (f.pixel(x, 12) - sharp.pixel(x, 12)).mag())
+                .sum::<Float>()
+        };
+        assert!(error(&focused) < error(&blurred));
+    }
+
+    #[test]
+    fn test_samples_match_across_threads() {
+        let rt = tracer(16, 16);
+        let mut a = Canvas::new(16, 16);
+        let mut b = Canvas::new(16, 16);
+        let sampler = Jittered { seed: 5 };
+        let stats = Renderer::new(1)
+            .samples(4)
+            .sampler(sampler)
+            .render(&rt, &mut a);
+        let threaded = Renderer::new(3)
+            .samples(4)
+            .sampler(sampler)
+            .tile_size(5)
+            .render(&rt, &mut b);
+
+        assert_eq!(a.as_rgba8(), b.as_rgba8());
+        assert!(stats.rays >= 16 * 16 * 4);
+        assert!(stats.intersection_tests > 0);
+        assert_eq!(threaded.rays, stats.rays);
+        assert_eq!(threaded.intersection_tests, stats.intersection_tests);
+    }
+
+    #[test]
+    fn test_concurrent_renders_count_apart() {
+        let rt = tracer(16, 16);
+        let alone = Renderer::new(2).render(&rt, &mut Canvas::new(16, 16));
+        let (a, b) = thread::scope(|s| {
+            let a = s.spawn(|| Renderer::new(2).render(&rt, &mut Canvas::new(16, 16)));
+            let b = s.spawn(|| {
+                Renderer::new(2)
+                    .samples(4)
+                    .render(&rt, &mut Canvas::new(16, 16))
+            });
+            (a.join().unwrap(), b.join().unwrap())
+        });
+
+        assert_eq!(a.rays, alone.rays);
+        assert_eq!(a.intersection_tests, alone.intersection_tests);
+        assert!(b.rays >= 4 * 16 * 16 && b.rays > a.rays);
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt::Debug;
+
+use crate::utils::{rng::Rng, vec3::Float};
+
+/// Picks where inside a pixel the camera rays go. Offsets are in `[0, 1)`
+/// from the pixel corner and only depend on the pixel and the seed, so a
+/// render comes out the same on any number of threads.
+pub trait Sampler: Debug + Send + Sync {
+    fn samples(&self, x: u32, y: u32, n: usize) -> Vec<(Float, Float)>;
+
+    /// A second set of `n` points for the camera lens. The pixel points are
+    /// shuffled so the two sets stay well spread but do not pair up along
+    /// the same pattern.
+    fn lens_samples(&self, x: u32, y: u32, n: usize) -> Vec<(Float, Float)> {
+        let mut points = self.samples(x, y, n);
+        let mut rng = Rng::for_pixel(LENS_SEED, x, y);
+        for i in (1..points.len()).rev() {
+            points.swap(i, rng.next_u64() as usize % (i + 1));
+        }
+        points
+    }
+}
+
+const LENS_SEED: u64 = 0x6c65_6e73;
+
+// corner and size of the cell of sample `i` out of `n`. Rows hold ⌈√n⌉
+// cells, the last one whatever is left in wider cells, and each row is as
+// tall as its share of the samples so every cell covers 1/n of the pixel.
+fn cell(i: usize, n: usize) -> (Float, Float, Float, Float) {
+    let cols = (n as Float).sqrt().ceil() as usize;
+    let first = i / cols * cols;
+    let count = cols.min(n - first);
+    let (w, h) = (1.0 / count as Float, count as Float / n as Float);
+    ((i - first) as Float * w, first as Float / n as Float, w, h)
+}
+
+/// Cell centers, the same for every pixel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Grid;
+
+impl Sampler for Grid {
+    fn samples(&self, _x: u32, _y: u32, n: usize) -> Vec<(Float, Float)> {
+        (0..n)
+            .map(|i| {
+                let (x0, y0, w, h) = cell(i, n);
+                (x0 + 0.5 * w, y0 + 0.5 * h)
+            })
+            .collect()
+    }
+}
+
+/// One random point in each cell of the grid.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Jittered {
+    pub seed: u64,
+}
+
+impl Sampler for Jittered {
+    fn samples(&self, x: u32, y: u32, n: usize) -> Vec<(Float, Float)> {
+        let mut rng = Rng::for_pixel(self.seed, x, y);
+        (0..n)
+            .map(|i| {
+                let (x0, y0, w, h) = cell(i, n);
+                (x0 + rng.next_float() * w, y0 + rng.next_float() * h)
+            })
+            .collect()
+    }
+}
+
+fn radical_inverse(base: u32, mut i: u32) -> Float {
+    let inv = 1.0 / base as f64;
+    let (mut result, mut f) = (0.0f64, inv);
+    while i > 0 {
+        result += (i % base) as f64 * f;
+        i /= base;
+        f *= inv;
+    }
+    result as Float
+}
+
+// Cranley-Patterson rotation, shifts a point set around the unit torus so
+// neighbouring pixels do not share the same pattern
+fn rotate(v: Float, shift: Float) -> Float {
+    let r = v + shift;
+    let r = if r >= 1.0 { r - 1.0 } else { r };
+    // f32 rounding can land exactly on 1.0
+    r.min(1.0 - Float::EPSILON)
+}
+
+/// Halton points in bases 2 and 3, randomly rotated per pixel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Halton {
+    pub seed: u64,
+}
+
+impl Sampler for Halton {
+    fn samples(&self, x: u32, y: u32, n: usize) -> Vec<(Float, Float)> {
+        let mut rng = Rng::for_pixel(self.seed, x, y);
+        let (sx, sy) = (rng.next_float(), rng.next_float());
+        (0..n as u32)
+            .map(|i| {
+                (
+                    rotate(radical_inverse(2, i), sx),
+                    rotate(radical_inverse(3, i), sy),
+                )
+            })
+            .collect()
+    }
+}
+
+/// First two dimensions of the Sobol sequence with a random digital shift
+/// per pixel, which keeps the points stratified.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sobol {
+    pub seed: u64,
+}
+
+impl Sobol {
+    fn point(i: u32) -> (u32, u32) {
+        // dimension 0 is the bit reversed index, dimension 1 uses the
+        // direction numbers of the polynomial x + 1
+        let (mut y, mut v) = (0u32, 1u32 << 31);
+        let mut bits = i;
+        while bits != 0 {
+            if bits & 1 != 0 {
+                y ^= v;
+            }
+            v ^= v >> 1;
+            bits >>= 1;
+        }
+        (i.reverse_bits(), y)
+    }
+}
+
+impl Sampler for Sobol {
+    fn samples(&self, x: u32, y: u32, n: usize) -> Vec<(Float, Float)> {
+        let mut rng = Rng::for_pixel(self.seed, x, y);
+        let (sx, sy) = (rng.next_u32(), rng.next_u32());
+        let to_float = |v: u32| (v >> 8) as Float / (1u32 << 24) as Float;
+        (0..n as u32)
+            .map(|i| {
+                let (a, b) = Self::point(i);
+                (to_float(a ^ sx), to_float(b ^ sy))
+            })
+            .collect()
+    }
+}
+
+/// Sampler from its name, randomized ones start from `seed`.
+pub fn parse(name: &str, seed: u64) -> Result<Box<dyn Sampler>, String> {
+    let sampler: Box<dyn Sampler> = match name {
+        "grid" => Box::new(Grid),
+        "jittered" => Box::new(Jittered { seed }),
+        "halton" => Box::new(Halton { seed }),
+        "sobol" => Box::new(Sobol { seed }),
+        _ => return Err(format!("unknown sampler `{}`", name)),
+    };
+    Ok(sampler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> Vec<Box<dyn Sampler>> {
+        ["grid", "jittered", "halton", "sobol"]
+            .iter()
+            .map(|s| parse(s, 9).unwrap())
+            .collect()
+    }
+
+    // every cell of a k x k grid gets exactly one point
+    fn stratified(points: &[(Float, Float)], k: usize) -> bool {
+        let mut cells = vec![0; k * k];
+        for (x, y) in points {
+            cells[(y * k as Float) as usize * k + (x * k as Float) as usize] += 1;
+        }
+        cells.iter().all(|&c| c == 1)
+    }
+
+    #[test]
+    fn test_grid() {
+        assert_eq!(Grid.samples(3, 4, 1), vec![(0.5, 0.5)]);
+        assert_eq!(
+            Grid.samples(0, 0, 4),
+            vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+        );
+        assert_eq!(Grid.samples(0, 0, 3).len(), 3);
+    }
+
+    #[test]
+    fn test_grid_centered() {
+        for n in 1..=20 {
+            let points = Grid.samples(0, 0, n);
+            let mean =
+                |f: fn(&(Float, Float)) -> Float| points.iter().map(f).sum::<Float>() / n as Float;
+            assert!((mean(|p| p.0) - 0.5).abs() < 1e-5, "{}", n);
+            assert!((mean(|p| p.1) - 0.5).abs() < 1e-5, "{}", n);
+        }
+        // the odd sample out gets the bottom row to itself
+        let three = Grid.samples(0, 0, 3);
+        assert_eq!((three[0].0, three[1].0, three[2].0), (0.25, 0.75, 0.5));
+        assert!((three[2].1 - 5.0 / 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_in_unit_square_and_deterministic() {
+        for s in all() {
+            for n in [1, 5, 16] {
+                let a = s.samples(7, 11, n);
+                assert_eq!(a.len(), n);
+                assert!(a
+                    .iter()
+                    .all(|(x, y)| (0.0..1.0).contains(x) && (0.0..1.0).contains(y)));
+                assert_eq!(a, s.samples(7, 11, n));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stratification() {
+        for name in ["grid", "jittered", "sobol"] {
+            let s = parse(name, 3).unwrap();
+            assert!(stratified(&s.samples(2, 5, 16), 4), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_pixels_differ() {
+        for s in &all()[1..] {
+            assert_ne!(s.samples(0, 0, 4), s.samples(1, 0, 4), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_lens_samples_are_a_permutation() {
+        for s in all() {
+            let mut pixel = s.samples(4, 2, 9);
+            let mut lens = s.lens_samples(4, 2, 9);
+            assert_eq!(lens, s.lens_samples(4, 2, 9));
+
+            let key = |p: &(Float, Float)| (p.0.to_bits(), p.1.to_bits());
+            pixel.sort_by_key(key);
+            lens.sort_by_key(key);
+            assert_eq!(pixel, lens);
+        }
+        assert_ne!(
+            Sobol { seed: 0 }.samples(0, 0, 16),
+            Sobol { seed: 0 }.lens_samples(0, 0, 16)
+        );
+    }
+
+    #[test]
+    fn test_low_discrepancy_values() {
+        assert_eq!(radical_inverse(2, 3), 0.75);
+        assert_eq!(radical_inverse(3, 5), 7.0 / 9.0);
+        let points: Vec<(u32, u32)> = (0..4).map(Sobol::point).collect();
+        assert_eq!(
+            points,
+            vec![
+                (0, 0),
+                (1 << 31, 1 << 31),
+                (1 << 30, 3 << 30),
+                (3 << 30, 1 << 30)
+            ]
+        );
+    }
+}
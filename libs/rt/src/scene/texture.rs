@@ -0,0 +1,501 @@
+use std::{fs, io, path::Path};
+
+use crate::utils::vec3::{Float, Vec3};
+
+use super::{png, tonemap::srgb_decode};
+
+/// How texels are combined between their centers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/// What lookups outside of `[0, 1)` land on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Wrap {
+    #[default]
+    Repeat,
+    /// Stretches the border texels.
+    Clamp,
+    /// Repeats, flipping every other copy so the seams match up.
+    Mirror,
+}
+
+impl Wrap {
+    // texel index `i` brought back into `0..n`
+    fn texel(self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::Clamp => i.clamp(0, n - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                if i < n {
+                    i
+                } else {
+                    2 * n - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+/// Image held in memory as colors, sampled with `u` going right and `v`
+/// going up from the bottom left corner.
+#[derive(Debug, Clone)]
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    // rows from the top, like the files store them
+    pixels: Vec<Vec3>,
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+impl Texture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>) -> Self {
+        assert_eq!(pixels.len(), width * height, "pixel buffer size");
+        assert!(width > 0 && height > 0, "empty texture");
+        Self {
+            width,
+            height,
+            pixels,
+            filter: Filter::default(),
+            wrap: Wrap::default(),
+        }
+    }
+
+    /// Reads a PNG or PPM file, told apart by their first bytes.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let texture = if data.starts_with(b"\x89PNG") {
+            Self::from_png(&data)
+        } else {
+            Self::from_ppm(&data)
+        };
+        texture.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Plain (`P3`) or binary (`P6`) PPM, up to 16 bits per sample.
+    pub fn from_ppm(data: &[u8]) -> Result<Self, String> {
+        let mut pos = 0;
+        // whitespace separated header fields, `#` comments run to the line end
+        let field = |pos: &mut usize| -> Result<String, String> {
+            loop {
+                match data.get(*pos) {
+                    Some(b'#') => {
+                        while data.get(*pos).is_some_and(|&c| c != b'\n') {
+                            *pos += 1;
+                        }
+                    }
+                    Some(c) if c.is_ascii_whitespace() => *pos += 1,
+                    Some(_) => break,
+                    None => return Err("truncated file".to_string()),
+                }
+            }
+            let start = *pos;
+            while data.get(*pos).is_some_and(|c| !c.is_ascii_whitespace()) {
+                *pos += 1;
+            }
+            Ok(String::from_utf8_lossy(&data[start..*pos]).into_owned())
+        };
+        let magic = field(&mut pos)?;
+        if magic != "P3" && magic != "P6" {
+            return Err("not a PPM file".to_string());
+        }
+        let number = |pos: &mut usize| -> Result<usize, String> {
+            let f = field(pos)?;
+            f.parse().map_err(|_| format!("invalid number `{}`", f))
+        };
+        let (width, height, max) = (number(&mut pos)?, number(&mut pos)?, number(&mut pos)?);
+        if width == 0 || height == 0 {
+            return Err("empty image".to_string());
+        }
+        if max == 0 || max > 65535 {
+            return Err(format!("invalid maximum value {}", max));
+        }
+
+        let count = width * height * 3;
+        let samples: Vec<usize> = if magic == "P3" {
+            (0..count)
+                .map(|_| number(&mut pos))
+                .collect::<Result<_, _>>()?
+        } else {
+            // a single whitespace byte separates the header from the data
+            let body = data.get(pos + 1..).unwrap_or(&[]);
+            let size = if max < 256 { 1 } else { 2 };
+            if body.len() < count * size {
+                return Err("not enough image data".to_string());
+            }
+            body.chunks(size)
+                .take(count)
+                .map(|b| b.iter().fold(0, |v, &c| v << 8 | c as usize))
+                .collect()
+        };
+        let scale = 1.0 / max as Float;
+        let pixels = samples
+            .chunks(3)
+            .map(|c| {
+                Vec3::new(
+                    c[0].min(max) as Float * scale,
+                    c[1].min(max) as Float * scale,
+                    c[2].min(max) as Float * scale,
+                )
+            })
+            .collect();
+        Ok(Self::new(width, height, pixels))
+    }
+
+    /// Any PNG `png::decode` reads, gray is spread over the three channels
+    /// and alpha is dropped.
+    pub fn from_png(data: &[u8]) -> Result<Self, String> {
+        let img = png::decode(data)?;
+        let scale = 1.0 / ((1u32 << img.depth) - 1) as Float;
+        let channels = img.channels as usize;
+        let pixels = img
+            .samples
+            .chunks(channels)
+            .map(|c| {
+                let s = |i: usize| c[i] as Float * scale;
+                if channels < 3 {
+                    Vec3::from_float(s(0))
+                } else {
+                    Vec3::new(s(0), s(1), s(2))
+                }
+            })
+            .collect();
+        Ok(Self::new(img.width as usize, img.height as usize, pixels))
+    }
+
+    /// Converts sRGB encoded pixels, as most photos and paintings are, to
+    /// linear ones.
+    pub fn srgb(mut self) -> Self {
+        for p in self.pixels.iter_mut() {
+            *p = Vec3::new(srgb_decode(p.x), srgb_decode(p.y), srgb_decode(p.z));
+        }
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Texel `x` columns from the left and `y` rows from the top, wrapped.
+    pub fn texel(&self, x: i64, y: i64) -> &Vec3 {
+        let x = self.wrap.texel(x, self.width);
+        let y = self.wrap.texel(y, self.height);
+        &self.pixels[y * self.width + x]
+    }
+
+    pub fn sample(&self, u: Float, v: Float) -> Vec3 {
+        let x = u * self.width as Float;
+        let y = (1.0 - v) * self.height as Float;
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64).clone(),
+            Filter::Bilinear => {
+                // texel centers sit on half integers
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x0 + 1, y0) * fx;
+                let bottom = self.texel(x0, y0 + 1) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+}
+
+/// Texture with successively halved copies of itself, so a lookup covering
+/// many texels reads a few from a smaller copy instead of aliasing.
+#[derive(Debug, Clone)]
+pub struct MipMap {
+    levels: Vec<Texture>,
+    /// Most samples taken along a stretched footprint, seen at a grazing
+    /// angle. 1 blurs such footprints along their short side too.
+    pub max_anisotropy: Float,
+}
+
+impl MipMap {
+    /// Builds the pyramid down to a single texel, each level a 2 by 2 box
+    /// filter of the one above.
+    pub fn new(texture: Texture) -> Self {
+        let mut levels = vec![texture];
+        loop {
+            let t = levels.last().unwrap();
+            if t.width == 1 && t.height == 1 {
+                break;
+            }
+            let (w, h) = ((t.width / 2).max(1), (t.height / 2).max(1));
+            let mut pixels = Vec::with_capacity(w * h);
+            for y in 0..h {
+                for x in 0..w {
+                    // odd sizes fold their last row or column into the one
+                    // before
+                    let xs = [2 * x, (2 * x + 1).min(t.width - 1)];
+                    let ys = [2 * y, (2 * y + 1).min(t.height - 1)];
+                    let mut sum = Vec3::zero();
+                    for &ty in ys.iter() {
+                        for &tx in xs.iter() {
+                            sum = sum + &t.pixels[ty * t.width + tx];
+                        }
+                    }
+                    pixels.push(sum * 0.25);
+                }
+            }
+            let next = Texture::new(w, h, pixels).filter(t.filter).wrap(t.wrap);
+            levels.push(next);
+        }
+        Self {
+            levels,
+            max_anisotropy: 8.0,
+        }
+    }
+
+    /// Only the full size texture, every lookup reads it directly.
+    pub fn single(texture: Texture) -> Self {
+        Self {
+            levels: vec![texture],
+            max_anisotropy: 8.0,
+        }
+    }
+
+    pub fn levels(&self) -> &[Texture] {
+        &self.levels
+    }
+
+    pub fn sample(&self, u: Float, v: Float) -> Vec3 {
+        self.levels[0].sample(u, v)
+    }
+
+    // blend of the two levels around `lod`
+    fn trilinear(&self, u: Float, v: Float, lod: Float) -> Vec3 {
+        let top = (self.levels.len() - 1) as Float;
+        let lod = lod.clamp(0.0, top);
+        let l0 = lod.floor();
+        let f = lod - l0;
+        let a = self.levels[l0 as usize].sample(u, v);
+        if f == 0.0 {
+            return a;
+        }
+        let b = self.levels[l0 as usize + 1].sample(u, v);
+        a * (1.0 - f) + b * f
+    }
+
+    /// Average over the parallelogram spanned by the texture space steps
+    /// `dx` and `dy` around `(u, v)`. The level is picked by the short
+    /// side, and samples are spread along the long one.
+    pub fn sample_footprint(
+        &self,
+        u: Float,
+        v: Float,
+        dx: (Float, Float),
+        dy: (Float, Float),
+    ) -> Vec3 {
+        let base = &self.levels[0];
+        let (w, h) = (base.width as Float, base.height as Float);
+        let len = |d: (Float, Float)| ((d.0 * w).powi(2) + (d.1 * h).powi(2)).sqrt();
+        let (lx, ly) = (len(dx), len(dy));
+        let (major, minor, axis) = if lx >= ly { (lx, ly, dx) } else { (ly, lx, dy) };
+        if self.levels.len() == 1 || !major.is_finite() || major <= 1.0 {
+            return self.sample(u, v);
+        }
+        let anisotropy = self.max_anisotropy.max(1.0);
+        let minor = minor.max(major / anisotropy);
+        let n = (major / minor).ceil() as usize;
+        let lod = minor.log2();
+        let mut sum = Vec3::zero();
+        for i in 0..n {
+            let t = (i as Float + 0.5) / n as Float - 0.5;
+            sum = sum + self.trilinear(u + axis.0 * t, v + axis.1 * t, lod);
+        }
+        sum / n as Float
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x2: red, green over blue, white
+    fn quad() -> Texture {
+        Texture::new(
+            2,
+            2,
+            vec![
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::from_float(1.0),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_ppm() {
+        let t = Texture::from_ppm(b"P3\n# a comment\n2 1\n10\n10 0 0  0 5 10\n").unwrap();
+        assert_eq!((t.width, t.height), (2, 1));
+        assert_eq!(t.texel(0, 0), &Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(t.texel(1, 0), &Vec3::new(0.0, 0.5, 1.0));
+
+        let mut p6 = b"P6 1 2 255\n".to_vec();
+        p6.extend([255, 0, 51, 0, 255, 0]);
+        let t = Texture::from_ppm(&p6).unwrap();
+        assert_eq!(t.texel(0, 0), &Vec3::new(1.0, 0.0, 0.2));
+        assert_eq!(t.texel(0, 1), &Vec3::new(0.0, 1.0, 0.0));
+
+        let mut wide = b"P6 1 1 65535\n".to_vec();
+        wide.extend([0xff, 0xff, 0, 0, 0x80, 0]);
+        let t = Texture::from_ppm(&wide).unwrap();
+        assert_eq!(t.texel(0, 0), &Vec3::new(1.0, 0.0, 0.50001));
+
+        assert_eq!(
+            Texture::from_ppm(b"P5 1 1 255\n\0").unwrap_err(),
+            "not a PPM file"
+        );
+        assert_eq!(
+            Texture::from_ppm(b"P6 2 2 255\n\0\0\0").unwrap_err(),
+            "not enough image data"
+        );
+        assert_eq!(
+            Texture::from_ppm(b"P3 1 1 255 1 x 1").unwrap_err(),
+            "invalid number `x`"
+        );
+    }
+
+    #[test]
+    fn test_png() {
+        let png = png::encode_rgb8(2, 1, &[255, 0, 0, 0, 51, 255]);
+        let t = Texture::from_png(&png).unwrap();
+        assert_eq!(t.texel(0, 0), &Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(t.texel(1, 0), &Vec3::new(0.0, 0.2, 1.0));
+
+        let t = Texture::from_png(&png).unwrap().srgb();
+        assert_eq!(t.texel(1, 0), &Vec3::new(0.0, srgb_decode(0.2), 1.0));
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("rt_texture_{}.ppm", std::process::id()));
+        fs::write(&path, b"P3 1 1 255 0 255 0").unwrap();
+        let t = Texture::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(t.unwrap().texel(0, 0), &Vec3::new(0.0, 1.0, 0.0));
+
+        let e = Texture::load("does/not/exist.png").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_nearest() {
+        let t = quad().filter(Filter::Nearest);
+        // v goes up, so the top row is at v near 1
+        assert_eq!(t.sample(0.1, 0.9), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(t.sample(0.6, 0.9), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(t.sample(0.1, 0.1), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(t.sample(0.6, 0.1), Vec3::from_float(1.0));
+    }
+
+    #[test]
+    fn test_bilinear() {
+        let t = quad().wrap(Wrap::Clamp);
+        assert_eq!(t.sample(0.25, 0.75), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(t.sample(0.5, 0.75), Vec3::new(0.5, 0.5, 0.0));
+        assert_eq!(t.sample(0.5, 0.5), Vec3::from_float(0.5));
+        // clamped borders keep the edge texel
+        assert_eq!(t.sample(0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        // repeating blends with the opposite edge instead
+        assert_eq!(quad().sample(0.0, 0.75), Vec3::new(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(Wrap::Repeat.texel(-1, 4), 3);
+        assert_eq!(Wrap::Repeat.texel(5, 4), 1);
+        assert_eq!(Wrap::Clamp.texel(-3, 4), 0);
+        assert_eq!(Wrap::Clamp.texel(9, 4), 3);
+        assert_eq!(Wrap::Mirror.texel(4, 4), 3);
+        assert_eq!(Wrap::Mirror.texel(-1, 4), 0);
+        assert_eq!(Wrap::Mirror.texel(9, 4), 1);
+
+        let t = quad().filter(Filter::Nearest).wrap(Wrap::Mirror);
+        assert_eq!(t.sample(1.1, 0.9), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(t.sample(2.1, 0.9), Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    // 4x4 of alternating black and white columns
+    fn columns() -> Texture {
+        let pixels = (0..16)
+            .map(|i| Vec3::from_float((i % 2) as Float))
+            .collect();
+        Texture::new(4, 4, pixels)
+    }
+
+    #[test]
+    fn test_mip_pyramid() {
+        let mips = MipMap::new(Texture::new(
+            5,
+            2,
+            (0..10).map(|i| Vec3::from_float(i as Float)).collect(),
+        ));
+        let sizes: Vec<(usize, usize)> =
+            mips.levels().iter().map(|t| (t.width, t.height)).collect();
+        assert_eq!(sizes, vec![(5, 2), (2, 1), (1, 1)]);
+        // (0 + 1 + 5 + 6) / 4 and (2 + 3 + 7 + 8) / 4
+        assert_eq!(mips.levels()[1].texel(0, 0), &Vec3::from_float(3.0));
+        assert_eq!(mips.levels()[1].texel(1, 0), &Vec3::from_float(5.0));
+        assert_eq!(mips.levels()[2].texel(0, 0), &Vec3::from_float(4.0));
+    }
+
+    #[test]
+    fn test_footprint_levels() {
+        let t = columns().filter(Filter::Nearest);
+        let mips = MipMap::new(t.clone());
+        // a footprint under a texel reads the full texture
+        let small = (0.1 / 4.0, 0.0);
+        assert_eq!(
+            mips.sample_footprint(0.3, 0.3, small, (0.0, 0.1 / 4.0)),
+            t.sample(0.3, 0.3)
+        );
+        // two texels wide averages the columns
+        let wide = (2.0 / 4.0, 0.0);
+        assert_eq!(
+            mips.sample_footprint(0.3, 0.3, wide, (0.0, 2.0 / 4.0)),
+            Vec3::from_float(0.5)
+        );
+        // without a pyramid nothing is blurred
+        assert_eq!(
+            MipMap::single(t.clone()).sample_footprint(0.3, 0.3, wide, (0.0, 0.5)),
+            t.sample(0.3, 0.3)
+        );
+    }
+
+    #[test]
+    fn test_anisotropic_footprint() {
+        let t = columns().filter(Filter::Nearest);
+        // stretched along v, where nothing changes, and thin along u
+        let (dx, dy) = ((0.2 / 4.0, 0.0), (0.0, 4.0 / 4.0));
+        let mut mips = MipMap::new(t.clone());
+        assert_eq!(mips.sample_footprint(0.1, 0.5, dx, dy), Vec3::zero());
+        assert_eq!(
+            mips.sample_footprint(0.3, 0.5, dx, dy),
+            Vec3::from_float(1.0)
+        );
+
+        // isotropic filtering blurs the columns away
+        mips.max_anisotropy = 1.0;
+        assert_eq!(
+            mips.sample_footprint(0.1, 0.5, dx, dy),
+            Vec3::from_float(0.5)
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use crate::utils::vec3::{Float, Vec3};
+
+/// Turns scene radiance into display values in `[0, 1]`, see
+/// `FrameBuffer::to_canvas`. Results are still linear, `Transfer` encodes
+/// them for the screen.
+pub trait ToneMap: Send + Sync {
+    fn map(&self, c: &Vec3) -> Vec3;
+}
+
+fn per_channel(c: &Vec3, f: impl Fn(Float) -> Float) -> Vec3 {
+    Vec3::new(f(c.x), f(c.y), f(c.z))
+}
+
+/// Cuts everything above 1.0, this is what `Canvas` always did.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Clamp;
+
+impl ToneMap for Clamp {
+    fn map(&self, c: &Vec3) -> Vec3 {
+        per_channel(c, |v| v.clamp(0.0, 1.0))
+    }
+}
+
+/// `1 - e^(-exposure * c)`, brighter scenes want a smaller exposure.
+#[derive(Debug, Clone, Copy)]
+pub struct Exposure {
+    pub exposure: Float,
+}
+
+impl ToneMap for Exposure {
+    fn map(&self, c: &Vec3) -> Vec3 {
+        per_channel(c, |v| 1.0 - (-self.exposure * v.max(0.0)).exp())
+    }
+}
+
+/// `c / (1 + c)`, never reaches white.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reinhard;
+
+impl ToneMap for Reinhard {
+    fn map(&self, c: &Vec3) -> Vec3 {
+        per_channel(c, |v| {
+            let v = v.max(0.0);
+            v / (1.0 + v)
+        })
+    }
+}
+
+/// Reinhard that maps `white` and anything above it to 1.0.
+#[derive(Debug, Clone, Copy)]
+pub struct ReinhardExtended {
+    pub white: Float,
+}
+
+impl ToneMap for ReinhardExtended {
+    fn map(&self, c: &Vec3) -> Vec3 {
+        let w2 = self.white * self.white;
+        per_channel(c, |v| {
+            let v = v.max(0.0);
+            (v * (1.0 + v / w2) / (1.0 + v)).min(1.0)
+        })
+    }
+}
+
+/// Krzysztof Narkowicz's curve fit of the ACES filmic reference transform.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Aces;
+
+impl ToneMap for Aces {
+    fn map(&self, c: &Vec3) -> Vec3 {
+        per_channel(c, |v| {
+            let v = v.max(0.0);
+            (v * (2.51 * v + 0.03) / (v * (2.43 * v + 0.59) + 0.14)).clamp(0.0, 1.0)
+        })
+    }
+}
+
+/// John Hable's filmic curve from Uncharted 2.
+#[derive(Debug, Clone, Copy)]
+pub struct Uncharted2 {
+    pub exposure_bias: Float,
+    pub white: Float,
+}
+
+impl Default for Uncharted2 {
+    fn default() -> Self {
+        Self {
+            exposure_bias: 2.0,
+            white: 11.2,
+        }
+    }
+}
+
+impl Uncharted2 {
+    fn curve(x: Float) -> Float {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    }
+}
+
+impl ToneMap for Uncharted2 {
+    fn map(&self, c: &Vec3) -> Vec3 {
+        let scale = 1.0 / Self::curve(self.white);
+        per_channel(c, |v| {
+            (Self::curve(v.max(0.0) * self.exposure_bias) * scale).clamp(0.0, 1.0)
+        })
+    }
+}
+
+/// Operator from a `name[:parameter]` spec such as `aces` or `exposure:2`.
+pub fn parse(spec: &str) -> Result<Box<dyn ToneMap>, String> {
+    let (name, param) = match spec.split_once(':') {
+        Some((n, p)) => {
+            let v = p
+                .parse::<Float>()
+                .map_err(|_| format!("invalid tone map parameter `{}`", p))?;
+            (n, Some(v))
+        }
+        None => (spec, None),
+    };
+    let op: Box<dyn ToneMap> = match name {
+        "clamp" => Box::new(Clamp),
+        "exposure" => Box::new(Exposure {
+            exposure: param.unwrap_or(1.0),
+        }),
+        "reinhard" => Box::new(Reinhard),
+        "reinhard-extended" => Box::new(ReinhardExtended {
+            white: param.unwrap_or(4.0),
+        }),
+        "aces" => Box::new(Aces),
+        "uncharted2" => Box::new(Uncharted2 {
+            white: param.unwrap_or(11.2),
+            ..Default::default()
+        }),
+        _ => return Err(format!("unknown tone map `{}`", name)),
+    };
+    if param.is_some() && matches!(name, "clamp" | "reinhard" | "aces") {
+        return Err(format!("tone map `{}` takes no parameter", name));
+    }
+    Ok(op)
+}
+
+/// sRGB OETF, linear `[0, 1]` to encoded.
+pub fn srgb_encode(v: Float) -> Float {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.003_130_8 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `srgb_encode`.
+pub fn srgb_decode(v: Float) -> Float {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// How display values are stored in 8 bit outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transfer {
+    /// Written as is, what the renderer always did.
+    #[default]
+    Linear,
+    Srgb,
+}
+
+impl Transfer {
+    pub fn encode(&self, c: &Vec3) -> Vec3 {
+        match self {
+            Transfer::Linear => c.clone(),
+            Transfer::Srgb => per_channel(c, srgb_encode),
+        }
+    }
+}
+
+/// Tone map followed by the transfer function, the full way from radiance
+/// to a canvas.
+pub struct Output {
+    pub tone_map: Box<dyn ToneMap>,
+    pub transfer: Transfer,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            tone_map: Box::new(Clamp),
+            transfer: Transfer::Linear,
+        }
+    }
+}
+
+impl ToneMap for Output {
+    fn map(&self, c: &Vec3) -> Vec3 {
+        self.transfer.encode(&self.tone_map.map(c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> Vec<Box<dyn ToneMap>> {
+        [
+            "clamp",
+            "exposure",
+            "reinhard",
+            "reinhard-extended",
+            "aces",
+            "uncharted2",
+        ]
+        .iter()
+        .map(|s| parse(s).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_operators_stay_in_range_and_increase() {
+        for op in all() {
+            let mut last = -1.0;
+            for i in 0..200 {
+                let v = op.map(&Vec3::from_float(i as Float * 0.25)).x;
+                assert!((0.0..=1.0).contains(&v));
+                assert!(v >= last);
+                last = v;
+            }
+            assert_eq!(op.map(&Vec3::from_float(-1.0)), Vec3::zero());
+        }
+    }
+
+    #[test]
+    fn test_known_values() {
+        let c = Vec3::new(1.0, 3.0, 0.0);
+
+        assert_eq!(Reinhard.map(&c), Vec3::new(0.5, 0.75, 0.0));
+        assert_eq!(
+            ReinhardExtended { white: 3.0 }.map(&c),
+            Vec3::new(0.55556, 1.0, 0.0)
+        );
+        assert_eq!(
+            Exposure { exposure: 1.0 }.map(&c),
+            Vec3::new(0.63212, 0.95021, 0.0)
+        );
+        assert_eq!(Aces.map(&Vec3::from_float(0.18)), Vec3::from_float(0.2669));
+        assert_eq!(
+            Uncharted2::default().map(&Vec3::from_float(5.6)),
+            Vec3::from_float(1.0)
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(parse("exposure:0.5").is_ok());
+        assert_eq!(parse("filmic").err().unwrap(), "unknown tone map `filmic`");
+        assert!(parse("aces:x").is_err());
+        assert_eq!(
+            parse("aces:2").err().unwrap(),
+            "tone map `aces` takes no parameter"
+        );
+        assert_eq!(
+            parse("clamp:5").err().unwrap(),
+            "tone map `clamp` takes no parameter"
+        );
+        assert!(parse("reinhard-extended:2").is_ok());
+    }
+
+    #[test]
+    fn test_srgb() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_encode(0.5) - 0.735_357).abs() < 1e-5);
+        assert!((srgb_encode(0.002) - 0.02584).abs() < 1e-6);
+        for i in 0..=20 {
+            let v = i as Float / 20.0;
+            assert!((srgb_decode(srgb_encode(v)) - v).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_output() {
+        let out = Output {
+            tone_map: Box::new(Reinhard),
+            transfer: Transfer::Srgb,
+        };
+
+        assert_eq!(out.map(&Vec3::from_float(1.0)).x, srgb_encode(0.5));
+        assert_eq!(
+            Output::default().map(&Vec3::new(2.0, 0.5, -1.0)),
+            Vec3::new(1.0, 0.5, 0.0)
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use super::{
+    matrix::Mat,
+    ray::Ray,
+    vec3::{Float, Vec3},
+};
+
+/// Axis aligned bounding box in world space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// Box that contains nothing, merging anything into it yields the other box.
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::from_float(Float::INFINITY),
+            max: Vec3::from_float(Float::NEG_INFINITY),
+        }
+    }
+
+    pub fn infinite() -> Self {
+        Self {
+            min: Vec3::from_float(Float::NEG_INFINITY),
+            max: Vec3::from_float(Float::INFINITY),
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        [&self.min, &self.max]
+            .iter()
+            .all(|v| v.x.is_finite() && v.y.is_finite() && v.z.is_finite())
+    }
+
+    pub fn add_point(&mut self, p: &Vec3) {
+        self.min.set_scalar(
+            self.min.x.min(p.x),
+            self.min.y.min(p.y),
+            self.min.z.min(p.z),
+        );
+        self.max.set_scalar(
+            self.max.x.max(p.x),
+            self.max.y.max(p.y),
+            self.max.z.max(p.z),
+        );
+    }
+
+    pub fn merge(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vec3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (&self.min + &self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> Float {
+        let d = &self.max - &self.min;
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Bounds of the eight transformed corners, `t` being an object to world
+    /// transform.
+    pub fn transform(&self, t: &Mat) -> Aabb {
+        if !self.is_finite() {
+            return Aabb::infinite();
+        }
+        let mut b = Aabb::empty();
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            b.add_point(&(t * &corner));
+        }
+        b
+    }
+
+    /// Slab test, returns the parametric range the ray spends inside the box.
+    pub fn intersect(&self, ray: &Ray, inv_dir: &Vec3) -> Option<(Float, Float)> {
+        let (tx0, tx1) = slab(ray.org.x, inv_dir.x, self.min.x, self.max.x);
+        let (ty0, ty1) = slab(ray.org.y, inv_dir.y, self.min.y, self.max.y);
+        let (tz0, tz1) = slab(ray.org.z, inv_dir.z, self.min.z, self.max.z);
+
+        let tmin = tx0.max(ty0).max(tz0);
+        let tmax = tx1.min(ty1).min(tz1);
+        if tmin > tmax {
+            return None;
+        }
+        Some((tmin, tmax))
+    }
+}
+
+fn slab(org: Float, inv_dir: Float, min: Float, max: Float) -> (Float, Float) {
+    let t0 = (min - org) * inv_dir;
+    let t1 = (max - org) * inv_dir;
+    // NaN shows up when the ray lies exactly on a slab plane, keep it open
+    let (t0, t1) = if t0 <= t1 { (t0, t1) } else { (t1, t0) };
+    (
+        if t0.is_nan() { Float::NEG_INFINITY } else { t0 },
+        if t1.is_nan() { Float::INFINITY } else { t1 },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{PI, SQRT_2};
+
+    use super::*;
+    use crate::world::transform::Transformable;
+
+    fn unit() -> Aabb {
+        Aabb::new(Vec3::from_float(-1.0), Vec3::from_float(1.0))
+    }
+
+    fn inv(dir: &Vec3) -> Vec3 {
+        Vec3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z)
+    }
+
+    #[test]
+    fn test_merge() {
+        let a = Aabb::new(Vec3::new(-5.0, -2.0, 0.0), Vec3::new(7.0, 4.0, 4.0));
+        let b = Aabb::new(Vec3::new(8.0, -7.0, -2.0), Vec3::new(14.0, 2.0, 8.0));
+        let m = a.merge(&b);
+
+        assert_eq!(m.min, Vec3::new(-5.0, -7.0, -2.0));
+        assert_eq!(m.max, Vec3::new(14.0, 4.0, 8.0));
+        assert_eq!(Aabb::empty().merge(&a), a);
+        assert_eq!(a.merge(&Aabb::empty()), a);
+    }
+
+    #[test]
+    fn test_transform() {
+        let t = Mat::identity(4).rotation_x(PI / 4.0).rotation_y(PI / 4.0);
+        let b = unit().transform(&t);
+
+        assert_eq!(b.min, Vec3::new(-SQRT_2, -1.70711, -1.70711));
+        assert_eq!(b.max, Vec3::new(SQRT_2, 1.70711, 1.70711));
+    }
+
+    #[test]
+    fn test_intersect() {
+        let b = Aabb::new(Vec3::new(5.0, -2.0, 0.0), Vec3::new(11.0, 4.0, 7.0));
+        let cases = [
+            (Vec3::new(15.0, 1.0, 2.0), Vec3::new(-1.0, 0.0, 0.0), true),
+            (Vec3::new(-5.0, -1.0, 4.0), Vec3::new(1.0, 0.0, 0.0), true),
+            (Vec3::new(7.0, 6.0, 5.0), Vec3::new(0.0, -1.0, 0.0), true),
+            (Vec3::new(9.0, -5.0, 6.0), Vec3::new(0.0, 1.0, 0.0), true),
+            (Vec3::new(8.0, 2.0, 12.0), Vec3::new(0.0, 0.0, -1.0), true),
+            (Vec3::new(6.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), true),
+            (Vec3::new(8.0, 1.0, 3.5), Vec3::new(0.0, 0.0, 1.0), true),
+            (Vec3::new(9.0, -1.0, -8.0), Vec3::new(2.0, 4.0, 6.0), false),
+            (Vec3::new(8.0, 3.0, -4.0), Vec3::new(6.0, 2.0, 4.0), false),
+            (Vec3::new(9.0, -1.0, -2.0), Vec3::new(4.0, 6.0, 2.0), false),
+            (Vec3::new(4.0, 0.0, 9.0), Vec3::new(0.0, 0.0, -1.0), false),
+            (Vec3::new(8.0, 6.0, -1.0), Vec3::new(0.0, -1.0, 0.0), false),
+            (Vec3::new(12.0, 5.0, 4.0), Vec3::new(-1.0, 0.0, 0.0), false),
+        ];
+
+        for (org, dir, expected) in cases {
+            let dir = dir.norm();
+            let r = Ray::new(org, dir.clone());
+            assert_eq!(b.intersect(&r, &inv(&dir)).is_some(), expected);
+        }
+    }
+
+    #[test]
+    fn test_surface_area() {
+        let b = Aabb::new(Vec3::zero(), Vec3::new(1.0, 2.0, 3.0));
+
+        assert_eq!(b.surface_area(), 22.0);
+        assert_eq!(Aabb::empty().surface_area(), 0.0);
+    }
+}
@@ -1,28 +1,299 @@
 use crate::world::{shapes::shape::Shape, w::Intersection};
 
-use super::{ray::Ray, vec3::Vec3};
+use super::{
+    material::Finish,
+    ray::{Differentials, Ray},
+    vec3::{Float, Vec3, EPSILON},
+};
 
 //#[derive(Debug)]
 pub struct Comp<'a> {
     pub intersection: &'a Intersection<'a>,
+    // the material's scalars at the hit point, noise driven ones included
+    pub finish: Finish,
     pub hitp: Vec3,
+    // hit point pushed off the surface, shadow rays start there so the
+    // surface does not shadow itself
+    pub over_point: Vec3,
+    pub under_point: Vec3,
     pub normalv: Vec3,
     pub reflectv: Vec3,
     pub eyev: Vec3,
     pub inside: bool,
+    pub n1: Float,
+    pub n2: Float,
+    // how far the hit point moves on the surface one pixel right and one
+    // down, zero when the ray has no differentials
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    // differentials of the incoming ray, bent along with it by reflection
+    // and refraction
+    pub diff: Option<Differentials>,
 }
 
 impl<'a> Comp<'a> {
-    pub fn prepare_comp(ray: &Ray, nearest: &'a Intersection) -> Comp<'a> {
+    /// `xs` is the sorted list `nearest` was picked from, it is walked to find
+    /// the refractive indices on both sides of the hit.
+    pub fn prepare_comp(ray: &Ray, nearest: &'a Intersection, xs: &[Intersection]) -> Comp<'a> {
         let hitp = ray.position(nearest.t);
-        let normalv = nearest.sp.normal_at(&hitp);
+        let eyev = -&ray.dir;
+        let mut geometric = nearest.sp.normal_at_uv(&hitp, nearest.u, nearest.v);
+        let mut normalv = match &nearest.sp.get_material().bump {
+            Some(b) => b.perturb(nearest.sp, &hitp, &geometric),
+            None => geometric.clone(),
+        };
+        let inside = geometric.dot(&eyev) < 0.0;
+        if inside {
+            geometric = -&geometric;
+            normalv = -&normalv;
+        }
+        let (n1, n2) = Self::refractive_indices(nearest, xs);
+        let (dpdx, dpdy) = ray
+            .footprint(&hitp, &geometric)
+            .unwrap_or_else(|| (Vec3::zero(), Vec3::zero()));
+        let material = nearest.sp.get_material();
+        let finish = if material.varying.is_empty() {
+            material.finish()
+        } else {
+            material.finish_at(&nearest.sp.world_to_object(&hitp))
+        };
         Self {
             intersection: nearest,
-            reflectv: -&ray.dir.reflect(&normalv),
+            finish,
+            reflectv: ray.dir.reflect(&normalv),
+            // offset along the real surface, a bent normal could push the
+            // points through it
+            over_point: &hitp + &geometric * EPSILON,
+            under_point: &hitp - &geometric * EPSILON,
             normalv,
             hitp,
-            eyev: -&ray.dir,
-            inside: false,
+            eyev,
+            inside,
+            n1,
+            n2,
+            dpdx,
+            dpdy,
+            diff: ray.diff.clone(),
+        }
+    }
+
+    fn refractive_indices(nearest: &Intersection, xs: &[Intersection]) -> (Float, Float) {
+        let mut containers: Vec<&dyn Shape> = Vec::new();
+        let mut n1 = 1.0;
+        for i in xs {
+            let is_hit = std::ptr::eq(i, nearest);
+            if is_hit {
+                n1 = containers
+                    .last()
+                    .map_or(1.0, |s| s.get_material().refractive_index);
+            }
+            match containers.iter().position(|s| i.is_same_shape(*s)) {
+                Some(idx) => {
+                    containers.remove(idx);
+                }
+                None => containers.push(i.sp),
+            }
+            if is_hit {
+                let n2 = containers
+                    .last()
+                    .map_or(1.0, |s| s.get_material().refractive_index);
+                return (n1, n2);
+            }
+        }
+        (n1, 1.0)
+    }
+
+    /// Schlick's approximation of the Fresnel reflectance.
+    pub fn schlick(&self) -> Float {
+        let mut cos = self.eyev.dot(&self.normalv);
+        if self.n1 > self.n2 {
+            let n = self.n1 / self.n2;
+            let sin2_t = n * n * (1.0 - cos * cos);
+            if sin2_t > 1.0 {
+                return 1.0;
+            }
+            cos = (1.0 - sin2_t).sqrt();
         }
+        let r0 = ((self.n1 - self.n2) / (self.n1 + self.n2)).powi(2);
+        r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        utils::{
+            material::{IMaterial, Param, Varying},
+            noise::{Noise, Perlin},
+        },
+        world::{
+            bump::{BumpMap, Height},
+            pattern::Gradient,
+            shapes::{plane::Plane, sphere::Sphere},
+            transform::Transformable,
+        },
+    };
+
+    fn glass_sphere() -> Sphere {
+        Sphere::default().transparency(1.0).refractive_index(1.5)
+    }
+
+    #[test]
+    fn test_inside() {
+        let sp = Sphere::default();
+        let r = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0));
+        let xs = vec![Intersection::new(&sp, -1.0), Intersection::new(&sp, 1.0)];
+        let c = Comp::prepare_comp(&r, &xs[1], &xs);
+
+        assert!(c.inside);
+        assert_eq!(c.hitp, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(c.normalv, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_outside() {
+        let sp = Sphere::default();
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = vec![Intersection::new(&sp, 4.0), Intersection::new(&sp, 6.0)];
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert!(!c.inside);
+        assert_eq!(c.normalv, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_n1_n2() {
+        let a = glass_sphere().scaling(2.0, 2.0, 2.0);
+        let b = glass_sphere()
+            .refractive_index(2.0)
+            .translation(0.0, 0.0, -0.25);
+        let c = glass_sphere()
+            .refractive_index(2.5)
+            .translation(0.0, 0.0, 0.25);
+        let r = Ray::new(Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = vec![
+            Intersection::new(&a, 2.0),
+            Intersection::new(&b, 2.75),
+            Intersection::new(&c, 3.25),
+            Intersection::new(&b, 4.75),
+            Intersection::new(&c, 5.25),
+            Intersection::new(&a, 6.0),
+        ];
+        let expected = [
+            (1.0, 1.5),
+            (1.5, 2.0),
+            (2.0, 2.5),
+            (2.5, 2.5),
+            (2.5, 1.5),
+            (1.5, 1.0),
+        ];
+
+        for (i, (n1, n2)) in expected.iter().enumerate() {
+            let c = Comp::prepare_comp(&r, &xs[i], &xs);
+            assert_eq!((c.n1, c.n2), (*n1, *n2));
+        }
+    }
+
+    #[test]
+    fn test_over_point() {
+        let sp = glass_sphere().translation(0.0, 0.0, 1.0);
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = vec![Intersection::new(&sp, 5.0)];
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert!(c.over_point.z < -EPSILON / 2.0);
+        assert!(c.hitp.z > c.over_point.z);
+    }
+
+    #[test]
+    fn test_under_point() {
+        let sp = glass_sphere().translation(0.0, 0.0, 1.0);
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = vec![Intersection::new(&sp, 5.0)];
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert!(c.under_point.z > EPSILON / 2.0);
+        assert!(c.hitp.z < c.under_point.z);
+    }
+
+    #[test]
+    fn test_bumped_normal() {
+        // heights rising along x tilt the shading normal back toward -x
+        let ramp = Gradient::new(Vec3::zero(), Vec3::from_float(1.0));
+        let floor = Plane::default().bump(BumpMap::new(Height::Pattern(Arc::new(ramp)), 1.0));
+        let r = Ray::new(Vec3::new(0.5, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let xs = vec![Intersection::new(&floor, 1.0)];
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        let bent = Vec3::new(-1.0, 1.0, 0.0).norm();
+        assert_eq!(c.normalv, bent);
+        assert_eq!(c.reflectv, Vec3::new(0.0, -1.0, 0.0).reflect(&bent));
+        assert_eq!(c.over_point, Vec3::new(0.5, EPSILON, 0.0));
+
+        // from below both normals flip
+        let r = Ray::new(Vec3::new(0.5, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+        assert_eq!(c.normalv, -&bent);
+        assert_eq!(c.over_point, Vec3::new(0.5, -EPSILON, 0.0));
+    }
+
+    #[test]
+    fn test_varying_finish() {
+        let noise = Arc::new(Perlin::new(7));
+        let floor = Plane::default()
+            .reflective(0.9)
+            .vary(Varying::new(Param::Reflective, noise.clone(), 0.2, 0.6).scaling(0.5, 0.5, 0.5))
+            .translation(3.0, 0.0, 0.0);
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let xs = vec![Intersection::new(&floor, 1.0)];
+
+        let mut seen = Vec::new();
+        for x in [3.3, 3.7, 4.4] {
+            let r = Ray::new(Vec3::new(x, 1.0, 0.25), down.clone());
+            let c = Comp::prepare_comp(&r, &xs[0], &xs);
+            // sampled in object space, then through the driver's own scaling
+            let p = Vec3::new((x - 3.0) * 2.0, 0.0, 0.5);
+            assert_eq!(c.finish.reflective, noise.remap(&p, 0.2, 0.6));
+            assert!((0.2..=0.6).contains(&c.finish.reflective));
+            assert_eq!(c.finish.diffuse, floor.m.diffuse);
+            seen.push(c.finish.reflective);
+        }
+        assert!(seen[0] != seen[1] && seen[1] != seen[2]);
+        // the material itself keeps its fixed value
+        assert_eq!(floor.m.reflective, 0.9);
+    }
+
+    #[test]
+    fn test_schlick_total_internal_reflection() {
+        let f = std::f32::consts::FRAC_1_SQRT_2;
+        let sp = glass_sphere();
+        let r = Ray::new(Vec3::new(0.0, 0.0, f), Vec3::new(0.0, 1.0, 0.0));
+        let xs = vec![Intersection::new(&sp, -f), Intersection::new(&sp, f)];
+        let c = Comp::prepare_comp(&r, &xs[1], &xs);
+
+        assert_eq!(c.schlick(), 1.0);
+    }
+
+    #[test]
+    fn test_schlick_perpendicular() {
+        let sp = glass_sphere();
+        let r = Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0));
+        let xs = vec![Intersection::new(&sp, -1.0), Intersection::new(&sp, 1.0)];
+        let c = Comp::prepare_comp(&r, &xs[1], &xs);
+
+        assert!((c.schlick() - 0.04).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_schlick_small_angle() {
+        let sp = glass_sphere();
+        let r = Ray::new(Vec3::new(0.0, 0.99, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = vec![Intersection::new(&sp, 1.8589)];
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert!((c.schlick() - 0.48873).abs() < 0.001);
     }
 }
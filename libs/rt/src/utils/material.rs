@@ -1,12 +1,91 @@
-use super::vec3::{Float, Vec3};
+use std::sync::Arc;
+
+use crate::world::{
+    bump::Bump,
+    pattern::{Blend, Pattern, Perturb, Solid},
+    transform::Transformable,
+};
+
+use super::{
+    matrix::Mat,
+    noise::Noise,
+    vec3::{Float, Vec3},
+};
+
+/// Scalar parameters of a `Material` that can vary across the surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    Ambient,
+    Diffuse,
+    Specular,
+    Shininess,
+    Reflective,
+    Transparency,
+}
+
+/// Drives `param` with `noise`, remapped onto `[lo, hi]`, at the object
+/// space point transformed like a pattern.
+#[derive(Debug)]
+pub struct Varying {
+    pub param: Param,
+    pub noise: Arc<dyn Noise>,
+    pub lo: Float,
+    pub hi: Float,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl Varying {
+    pub fn new(param: Param, noise: Arc<dyn Noise>, lo: Float, hi: Float) -> Self {
+        Self {
+            param,
+            noise,
+            lo,
+            hi,
+            t: Mat::identity(4),
+            inverse: Mat::identity(4),
+        }
+    }
+
+    pub fn value_at(&self, object_point: &Vec3) -> Float {
+        let p = &self.inverse * object_point;
+        self.noise.remap(&p, self.lo, self.hi)
+    }
+}
+
+impl Transformable for Varying {
+    fn apply_transform(&mut self, transform: &Mat) {
+        self.t = &self.t * transform;
+        self.inverse = self.t.inverse();
+    }
+}
+
+/// The scalar parameters shading reads, as they are at one point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Finish {
+    pub ambient: Float,
+    pub diffuse: Float,
+    pub specular: Float,
+    pub shininess: Float,
+    pub reflective: Float,
+    pub transparency: Float,
+}
 
 #[derive(Debug, Clone)]
 pub struct Material {
     pub color: Vec3,
+    // replaces `color` when set
+    pub pattern: Option<Arc<dyn Pattern>>,
+    // bends the shading normal, the geometry stays put
+    pub bump: Option<Arc<dyn Bump>>,
+    // noise in place of the fixed scalars below, see `finish_at`
+    pub varying: Vec<Arc<Varying>>,
     pub ambient: Float,
     pub diffuse: Float,
     pub specular: Float,
     pub reflective: Float,
+    pub transparency: Float,
+    pub refractive_index: Float,
     pub shininess: Float,
 }
 
@@ -14,43 +93,120 @@ impl Default for Material {
     fn default() -> Self {
         Self {
             color: Vec3::from_float(1.0),
+            pattern: None,
+            bump: None,
+            varying: Vec::new(),
             ambient: 0.1,
             diffuse: 0.9,
             specular: 0.9,
             reflective: 0.0,
+            transparency: 0.0,
+            refractive_index: 1.0,
             shininess: 200.0,
         }
     }
 }
 
+impl Material {
+    /// The fixed scalars, `varying` left out.
+    pub fn finish(&self) -> Finish {
+        Finish {
+            ambient: self.ambient,
+            diffuse: self.diffuse,
+            specular: self.specular,
+            shininess: self.shininess,
+            reflective: self.reflective,
+            transparency: self.transparency,
+        }
+    }
+
+    /// The scalars at `object_point`, each `varying` one read off its noise.
+    pub fn finish_at(&self, object_point: &Vec3) -> Finish {
+        let mut f = self.finish();
+        for v in self.varying.iter() {
+            let value = v.value_at(object_point);
+            match v.param {
+                Param::Ambient => f.ambient = value,
+                Param::Diffuse => f.diffuse = value,
+                Param::Specular => f.specular = value,
+                Param::Shininess => f.shininess = value,
+                Param::Reflective => f.reflective = value,
+                Param::Transparency => f.transparency = value,
+            }
+        }
+        f
+    }
+
+    // the pattern, or the flat color as one
+    fn surface(&self) -> Arc<dyn Pattern> {
+        match &self.pattern {
+            Some(p) => p.clone(),
+            None => Arc::new(Solid::new(self.color.clone())),
+        }
+    }
+}
+
 pub trait IMaterial: Sized {
-    fn color(mut self, x: Float, y: Float, z: Float) -> Self {
-        self.get_material().color.set_scalar(x, y, z);
-        self
+    fn color(self, x: Float, y: Float, z: Float) -> Self {
+        self.with_material(|m| m.color.set_scalar(x, y, z))
     }
 
-    fn diffuse(mut self, f: Float) -> Self {
-        self.get_material().diffuse = f;
-        self
+    fn pattern(self, p: impl Pattern + 'static) -> Self {
+        self.with_material(|m| m.pattern = Some(Arc::new(p)))
     }
 
-    fn specular(mut self, f: Float) -> Self {
-        self.get_material().specular = f;
-        self
+    /// Averages what the surface shows so far with `p`.
+    fn blend(self, p: impl Pattern + 'static) -> Self {
+        self.with_material(|m| m.pattern = Some(Arc::new(Blend::new(m.surface(), Arc::new(p)))))
     }
 
-    fn ambient(mut self, f: Float) -> Self {
-        self.get_material().ambient = f;
-        self
+    /// Wobbles what the surface shows so far with noise of the given seed.
+    fn perturb(self, scale: Float, seed: u64) -> Self {
+        self.with_material(|m| m.pattern = Some(Arc::new(Perturb::new(m.surface(), scale, seed))))
     }
 
-    fn reflective(mut self, f: Float) -> Self {
-        self.get_material().reflective = f;
-        self
+    /// Bump or normal map for the shading normal.
+    fn bump(self, b: impl Bump + 'static) -> Self {
+        self.with_material(|m| m.bump = Some(Arc::new(b)))
+    }
+
+    /// Lets a scalar parameter follow a noise field.
+    fn vary(self, v: Varying) -> Self {
+        self.with_material(|m| m.varying.push(Arc::new(v)))
+    }
+
+    fn diffuse(self, f: Float) -> Self {
+        self.with_material(|m| m.diffuse = f)
+    }
+
+    fn specular(self, f: Float) -> Self {
+        self.with_material(|m| m.specular = f)
+    }
+
+    fn ambient(self, f: Float) -> Self {
+        self.with_material(|m| m.ambient = f)
+    }
+
+    fn reflective(self, f: Float) -> Self {
+        self.with_material(|m| m.reflective = f)
+    }
+
+    fn transparency(self, f: Float) -> Self {
+        self.with_material(|m| m.transparency = f)
+    }
+
+    fn refractive_index(self, f: Float) -> Self {
+        self.with_material(|m| m.refractive_index = f)
+    }
+
+    fn shininess(self, f: Float) -> Self {
+        self.with_material(|m| m.shininess = f)
     }
 
-    fn shininess(mut self, f: Float) -> Self {
-        self.get_material().shininess = f;
+    /// Every builder goes through here, shapes keeping copies of their
+    /// material elsewhere override it to pass the change on.
+    fn with_material(mut self, f: impl FnOnce(&mut Material)) -> Self {
+        f(self.get_material());
         self
     }
 
@@ -1,5 +1,9 @@
+pub mod aabb;
 pub mod comp;
 pub mod material;
 pub mod matrix;
+pub mod noise;
 pub mod ray;
+pub mod rng;
+pub mod stats;
 pub mod vec3;
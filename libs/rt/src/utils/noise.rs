@@ -0,0 +1,469 @@
+use std::{fmt::Debug, sync::Arc};
+
+use super::{
+    rng::Rng,
+    vec3::{Float, Vec3},
+};
+
+/// Scalar field over space. Every generator is seeded so a scene renders the
+/// same each time, and the value can drive a pattern or any material
+/// parameter through `remap`.
+pub trait Noise: Debug + Send + Sync {
+    fn noise(&self, p: &Vec3) -> Float;
+
+    /// Range the values fall in, roughly for the gradient noises.
+    fn range(&self) -> (Float, Float);
+
+    /// `noise` moved from `range` onto `[lo, hi]`, clamped.
+    fn remap(&self, p: &Vec3, lo: Float, hi: Float) -> Float {
+        let (min, max) = self.range();
+        let f = ((self.noise(p) - min) / (max - min)).clamp(0.0, 1.0);
+        lo + (hi - lo) * f
+    }
+
+    /// Three decorrelated values, for displacing points.
+    fn vector(&self, p: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.noise(p),
+            self.noise(&(p + Vec3::new(31.4, 15.9, 26.5))),
+            self.noise(&(p + Vec3::new(-35.8, 97.9, -32.3))),
+        )
+    }
+}
+
+// seeded permutation of 0..=255, repeated so lookups can skip a wrap
+fn permutation(seed: u64) -> [u8; 512] {
+    let mut p: Vec<u8> = (0..=255).collect();
+    let mut rng = Rng::new(seed);
+    for i in (1..p.len()).rev() {
+        p.swap(i, rng.next_u64() as usize % (i + 1));
+    }
+    let mut perm = [0; 512];
+    for (i, v) in perm.iter_mut().enumerate() {
+        *v = p[i & 255];
+    }
+    perm
+}
+
+/// Ken Perlin's improved gradient noise. The permutation comes from `seed`
+/// so two generators with the same seed give the same field.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    perm: [u8; 512],
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+fn fade(t: Float) -> Float {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: Float, a: Float, b: Float) -> Float {
+    a + t * (b - a)
+}
+
+// dot product with one of the 12 cube edge directions
+fn grad(hash: u8, x: Float, y: Float, z: Float) -> Float {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            perm: permutation(seed),
+        }
+    }
+}
+
+impl Noise for Perlin {
+    /// Zero on every lattice point.
+    fn noise(&self, p: &Vec3) -> Float {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+        let cell = |f: Float| (f as i64 & 255) as usize;
+        let (xi, yi, zi) = (cell(fx), cell(fy), cell(fz));
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let perm = &self.perm;
+        let a = perm[xi] as usize + yi;
+        let (aa, ab) = (perm[a] as usize + zi, perm[a + 1] as usize + zi);
+        let b = perm[xi + 1] as usize + yi;
+        let (ba, bb) = (perm[b] as usize + zi, perm[b + 1] as usize + zi);
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(perm[aa], x, y, z), grad(perm[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(perm[ab], x, y - 1.0, z),
+                    grad(perm[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(perm[aa + 1], x, y, z - 1.0),
+                    grad(perm[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(perm[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(perm[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    fn range(&self) -> (Float, Float) {
+        (-1.0, 1.0)
+    }
+}
+
+/// Stefan Gustavson's simplex noise, cheaper than Perlin in 3D and without
+/// its axis aligned artifacts.
+#[derive(Debug, Clone)]
+pub struct Simplex {
+    perm: [u8; 512],
+}
+
+impl Simplex {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            perm: permutation(seed),
+        }
+    }
+}
+
+impl Noise for Simplex {
+    fn noise(&self, p: &Vec3) -> Float {
+        const F3: Float = 1.0 / 3.0;
+        const G3: Float = 1.0 / 6.0;
+        // skew into the simplex grid to find the cell
+        let s = (p.x + p.y + p.z) * F3;
+        let (i, j, k) = ((p.x + s).floor(), (p.y + s).floor(), (p.z + s).floor());
+        let t = (i + j + k) * G3;
+        let x0 = p.x - (i - t);
+        let y0 = p.y - (j - t);
+        let z0 = p.z - (k - t);
+
+        // which of the six tetrahedra of the cube the point is in
+        let ((i1, j1, k1), (i2, j2, k2)) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+
+        let cell = |f: Float| (f as i64 & 255) as usize;
+        let (ii, jj, kk) = (cell(i), cell(j), cell(k));
+        let perm = &self.perm;
+        let corner = |di: usize, dj: usize, dk: usize, offset: Float| {
+            let x = x0 - di as Float + offset;
+            let y = y0 - dj as Float + offset;
+            let z = z0 - dk as Float + offset;
+            let t = 0.6 - x * x - y * y - z * z;
+            if t < 0.0 {
+                return 0.0;
+            }
+            let h = perm[ii + di + perm[jj + dj + perm[kk + dk] as usize] as usize];
+            t * t * t * t * grad(h, x, y, z)
+        };
+        32.0 * (corner(0, 0, 0, 0.0)
+            + corner(i1, j1, k1, G3)
+            + corner(i2, j2, k2, 2.0 * G3)
+            + corner(1, 1, 1, 3.0 * G3))
+    }
+
+    fn range(&self) -> (Float, Float) {
+        (-1.0, 1.0)
+    }
+}
+
+/// What a `Worley` field measures, from the distances to the nearest and
+/// second nearest feature points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cellular {
+    /// Distance to the nearest point, round blobs.
+    #[default]
+    F1,
+    F2,
+    /// `F2 - F1`, zero along the borders between cells.
+    Edge,
+}
+
+/// Steven Worley's cellular noise with one random feature point per unit
+/// cube.
+#[derive(Debug, Clone)]
+pub struct Worley {
+    pub seed: u64,
+    pub feature: Cellular,
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            feature: Cellular::F1,
+        }
+    }
+
+    fn point(&self, i: i64, j: i64, k: i64) -> Vec3 {
+        let h = (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (j as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+            ^ (k as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+        let mut rng = Rng::new(self.seed ^ h);
+        Vec3::new(
+            i as Float + rng.next_float(),
+            j as Float + rng.next_float(),
+            k as Float + rng.next_float(),
+        )
+    }
+}
+
+impl Noise for Worley {
+    fn noise(&self, p: &Vec3) -> Float {
+        let (ci, cj, ck) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let (mut f1, mut f2) = (Float::INFINITY, Float::INFINITY);
+        for k in ck - 1..=ck + 1 {
+            for j in cj - 1..=cj + 1 {
+                for i in ci - 1..=ci + 1 {
+                    let d = (self.point(i, j, k) - p).mag();
+                    if d < f1 {
+                        (f1, f2) = (d, f1);
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        match self.feature {
+            Cellular::F1 => f1,
+            Cellular::F2 => f2,
+            Cellular::Edge => f2 - f1,
+        }
+    }
+
+    fn range(&self) -> (Float, Float) {
+        match self.feature {
+            Cellular::F2 => (0.0, 1.5),
+            _ => (0.0, 1.0),
+        }
+    }
+}
+
+/// How `Fractal` folds the octaves together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FractalKind {
+    /// Plain sum, soft clouds.
+    #[default]
+    Fbm,
+    /// Sum of absolute values, creases where the noise crosses zero. Marble
+    /// and fire use it.
+    Turbulence,
+    /// Inverted and squared creases, sharp ridges like mountain ranges.
+    Ridged,
+}
+
+/// Octaves of `noise`, each one `lacunarity` times finer and `gain` times
+/// weaker than the last, normalized back into the base range.
+#[derive(Debug, Clone)]
+pub struct Fractal {
+    pub noise: Arc<dyn Noise>,
+    pub kind: FractalKind,
+    pub octaves: u32,
+    pub lacunarity: Float,
+    pub gain: Float,
+}
+
+impl Fractal {
+    pub fn new(noise: Arc<dyn Noise>, kind: FractalKind, octaves: u32) -> Self {
+        Self {
+            noise,
+            kind,
+            octaves: octaves.max(1),
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Noise for Fractal {
+    fn noise(&self, p: &Vec3) -> Float {
+        let (mut sum, mut norm) = (0.0, 0.0);
+        let (mut freq, mut amp) = (1.0, 1.0);
+        for octave in 0..self.octaves {
+            // shifted so the octaves do not all vanish on the same lattice
+            let shift = octave as Float * 17.31;
+            let q = p * freq + Vec3::new(shift, -shift, shift * 0.5);
+            let n = self.noise.noise(&q);
+            let v = match self.kind {
+                FractalKind::Fbm => n,
+                FractalKind::Turbulence => n.abs(),
+                FractalKind::Ridged => (1.0 - n.abs()) * (1.0 - n.abs()),
+            };
+            sum += v * amp;
+            norm += amp;
+            freq *= self.lacunarity;
+            amp *= self.gain;
+        }
+        sum / norm
+    }
+
+    fn range(&self) -> (Float, Float) {
+        let (lo, hi) = self.noise.range();
+        match self.kind {
+            FractalKind::Fbm => (lo, hi),
+            FractalKind::Turbulence => (0.0, lo.abs().max(hi.abs())),
+            FractalKind::Ridged => (0.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perlin_lattice_and_range() {
+        let n = Perlin::new(7);
+        assert_eq!(n.noise(&Vec3::new(3.0, -2.0, 5.0)), 0.0);
+
+        let mut rng = Rng::new(1);
+        let (mut lo, mut hi) = (0.0, 0.0);
+        for _ in 0..2000 {
+            let p = Vec3::new(
+                rng.next_float() * 20.0 - 10.0,
+                rng.next_float() * 20.0 - 10.0,
+                rng.next_float() * 20.0 - 10.0,
+            );
+            let v = n.noise(&p);
+            assert!((-1.1..=1.1).contains(&v));
+            (lo, hi) = (v.min(lo), v.max(hi));
+        }
+        assert!(lo < -0.4 && hi > 0.4);
+    }
+
+    fn random_points(n: usize) -> Vec<Vec3> {
+        let mut rng = Rng::new(1);
+        let mut coord = move || rng.range(-10.0, 10.0);
+        (0..n)
+            .map(|_| Vec3::new(coord(), coord(), coord()))
+            .collect()
+    }
+
+    fn spread(noise: &dyn Noise) -> (Float, Float) {
+        random_points(2000)
+            .iter()
+            .map(|p| noise.noise(p))
+            .fold((Float::INFINITY, -Float::INFINITY), |(lo, hi), v| {
+                (lo.min(v), hi.max(v))
+            })
+    }
+
+    #[test]
+    fn test_perlin_seeded_and_smooth() {
+        let p = Vec3::new(1.3, 2.7, -0.4);
+        assert_eq!(Perlin::new(3).noise(&p), Perlin::new(3).noise(&p));
+        assert_ne!(Perlin::new(3).noise(&p), Perlin::new(4).noise(&p));
+
+        let n = Perlin::new(3);
+        let q = &p + Vec3::new(0.001, 0.0, 0.0);
+        assert!((n.noise(&p) - n.noise(&q)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_simplex() {
+        let n = Simplex::new(5);
+        let (lo, hi) = spread(&n);
+        assert!(lo >= -1.1 && hi <= 1.1);
+        assert!(lo < -0.4 && hi > 0.4);
+
+        let p = Vec3::new(0.3, -4.2, 7.7);
+        assert_eq!(n.noise(&p), Simplex::new(5).noise(&p));
+        assert_ne!(n.noise(&p), Simplex::new(6).noise(&p));
+        let q = &p + Vec3::new(0.0, 0.001, 0.0);
+        assert!((n.noise(&p) - n.noise(&q)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_worley() {
+        let w = Worley::new(11);
+        // zero right on a feature point
+        let site = w.point(2, -1, 0);
+        assert!(w.noise(&site) < 1e-6);
+
+        for p in random_points(500) {
+            let f1 = w.noise(&p);
+            let f2 = Worley {
+                feature: Cellular::F2,
+                ..w.clone()
+            }
+            .noise(&p);
+            let edge = Worley {
+                feature: Cellular::Edge,
+                ..w.clone()
+            }
+            .noise(&p);
+            assert!(f1 >= 0.0 && f1 <= f2);
+            assert!((edge - (f2 - f1)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_fractals() {
+        let base: Arc<dyn Noise> = Arc::new(Perlin::new(2));
+        let fbm = Fractal::new(base.clone(), FractalKind::Fbm, 5);
+        let turbulence = Fractal::new(base.clone(), FractalKind::Turbulence, 5);
+        let ridged = Fractal::new(base.clone(), FractalKind::Ridged, 5);
+
+        let (lo, hi) = spread(&fbm);
+        assert!(lo >= -1.0 && hi <= 1.0 && lo < 0.0 && hi > 0.0);
+        let (lo, hi) = spread(&turbulence);
+        assert!(lo >= 0.0 && hi <= 1.0);
+        let (lo, hi) = spread(&ridged);
+        assert!(lo >= 0.0 && hi <= 1.0);
+
+        // one octave is the base noise itself
+        let p = Vec3::new(0.4, 1.7, -2.2);
+        assert_eq!(
+            Fractal::new(base.clone(), FractalKind::Fbm, 1).noise(&p),
+            base.noise(&p)
+        );
+    }
+
+    #[test]
+    fn test_remap() {
+        let w = Worley::new(3);
+        for p in random_points(200) {
+            let v = w.remap(&p, 0.2, 0.4);
+            assert!((0.2..=0.4).contains(&v));
+        }
+        let n = Perlin::new(0);
+        assert_eq!(n.remap(&Vec3::new(1.0, 2.0, 3.0), 0.0, 10.0), 5.0);
+    }
+}
@@ -1,26 +1,74 @@
 use super::matrix::Mat;
 use crate::utils::vec3::{Float, Vec3};
 
+/// Rays through the neighbouring pixels, one step along x and one along y.
+/// How far they land from the main ray tells how much of a surface one
+/// pixel covers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Differentials {
+    pub rx_org: Vec3,
+    pub rx_dir: Vec3,
+    pub ry_org: Vec3,
+    pub ry_dir: Vec3,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Ray {
     pub org: Vec3,
     pub dir: Vec3,
+    pub diff: Option<Differentials>,
 }
 
 impl Ray {
     pub fn new(org: Vec3, dir: Vec3) -> Self {
-        Self { org, dir }
+        Self {
+            org,
+            dir,
+            diff: None,
+        }
+    }
+
+    pub fn with_differentials(mut self, diff: Differentials) -> Self {
+        self.diff = Some(diff);
+        self
+    }
+
+    /// Brings the neighbouring rays `s` times closer, for pixels that are
+    /// split between several samples.
+    pub fn scale_differentials(&mut self, s: Float) {
+        if let Some(d) = self.diff.as_mut() {
+            d.rx_org = &self.org + (&d.rx_org - &self.org) * s;
+            d.ry_org = &self.org + (&d.ry_org - &self.org) * s;
+            d.rx_dir = &self.dir + (&d.rx_dir - &self.dir) * s;
+            d.ry_dir = &self.dir + (&d.ry_dir - &self.dir) * s;
+        }
+    }
+
+    /// Where the neighbouring rays cross the plane through `p` facing `n`,
+    /// as offsets from `p`. `None` without differentials or when they run
+    /// along the plane.
+    pub fn footprint(&self, p: &Vec3, n: &Vec3) -> Option<(Vec3, Vec3)> {
+        let d = self.diff.as_ref()?;
+        let dist = n.dot(p);
+        let offset = |org: &Vec3, dir: &Vec3| {
+            let denom = n.dot(dir);
+            if denom.abs() < 1e-8 {
+                return None;
+            }
+            let t = (dist - n.dot(org)) / denom;
+            Some(org + dir * t - p)
+        };
+        Some((offset(&d.rx_org, &d.rx_dir)?, offset(&d.ry_org, &d.ry_dir)?))
     }
 
     pub fn position(&self, t: Float) -> Vec3 {
         &self.org + &self.dir * t
     }
 
+    /// Differentials are left behind, shapes only need the ray itself to
+    /// find hits in their own space.
     pub fn transform(&self, m: &Mat) -> Self {
-        Self {
-            org: m * &self.org,
-            dir: m ^ &self.dir,
-        }
+        Self::new(m * &self.org, m ^ &self.dir)
     }
 }
 
@@ -51,6 +99,35 @@ mod tests {
         assert_eq!(ray.position(2.5), Vec3::new(4.5, 3.0, 4.0));
     }
 
+    #[test]
+    fn test_footprint() {
+        // looking straight down at the floor, neighbours shifted by 0.1
+        let r = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).with_differentials(
+            Differentials {
+                rx_org: Vec3::new(0.1, 1.0, 0.0),
+                rx_dir: Vec3::new(0.0, -1.0, 0.0),
+                ry_org: Vec3::new(0.0, 1.0, 0.0),
+                ry_dir: Vec3::new(0.0, -1.0, 0.1).norm(),
+            },
+        );
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let (dx, dy) = r.footprint(&Vec3::zero(), &up).unwrap();
+
+        assert_eq!(dx, Vec3::new(0.1, 0.0, 0.0));
+        assert_eq!(dy, Vec3::new(0.0, 0.0, 0.1));
+
+        let mut half = r;
+        half.scale_differentials(0.5);
+        let (dx, _) = half.footprint(&Vec3::zero(), &up).unwrap();
+        assert_eq!(dx, Vec3::new(0.05, 0.0, 0.0));
+
+        // a wall the rays run along
+        let side = Vec3::new(1.0, 0.0, 0.0);
+        assert!(half.footprint(&Vec3::zero(), &side).is_none());
+        let plain = Ray::new(Vec3::zero(), up.clone());
+        assert!(plain.footprint(&Vec3::zero(), &up).is_none());
+    }
+
     #[test]
     fn test_transform_1() {
         let r1 = Ray::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 1.0, 0.0));
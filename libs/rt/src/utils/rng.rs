@@ -0,0 +1,102 @@
+use super::vec3::{Float, Vec3};
+
+/// SplitMix64, small and fast with good enough statistics for sampling.
+/// Renders stay reproducible because every stream starts from an explicit
+/// seed.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Independent stream for one pixel, so results do not depend on which
+    /// thread traced it or in what order.
+    pub fn for_pixel(seed: u64, x: u32, y: u32) -> Self {
+        let mut rng = Self::new(seed ^ ((x as u64) << 32 | y as u64));
+        let state = rng.next_u64();
+        Self::new(state)
+    }
+
+    /// Stream tied to a point in space, the same point always draws the
+    /// same numbers.
+    pub fn for_point(p: &Vec3) -> Self {
+        let mut state = 0;
+        for v in [p.x, p.y, p.z] {
+            state = Self::new(state ^ v.to_bits() as u64).next_u64();
+        }
+        Self::new(state)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_float(&mut self) -> Float {
+        // 24 bits is all the mantissa of an f32 holds
+        (self.next_u64() >> 40) as Float / (1u64 << 24) as Float
+    }
+
+    /// Uniform in `[lo, hi)`.
+    pub fn range(&mut self, lo: Float, hi: Float) -> Float {
+        lo + (hi - lo) * self.next_float()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let a: Vec<u64> = (0..4)
+            .scan(Rng::new(7), |r, _| Some(r.next_u64()))
+            .collect();
+        let b: Vec<u64> = (0..4)
+            .scan(Rng::new(7), |r, _| Some(r.next_u64()))
+            .collect();
+
+        assert_eq!(a, b);
+        assert_eq!(Rng::new(0).next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_ne!(
+            Rng::for_pixel(1, 2, 3).next_u64(),
+            Rng::for_pixel(1, 3, 2).next_u64()
+        );
+    }
+
+    #[test]
+    fn test_for_point() {
+        let p = Vec3::new(0.5, -1.0, 2.0);
+        assert_eq!(
+            Rng::for_point(&p).next_u64(),
+            Rng::for_point(&p.clone()).next_u64()
+        );
+        // swapping coordinates gives another stream
+        let q = Vec3::new(-1.0, 0.5, 2.0);
+        assert_ne!(Rng::for_point(&p).next_u64(), Rng::for_point(&q).next_u64());
+    }
+
+    #[test]
+    fn test_float_range() {
+        let mut r = Rng::new(42);
+        let mut sum = 0.0;
+        for _ in 0..10_000 {
+            let f = r.next_float();
+            assert!((0.0..1.0).contains(&f));
+            sum += f;
+        }
+        assert!((sum / 10_000.0 - 0.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,56 @@
+use std::{cell::Cell, time::Duration};
+
+// Counting goes to thread locals so tracing threads do not fight over a
+// cache line, whoever traces on the thread `take`s them afterwards.
+thread_local! {
+    static RAYS: Cell<u64> = const { Cell::new(0) };
+    static TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderStats {
+    pub rays: u64,
+    pub intersection_tests: u64,
+    pub elapsed: Duration,
+}
+
+impl RenderStats {
+    /// Adds the counts of `other`, the elapsed time is left alone.
+    pub fn add_counts(&mut self, other: &RenderStats) {
+        self.rays += other.rays;
+        self.intersection_tests += other.intersection_tests;
+    }
+}
+
+impl std::fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.elapsed.as_secs_f64();
+        writeln!(f, "rays cast:          {}", self.rays)?;
+        writeln!(f, "intersection tests: {}", self.intersection_tests)?;
+        write!(f, "wall time:          {:.3}s", secs)?;
+        if secs > 0.0 {
+            write!(f, " ({:.0} rays/s)", self.rays as f64 / secs)?;
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+pub fn count_ray() {
+    RAYS.with(|c| c.set(c.get() + 1));
+}
+
+#[inline]
+pub fn count_intersection_test() {
+    TESTS.with(|c| c.set(c.get() + 1));
+}
+
+/// Counts of the calling thread since its last `take`, which start over
+/// from zero.
+pub fn take() -> RenderStats {
+    RenderStats {
+        rays: RAYS.with(|c| c.replace(0)),
+        intersection_tests: TESTS.with(|c| c.replace(0)),
+        elapsed: Duration::ZERO,
+    }
+}
@@ -0,0 +1,241 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::utils::{
+    noise::Noise,
+    vec3::{Float, Vec3},
+};
+
+use super::{
+    pattern::{ImageTexture, Pattern},
+    shapes::shape::Shape,
+};
+
+/// Bends the shading normal of a surface without moving the surface. Works
+/// on world points and normals, lookups go through `sp` into its object
+/// space so the detail sticks to the shape.
+pub trait Bump: Debug + Send + Sync {
+    fn perturb(&self, sp: &dyn Shape, p: &Vec3, n: &Vec3) -> Vec3;
+}
+
+// step of the finite differences, in world units
+const STEP: Float = 1e-3;
+
+// two unit vectors spanning the plane facing `n`
+fn tangent_plane(n: &Vec3) -> (Vec3, Vec3) {
+    let a = if n.x.abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t = a.cross(n).norm();
+    let b = n.cross(&t);
+    (t, b)
+}
+
+/// Directions the texture coordinates `uv` grow in around `p`, made
+/// orthonormal with `n`: the tangent follows `u` and the bitangent points
+/// toward growing `v`. `None` where the mapping folds, like at the poles
+/// of a spherical map.
+pub fn uv_tangents(
+    uv: impl Fn(&Vec3) -> (Float, Float),
+    p: &Vec3,
+    n: &Vec3,
+) -> Option<(Vec3, Vec3)> {
+    let (t, b) = tangent_plane(n);
+    let (u0, v0) = uv(p);
+    // steps across the seam of a wrapping map are short ones back
+    let rate = |q: Vec3| {
+        let (u, v) = uv(&q);
+        let (du, dv) = (u - u0, v - v0);
+        ((du - du.round()) / STEP, (dv - dv.round()) / STEP)
+    };
+    let (ut, vt) = rate(p + &t * STEP);
+    let (ub, vb) = rate(p + &b * STEP);
+    // invert the jacobian of (t, b) -> (u, v) for the surface direction of
+    // a unit step in u and in v
+    let det = ut * vb - ub * vt;
+    if det.abs() < 1e-6 {
+        return None;
+    }
+    let dpdu = (&t * vb - &b * vt) / det;
+    let dpdv = (&b * ut - &t * ub) / det;
+    let tangent = dpdu.norm();
+    let bitangent = n.cross(&tangent);
+    if bitangent.dot(&dpdv) < 0.0 {
+        Some((tangent, -&bitangent))
+    } else {
+        Some((tangent, bitangent))
+    }
+}
+
+/// Where a `BumpMap` reads its heights.
+#[derive(Debug, Clone)]
+pub enum Height {
+    /// Brightness of a pattern, an image texture or a procedural one.
+    Pattern(Arc<dyn Pattern>),
+    /// Raw noise values, ripples on water and the like.
+    Noise(Arc<dyn Noise>),
+}
+
+impl Height {
+    pub fn at(&self, object_point: &Vec3) -> Float {
+        match self {
+            Height::Pattern(p) => {
+                let c = p.color_at(object_point);
+                (c.x + c.y + c.z) / 3.0
+            }
+            Height::Noise(n) => n.noise(object_point),
+        }
+    }
+}
+
+/// Shades the surface as if it were pushed out along its normal by
+/// `height * scale`.
+#[derive(Debug, Clone)]
+pub struct BumpMap {
+    pub height: Height,
+    pub scale: Float,
+}
+
+impl BumpMap {
+    pub fn new(height: Height, scale: Float) -> Self {
+        Self { height, scale }
+    }
+}
+
+impl Bump for BumpMap {
+    fn perturb(&self, sp: &dyn Shape, p: &Vec3, n: &Vec3) -> Vec3 {
+        let h = |q: Vec3| self.height.at(&sp.world_to_object(&q));
+        let (t, b) = tangent_plane(n);
+        let h0 = h(p.clone());
+        let dt = (h(p + &t * STEP) - h0) / STEP * self.scale;
+        let db = (h(p + &b * STEP) - h0) / STEP * self.scale;
+        (n - t * dt - b * db).norm()
+    }
+}
+
+/// Tangent space normals stored as colors, `(0.5, 0.5, 1.0)` leaving the
+/// normal alone. The tangents come from the UV map of `image`, and
+/// `strength` scales the sideways part.
+#[derive(Debug)]
+pub struct NormalMap {
+    pub image: ImageTexture,
+    pub strength: Float,
+}
+
+impl NormalMap {
+    pub fn new(image: ImageTexture) -> Self {
+        Self {
+            image,
+            strength: 1.0,
+        }
+    }
+}
+
+impl Bump for NormalMap {
+    fn perturb(&self, sp: &dyn Shape, p: &Vec3, n: &Vec3) -> Vec3 {
+        let image = &self.image;
+        let uv = |q: &Vec3| image.map.uv(&(&image.inverse * &sp.world_to_object(q)));
+        let Some((tangent, bitangent)) = uv_tangents(uv, p, n) else {
+            return n.clone();
+        };
+        let (u, v) = uv(p);
+        let c = image.texture.sample(u, v);
+        let x = (c.x * 2.0 - 1.0) * self.strength;
+        let y = (c.y * 2.0 - 1.0) * self.strength;
+        let z = c.z * 2.0 - 1.0;
+        (tangent * x + bitangent * y + n * z).norm()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        scene::texture::{MipMap, Texture},
+        utils::noise::Perlin,
+        world::{
+            pattern::Gradient,
+            shapes::{plane::Plane, sphere::Sphere},
+            transform::Transformable,
+            uv::{spherical_map, UvMap},
+        },
+    };
+
+    fn up() -> Vec3 {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+
+    fn flat(color: Vec3) -> ImageTexture {
+        ImageTexture::new(
+            Arc::new(MipMap::single(Texture::new(1, 1, vec![color]))),
+            UvMap::Planar,
+        )
+    }
+
+    #[test]
+    fn test_uv_tangents() {
+        let planar = |q: &Vec3| UvMap::Planar.uv(q);
+        let (t, b) = uv_tangents(planar, &Vec3::new(0.3, 0.0, 0.6), &up()).unwrap();
+        assert_eq!(t, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(b, Vec3::new(0.0, 0.0, 1.0));
+
+        // on the equator of a sphere u goes round, v goes up
+        let n = Vec3::new(0.0, 0.0, -1.0);
+        let (t, b) = uv_tangents(spherical_map, &n, &n).unwrap();
+        assert_eq!(t, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(b, Vec3::new(0.0, 1.0, 0.0));
+        // across the seam at the back too
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let (t, _) = uv_tangents(spherical_map, &n, &n).unwrap();
+        assert_eq!(t, Vec3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_bump_slope() {
+        // heights rise by 1 every unit along x, pushed 0.5 out that is a
+        // slope of 1/2
+        let ramp = Gradient::new(Vec3::zero(), Vec3::from_float(1.0));
+        let bump = BumpMap::new(Height::Pattern(Arc::new(ramp)), 0.5);
+        let plane = Plane::default();
+        let n = bump.perturb(&plane, &Vec3::new(0.3, 0.0, 0.2), &up());
+
+        assert_eq!(n, Vec3::new(-0.5, 1.0, 0.0).norm());
+        // nothing changes without a scale
+        let none = BumpMap::new(bump.height.clone(), 0.0);
+        assert_eq!(none.perturb(&plane, &Vec3::new(0.3, 0.0, 0.2), &up()), up());
+    }
+
+    #[test]
+    fn test_bump_follows_shape() {
+        // ripples stay on the sphere as it moves
+        let ripples = BumpMap::new(Height::Noise(Arc::new(Perlin::new(3))), 0.3);
+        let here = Sphere::default();
+        let there = Sphere::default().translation(5.0, 0.0, 0.0);
+        let n = Vec3::new(0.6, 0.0, -0.8);
+        let a = ripples.perturb(&here, &n, &n);
+        let b = ripples.perturb(&there, &(&n + Vec3::new(5.0, 0.0, 0.0)), &n);
+
+        assert_ne!(a, n);
+        assert!((a.mag() - 1.0).abs() < 1e-5);
+        assert!((a - b).mag() < 1e-3);
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let plane = Plane::default();
+        let p = Vec3::new(0.3, 0.0, 0.6);
+
+        let neutral = NormalMap::new(flat(Vec3::new(0.5, 0.5, 1.0)));
+        assert_eq!(neutral.perturb(&plane, &p, &up()), up());
+
+        // tangent space x is the u direction of the planar map
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let tilted = NormalMap::new(flat(Vec3::new(0.5 + s / 2.0, 0.5, 0.5 + s / 2.0)));
+        assert_eq!(tilted.perturb(&plane, &p, &up()), Vec3::new(s, s, 0.0));
+
+        let mut weak = tilted;
+        weak.strength = 0.0;
+        assert_eq!(weak.perturb(&plane, &p, &up()), up());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::utils::{
+    aabb::Aabb,
+    ray::Ray,
+    vec3::{Float, Vec3},
+};
+
+const BINS: usize = 12;
+const MAX_LEAF: usize = 4;
+// relative cost of visiting a node versus testing a primitive
+const TRAVERSAL_COST: Float = 0.125;
+
+#[derive(Debug)]
+struct Node {
+    bounds: Aabb,
+    // leaf: range in `indices`, inner: `start` is the right child and the
+    // left child directly follows its parent
+    start: usize,
+    count: usize,
+}
+
+/// Bounding volume hierarchy over a list of boxes, built with the surface
+/// area heuristic. It only stores indices, the caller owns the primitives.
+#[derive(Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+struct Primitive {
+    index: usize,
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+impl Bvh {
+    /// `bounds[i]` is the box of primitive `i`, every box must be finite.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut prims: Vec<Primitive> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, b)| Primitive {
+                index,
+                centroid: b.centroid(),
+                bounds: b.clone(),
+            })
+            .collect();
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * prims.len()),
+            indices: Vec::with_capacity(prims.len()),
+        };
+        if !prims.is_empty() {
+            bvh.build_node(&mut prims);
+        }
+        bvh
+    }
+
+    /// Number of primitives the tree was built over.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn build_node(&mut self, prims: &mut [Primitive]) -> usize {
+        let bounds = prims.iter().fold(Aabb::empty(), |b, p| b.merge(&p.bounds));
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            start: 0,
+            count: 0,
+        });
+
+        let split = if prims.len() > MAX_LEAF {
+            Self::find_split(prims, &self.nodes[id].bounds)
+        } else {
+            None
+        };
+        match split {
+            Some(mid) => {
+                let (left, right) = prims.split_at_mut(mid);
+                self.build_node(left);
+                self.nodes[id].start = self.build_node(right);
+            }
+            None => {
+                self.nodes[id].start = self.indices.len();
+                self.nodes[id].count = prims.len();
+                self.indices.extend(prims.iter().map(|p| p.index));
+            }
+        }
+        id
+    }
+
+    /// Binned SAH split, partitions `prims` and returns the split position or
+    /// `None` when a leaf is cheaper.
+    fn find_split(prims: &mut [Primitive], bounds: &Aabb) -> Option<usize> {
+        let cb = prims.iter().fold(Aabb::empty(), |mut b, p| {
+            b.add_point(&p.centroid);
+            b
+        });
+        let mut best: Option<(usize, usize, Float)> = None;
+        for axis in 0..3 {
+            let (lo, hi) = (axis_of(&cb.min, axis), axis_of(&cb.max, axis));
+            if hi - lo <= 0.0 {
+                continue;
+            }
+            let mut bins: Vec<(Aabb, usize)> = vec![(Aabb::empty(), 0); BINS];
+            for p in prims.iter() {
+                let b = bin_of(axis_of(&p.centroid, axis), lo, hi);
+                bins[b].0 = bins[b].0.merge(&p.bounds);
+                bins[b].1 += 1;
+            }
+            // sweep from the right to get the cost of every right side
+            let mut right_area = [0.0; BINS];
+            let mut right_count = [0; BINS];
+            let mut acc = (Aabb::empty(), 0);
+            for i in (1..BINS).rev() {
+                acc = (acc.0.merge(&bins[i].0), acc.1 + bins[i].1);
+                right_area[i] = acc.0.surface_area();
+                right_count[i] = acc.1;
+            }
+            let mut acc = (Aabb::empty(), 0);
+            for i in 0..BINS - 1 {
+                acc = (acc.0.merge(&bins[i].0), acc.1 + bins[i].1);
+                let cost = acc.0.surface_area() * acc.1 as Float
+                    + right_area[i + 1] * right_count[i + 1] as Float;
+                if best.is_none_or(|(_, _, c)| cost < c) {
+                    best = Some((axis, i, cost));
+                }
+            }
+        }
+
+        let (axis, bin, cost) = best?;
+        let area = bounds.surface_area();
+        let leaf_cost = prims.len() as Float;
+        if area > 0.0 && TRAVERSAL_COST + cost / area >= leaf_cost {
+            return None;
+        }
+        let (lo, hi) = (axis_of(&cb.min, axis), axis_of(&cb.max, axis));
+        let mut mid = 0;
+        for i in 0..prims.len() {
+            if bin_of(axis_of(&prims[i].centroid, axis), lo, hi) <= bin {
+                prims.swap(i, mid);
+                mid += 1;
+            }
+        }
+        if mid == 0 || mid == prims.len() {
+            return None;
+        }
+        Some(mid)
+    }
+
+    /// Calls `visit` with the index of every primitive whose box the ray
+    /// enters in `[0, t_max]`, nearest boxes first. `visit` returns the new
+    /// `t_max`, so nearest or any hit queries can cut the walk short.
+    pub fn traverse(&self, ray: &Ray, mut t_max: Float, mut visit: impl FnMut(usize) -> Float) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inv_dir = Vec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+        let enter = |node: usize| {
+            let (t0, t1) = self.nodes[node].bounds.intersect(ray, &inv_dir)?;
+            if t1 < 0.0 {
+                return None;
+            }
+            Some(t0)
+        };
+
+        let mut stack: Vec<(usize, Float)> = Vec::with_capacity(64);
+        if let Some(t) = enter(0) {
+            stack.push((0, t));
+        }
+        while let Some((id, t_enter)) = stack.pop() {
+            if t_enter > t_max {
+                continue;
+            }
+            let node = &self.nodes[id];
+            if node.count > 0 {
+                for &i in &self.indices[node.start..node.start + node.count] {
+                    t_max = visit(i);
+                }
+                continue;
+            }
+            let near = (id + 1, enter(id + 1));
+            let far = (node.start, enter(node.start));
+            let (near, far) = match (near.1, far.1) {
+                (Some(a), Some(b)) if b < a => (far, near),
+                _ => (near, far),
+            };
+            // the far child is pushed first so the near one is popped next
+            for (child, t) in [far, near] {
+                if let Some(t) = t {
+                    stack.push((child, t));
+                }
+            }
+        }
+    }
+}
+
+fn axis_of(v: &Vec3, axis: usize) -> Float {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+fn bin_of(c: Float, lo: Float, hi: Float) -> usize {
+    let b = ((c - lo) / (hi - lo) * BINS as Float) as usize;
+    b.min(BINS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube_at(x: Float, y: Float, z: Float) -> Aabb {
+        Aabb::new(
+            Vec3::new(x - 0.5, y - 0.5, z - 0.5),
+            Vec3::new(x + 0.5, y + 0.5, z + 0.5),
+        )
+    }
+
+    #[test]
+    fn test_build_all_indices() {
+        let boxes: Vec<Aabb> = (0..100).map(|i| cube_at(i as Float, 0.0, 0.0)).collect();
+        let bvh = Bvh::build(&boxes);
+        let mut seen = bvh.indices.clone();
+        seen.sort();
+
+        assert_eq!(bvh.len(), 100);
+        assert_eq!(seen, (0..100).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_traverse_front_to_back() {
+        let boxes: Vec<Aabb> = (0..50)
+            .map(|i| cube_at(0.0, 0.0, i as Float * 2.0))
+            .collect();
+        let bvh = Bvh::build(&boxes);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut order = Vec::new();
+        bvh.traverse(&ray, Float::INFINITY, |i| {
+            order.push(i);
+            Float::INFINITY
+        });
+
+        assert_eq!(order, (0..50).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_traverse_cut_short() {
+        let boxes: Vec<Aabb> = (0..50)
+            .map(|i| cube_at(0.0, 0.0, i as Float * 2.0))
+            .collect();
+        let bvh = Bvh::build(&boxes);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut visited = 0;
+        bvh.traverse(&ray, Float::INFINITY, |_| {
+            visited += 1;
+            Float::NEG_INFINITY
+        });
+
+        assert!(visited <= MAX_LEAF);
+    }
+
+    #[test]
+    fn test_traverse_miss() {
+        let boxes: Vec<Aabb> = (0..20).map(|i| cube_at(i as Float, 0.0, 0.0)).collect();
+        let bvh = Bvh::build(&boxes);
+        let ray = Ray::new(Vec3::new(0.0, 5.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut visited = 0;
+        bvh.traverse(&ray, Float::INFINITY, |_| {
+            visited += 1;
+            Float::INFINITY
+        });
+
+        assert_eq!(visited, 0);
+    }
+}
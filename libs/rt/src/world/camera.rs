@@ -1,11 +1,83 @@
 use crate::utils::{
     matrix::Mat,
-    ray::Ray,
+    ray::{Differentials, Ray},
     vec3::{Float, Vec3},
 };
 
+use std::f32::consts::{FRAC_PI_2, PI};
+
 use super::transform::Transformable;
 
+/// Shape of the lens opening, which is also the shape out of focus
+/// highlights take.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aperture {
+    Circle,
+    /// Regular polygon with `blades` sides, turned by `rotation` radians.
+    Polygon {
+        blades: u32,
+        rotation: Float,
+    },
+}
+
+impl Aperture {
+    /// Maps a point of the unit square onto the aperture of radius 1,
+    /// uniformly and keeping stratified samples stratified.
+    pub fn sample(&self, u: Float, v: Float) -> (Float, Float) {
+        match *self {
+            Aperture::Circle => concentric_disk(u, v),
+            Aperture::Polygon { blades, rotation } => {
+                let n = blades.max(3);
+                let side = ((u * n as Float) as u32).min(n - 1);
+                let u = u * n as Float - side as Float;
+                let step = 2.0 * std::f32::consts::PI / n as Float;
+                let a0 = rotation + step * side as Float;
+                let a1 = a0 + step;
+                // uniform point in the triangle made by the center and a side
+                let s = u.sqrt();
+                (
+                    s * ((1.0 - v) * a0.cos() + v * a1.cos()),
+                    s * ((1.0 - v) * a0.sin() + v * a1.sin()),
+                )
+            }
+        }
+    }
+}
+
+// Shirley and Chiu's mapping, squares go to rings so strata stay compact
+fn concentric_disk(u: Float, v: Float) -> (Float, Float) {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let quarter = std::f32::consts::FRAC_PI_4;
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, quarter * (b / a))
+    } else {
+        (b, 2.0 * quarter - quarter * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// How image positions turn into ray directions.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    /// Pinhole through the `fov` the camera was made with, the only one
+    /// that uses the lens.
+    #[default]
+    Perspective,
+    /// Parallel rays, `width` is how much of the world fits across the
+    /// image.
+    Orthographic { width: Float },
+    /// Equidistant fisheye, the angle from the view axis grows linearly
+    /// with the distance from the image center and `fov` spans the image
+    /// width.
+    Fisheye { fov: Float },
+    /// Longitude across the width and latitude down the height, covering
+    /// the full sphere around the camera.
+    Equirectangular,
+}
+
 #[derive(Debug)]
 pub struct Camera {
     pub width: u32,
@@ -17,6 +89,11 @@ pub struct Camera {
     t: Mat,
     inverse: Mat,
     fov: f32,
+    // thin lens, a radius of 0 is a pinhole and everything is sharp
+    pub aperture_radius: Float,
+    pub focal_distance: Float,
+    pub aperture: Aperture,
+    pub projection: Projection,
 }
 
 impl Camera {
@@ -33,6 +110,10 @@ impl Camera {
             inverse: t.inverse(),
             t,
             fov,
+            aperture_radius: 0.0,
+            focal_distance: 1.0,
+            aperture: Aperture::Circle,
+            projection: Projection::Perspective,
         }
     }
 
@@ -52,12 +133,87 @@ impl Camera {
     }
 
     pub fn get_ray(&self, x: u32, y: u32) -> Ray {
-        let x = -(self.x_step * (x as Float) - 1.0) * self.ar * self.angle;
-        let y = -(self.y_step * (y as Float) - 1.0) * self.angle;
+        self.get_ray_at(x as Float, y as Float)
+    }
 
-        let p = &self.inverse * &Vec3::new(x, y, -1.0);
-        let o = &self.inverse * &Vec3::from_float(0.0);
-        let dir = (p - &o).norm();
+    /// Ray through a point of the image plane given in pixels, so fractions
+    /// land inside a pixel. Leaves from the center of the lens.
+    pub fn get_ray_at(&self, x: Float, y: Float) -> Ray {
+        self.get_lens_ray(x, y, 0.5, 0.5)
+    }
+
+    /// Same as `get_ray_at` from the point `(u, v)` of the unit square mapped
+    /// onto the aperture. All rays through a pixel meet again on the focal
+    /// plane. The ray carries differentials toward the next pixel right and
+    /// down, through the same point of the lens.
+    pub fn get_lens_ray(&self, x: Float, y: Float, u: Float, v: Float) -> Ray {
+        let rx = self.lens_ray(x + 1.0, y, u, v);
+        let ry = self.lens_ray(x, y + 1.0, u, v);
+        self.lens_ray(x, y, u, v).with_differentials(Differentials {
+            rx_org: rx.org,
+            rx_dir: rx.dir,
+            ry_org: ry.org,
+            ry_dir: ry.dir,
+        })
+    }
+
+    fn lens_ray(&self, x: Float, y: Float, u: Float, v: Float) -> Ray {
+        // image position in [-1, 1], positive towards the top left like the
+        // camera space x and y
+        let sx = 1.0 - self.x_step * x;
+        let sy = 1.0 - self.y_step * y;
+        let (o, dir) = match self.projection {
+            Projection::Perspective => return self.perspective_ray(sx, sy, u, v),
+            Projection::Orthographic { width } => {
+                let half = width * 0.5;
+                (
+                    Vec3::new(sx * half, sy * half / self.ar, 0.0),
+                    Vec3::new(0.0, 0.0, -1.0),
+                )
+            }
+            Projection::Fisheye { fov } => {
+                let sy = sy / self.ar;
+                let r = (sx * sx + sy * sy).sqrt();
+                let theta = (r * fov * 0.5).min(PI);
+                let (ux, uy) = if r > 0.0 {
+                    (sx / r, sy / r)
+                } else {
+                    (0.0, 0.0)
+                };
+                (
+                    Vec3::zero(),
+                    Vec3::new(theta.sin() * ux, theta.sin() * uy, -theta.cos()),
+                )
+            }
+            Projection::Equirectangular => {
+                let (lon, lat) = (sx * PI, sy * FRAC_PI_2);
+                (
+                    Vec3::zero(),
+                    Vec3::new(lat.cos() * lon.sin(), lat.sin(), -lat.cos() * lon.cos()),
+                )
+            }
+        };
+        let o = &self.inverse * &o;
+        let dir = (&self.inverse ^ &dir).norm();
+        Ray::new(o, dir)
+    }
+
+    fn perspective_ray(&self, sx: Float, sy: Float, u: Float, v: Float) -> Ray {
+        let x = sx * self.ar * self.angle;
+        let y = sy * self.angle;
+
+        if self.aperture_radius <= 0.0 {
+            let p = &self.inverse * &Vec3::new(x, y, -1.0);
+            let o = &self.inverse * &Vec3::from_float(0.0);
+            let dir = (p - &o).norm();
+            return Ray::new(o, dir);
+        }
+        let f = self.focal_distance;
+        let focus = &self.inverse * &Vec3::new(x * f, y * f, -f);
+        let (lx, ly) = self.aperture.sample(u, v);
+        let r = self.aperture_radius;
+        let o = &self.inverse * &Vec3::new(lx * r, ly * r, 0.0);
+        let dir = (focus - &o).norm();
         Ray::new(o, dir)
     }
 }
@@ -69,3 +225,175 @@ impl Transformable for Camera {
         self.inverse = self.t.inverse();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    fn lens_camera(aperture: Aperture) -> Camera {
+        let mut c = Camera::new(
+            40,
+            30,
+            1.0,
+            Mat::view_transformation(
+                &Vec3::new(0.0, 1.0, -5.0),
+                &Vec3::new(0.0, 1.0, 0.0),
+                &Vec3::new(0.0, 1.0, 0.0),
+            ),
+        );
+        c.aperture_radius = 0.25;
+        c.focal_distance = 5.0;
+        c.aperture = aperture;
+        c
+    }
+
+    #[test]
+    fn test_focal_plane_is_sharp() {
+        let apertures = [
+            Aperture::Circle,
+            Aperture::Polygon {
+                blades: 6,
+                rotation: 0.3,
+            },
+        ];
+        for aperture in apertures {
+            let c = lens_camera(aperture);
+            let center = c.get_ray_at(13.5, 21.5);
+            // the focal plane is z = 0 in world space, 5 units from the eye
+            let t = -center.org.z / center.dir.z;
+            let expected = center.position(t);
+            for (u, v) in [(0.1, 0.2), (0.9, 0.5), (0.3, 0.95)] {
+                let r = c.get_lens_ray(13.5, 21.5, u, v);
+                assert_ne!(r.org, center.org);
+                assert_eq!(r.position(-r.org.z / r.dir.z), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_out_of_focus_points_spread() {
+        let c = lens_camera(Aperture::Circle);
+        let plane = |r: Ray| r.position((5.0 - r.org.z) / r.dir.z);
+        let a = plane(c.get_lens_ray(20.0, 15.0, 0.0, 0.5));
+        let b = plane(c.get_lens_ray(20.0, 15.0, 1.0, 0.5));
+
+        assert!((&a - &b).mag() > 0.4);
+    }
+
+    #[test]
+    fn test_differentials() {
+        let c = lens_camera(Aperture::Circle);
+        let r = c.get_lens_ray(3.0, 4.0, 0.2, 0.7);
+        let d = r.diff.as_ref().unwrap();
+        let rx = c.get_lens_ray(4.0, 4.0, 0.2, 0.7);
+        let ry = c.get_lens_ray(3.0, 5.0, 0.2, 0.7);
+
+        assert_eq!((&d.rx_org, &d.rx_dir), (&rx.org, &rx.dir));
+        assert_eq!((&d.ry_org, &d.ry_dir), (&ry.org, &ry.dir));
+    }
+
+    #[test]
+    fn test_pinhole_ignores_lens() {
+        let mut c = lens_camera(Aperture::Circle);
+        c.aperture_radius = 0.0;
+        let a = c.get_lens_ray(3.0, 4.0, 0.1, 0.9);
+        let b = c.get_ray(3, 4);
+
+        assert_eq!(a.org, b.org);
+        assert_eq!(a.dir, b.dir);
+    }
+
+    fn projected(projection: Projection) -> Camera {
+        // looking down -x from above the origin
+        let mut c = Camera::new(
+            200,
+            100,
+            1.0,
+            Mat::view_transformation(
+                &Vec3::new(0.0, 2.0, 0.0),
+                &Vec3::new(-1.0, 2.0, 0.0),
+                &Vec3::new(0.0, 1.0, 0.0),
+            ),
+        );
+        c.projection = projection;
+        c
+    }
+
+    #[test]
+    fn test_orthographic() {
+        let c = projected(Projection::Orthographic { width: 8.0 });
+        let center = c.get_ray_at(100.0, 50.0);
+        let corner = c.get_ray_at(0.0, 0.0);
+
+        assert_eq!(center.org, Vec3::new(0.0, 2.0, 0.0));
+        assert_eq!(center.dir, Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(corner.dir, center.dir);
+        assert_eq!(
+            (&corner.org - &center.org).mag(),
+            (16.0 + 4.0 as Float).sqrt()
+        );
+        assert_eq!(corner.org.y, 4.0);
+    }
+
+    #[test]
+    fn test_fisheye() {
+        let c = projected(Projection::Fisheye { fov: PI });
+        let axis = Vec3::new(-1.0, 0.0, 0.0);
+
+        assert_eq!(c.get_ray_at(100.0, 50.0).dir, axis);
+        // the image edges are 90 degrees off axis
+        assert!(c.get_ray_at(0.0, 50.0).dir.dot(&axis).abs() < 1e-6);
+        // fov spans the width, the image is half as tall
+        let top = c.get_ray_at(100.0, 0.0).dir;
+        assert_eq!(top, Vec3::new(-FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0));
+        let half_way = c.get_ray_at(50.0, 50.0).dir;
+        assert!((half_way.dot(&axis).acos() - PI / 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_equirectangular() {
+        let c = projected(Projection::Equirectangular);
+        let dir = |x: Float, y: Float| c.get_ray_at(x, y).dir;
+
+        assert_eq!(dir(100.0, 50.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(dir(0.0, 50.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(dir(100.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(dir(100.0, 100.0), Vec3::new(0.0, -1.0, 0.0));
+        // a quarter turn to the left and to the right
+        assert_eq!(dir(50.0, 50.0), -&dir(150.0, 50.0));
+        assert!(dir(50.0, 50.0).x.abs() < 1e-6);
+        assert_eq!(c.get_ray_at(30.0, 20.0).org, Vec3::new(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn test_aperture_shapes() {
+        let hexagon = Aperture::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        };
+        // the inner radius of a hexagon of radius 1
+        let apothem = (std::f32::consts::PI / 6.0).cos();
+        let mut outside_apothem = 0;
+        for i in 0..16 {
+            for j in 0..16 {
+                let (u, v) = ((i as Float + 0.5) / 16.0, (j as Float + 0.5) / 16.0);
+                let (x, y) = Aperture::Circle.sample(u, v);
+                assert!(x * x + y * y <= 1.0 + 1e-5);
+
+                let (x, y) = hexagon.sample(u, v);
+                let r = (x * x + y * y).sqrt();
+                assert!(r <= 1.0 + 1e-5);
+                // a point past the apothem has to sit near a corner
+                if r > apothem + 1e-3 {
+                    outside_apothem += 1;
+                    let corner = (y.atan2(x) / (std::f32::consts::PI / 3.0)).round();
+                    let to_corner = y.atan2(x) - corner * std::f32::consts::PI / 3.0;
+                    assert!(to_corner.abs() < 0.53);
+                }
+            }
+        }
+        assert!(outside_apothem > 0);
+        assert_eq!(Aperture::Circle.sample(0.5, 0.5), (0.0, 0.0));
+    }
+}
@@ -1,21 +1,450 @@
-use crate::utils::{ray::Ray, vec3::Vec3};
+use std::fmt::Debug;
 
-#[derive(Debug)]
-pub struct Light {
+use crate::utils::{
+    rng::Rng,
+    vec3::{Float, Vec3, EPSILON},
+};
+
+use super::camera::Aperture;
+
+/// Light arriving at a shaded point from one point of a light.
+#[derive(Debug, Clone)]
+pub struct LightSample {
+    /// Unit vector from the shaded point toward the light.
+    pub dir: Vec3,
+    /// How far a shadow ray has to go, infinite for directional lights.
+    pub distance: Float,
+    /// Color reaching the point, after falloff.
+    pub intensity: Vec3,
+}
+
+pub trait Light: Debug + Send + Sync {
+    /// Color of the light before any falloff, the ambient term uses it.
+    fn intensity(&self) -> &Vec3;
+
+    /// Samples to average when shading `hitp`, lights with hard shadows
+    /// return a single one.
+    fn samples(&self, hitp: &Vec3) -> Vec<LightSample>;
+}
+
+fn sample_from(
+    hitp: &Vec3,
+    position: &Vec3,
+    intensity: &Vec3,
+    inverse_square: bool,
+) -> LightSample {
+    let to_light = position - hitp;
+    let distance = to_light.mag();
+    let intensity = if inverse_square {
+        intensity / (distance * distance)
+    } else {
+        intensity.clone()
+    };
+    LightSample {
+        dir: to_light / distance,
+        distance,
+        intensity,
+    }
+}
+
+/// Light spreading from a single position in every direction, shadows it
+/// casts have hard edges.
+#[derive(Debug, Clone)]
+pub struct PointLight {
     pub position: Vec3,
     pub intensity: Vec3,
+    /// Divides the intensity by the squared distance, off by default to keep
+    /// the look of the older scenes.
+    pub inverse_square: bool,
 }
 
-impl Light {
+impl PointLight {
     pub fn new(position: Vec3, intensity: Vec3) -> Self {
         Self {
             position,
             intensity,
+            inverse_square: false,
+        }
+    }
+
+    pub fn inverse_square(mut self, inverse_square: bool) -> Self {
+        self.inverse_square = inverse_square;
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn intensity(&self) -> &Vec3 {
+        &self.intensity
+    }
+
+    fn samples(&self, hitp: &Vec3) -> Vec<LightSample> {
+        vec![sample_from(
+            hitp,
+            &self.position,
+            &self.intensity,
+            self.inverse_square,
+        )]
+    }
+}
+
+/// The surface an `AreaLight` emits from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LightShape {
+    /// Parallelogram centered on the light position with edges `u` and `v`,
+    /// split into `usteps` x `vsteps` cells that get one sample each.
+    Rect {
+        u: Vec3,
+        v: Vec3,
+        usteps: usize,
+        vsteps: usize,
+    },
+    /// Ball around the light position. Seen from the shaded point it is a
+    /// disk, which is where the `samples` go.
+    Sphere { radius: Float, samples: usize },
+}
+
+/// Light from a whole surface. It is sampled at several points and the
+/// shadow test averages over them, which gives soft shadow edges.
+#[derive(Debug, Clone)]
+pub struct AreaLight {
+    pub position: Vec3,
+    pub intensity: Vec3,
+    pub shape: LightShape,
+    /// Random point in every cell when set, cell centers otherwise. Jittered
+    /// points only depend on the shaded point so renders stay reproducible.
+    pub jitter: bool,
+    pub inverse_square: bool,
+}
+
+impl AreaLight {
+    pub fn new(position: Vec3, shape: LightShape, intensity: Vec3) -> Self {
+        Self {
+            position,
+            intensity,
+            shape,
+            jitter: true,
+            inverse_square: false,
+        }
+    }
+
+    pub fn rect(
+        center: Vec3,
+        u: Vec3,
+        usteps: usize,
+        v: Vec3,
+        vsteps: usize,
+        intensity: Vec3,
+    ) -> Self {
+        let shape = LightShape::Rect {
+            u,
+            v,
+            usteps: usteps.max(1),
+            vsteps: vsteps.max(1),
+        };
+        Self::new(center, shape, intensity)
+    }
+
+    pub fn sphere(center: Vec3, radius: Float, samples: usize, intensity: Vec3) -> Self {
+        let shape = LightShape::Sphere {
+            radius,
+            samples: samples.max(1),
+        };
+        Self::new(center, shape, intensity)
+    }
+
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn inverse_square(mut self, inverse_square: bool) -> Self {
+        self.inverse_square = inverse_square;
+        self
+    }
+
+    /// Points on the light to test the visibility of `hitp` against.
+    pub fn points(&self, hitp: &Vec3) -> Vec<Vec3> {
+        let mut rng = Rng::for_point(hitp);
+        let mut offset = || if self.jitter { rng.next_float() } else { 0.5 };
+        match &self.shape {
+            LightShape::Rect {
+                u,
+                v,
+                usteps,
+                vsteps,
+            } => {
+                let mut points = Vec::with_capacity(usteps * vsteps);
+                for j in 0..*vsteps {
+                    for i in 0..*usteps {
+                        let s = (i as Float + offset()) / *usteps as Float - 0.5;
+                        let t = (j as Float + offset()) / *vsteps as Float - 0.5;
+                        points.push(&self.position + u * s + v * t);
+                    }
+                }
+                points
+            }
+            LightShape::Sphere { radius, samples } => {
+                // disk facing the shaded point, which has no direction from
+                // the center itself
+                let w = hitp - &self.position;
+                if w.mag() < EPSILON {
+                    return vec![self.position.clone()];
+                }
+                let w = w.norm();
+                let a = if w.x.abs() > 0.9 {
+                    Vec3::new(0.0, 1.0, 0.0)
+                } else {
+                    Vec3::new(1.0, 0.0, 0.0)
+                };
+                let t = w.cross(&a).norm() * *radius;
+                let b = w.cross(&t);
+                let cols = (*samples as Float).sqrt().ceil() as usize;
+                let rows = samples.div_ceil(cols);
+                (0..*samples)
+                    .map(|i| {
+                        let su = ((i % cols) as Float + offset()) / cols as Float;
+                        let sv = ((i / cols) as Float + offset()) / rows as Float;
+                        let (x, y) = Aperture::Circle.sample(su, sv);
+                        &self.position + &t * x + &b * y
+                    })
+                    .collect()
+            }
         }
     }
+}
+
+impl Light for AreaLight {
+    fn intensity(&self) -> &Vec3 {
+        &self.intensity
+    }
+
+    fn samples(&self, hitp: &Vec3) -> Vec<LightSample> {
+        self.points(hitp)
+            .iter()
+            .map(|p| sample_from(hitp, p, &self.intensity, self.inverse_square))
+            .collect()
+    }
+}
+
+/// Point light limited to a cone around `direction`. Inside the `inner`
+/// angle it is at full strength, it fades out smoothly until `outer`.
+#[derive(Debug, Clone)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    // half angles from the axis in radians
+    pub inner: Float,
+    pub outer: Float,
+    pub inverse_square: bool,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Vec3,
+        direction: Vec3,
+        inner: Float,
+        outer: Float,
+        intensity: Vec3,
+    ) -> Self {
+        Self {
+            position,
+            direction: direction.norm(),
+            intensity,
+            inner,
+            outer: outer.max(inner),
+            inverse_square: false,
+        }
+    }
+
+    pub fn inverse_square(mut self, inverse_square: bool) -> Self {
+        self.inverse_square = inverse_square;
+        self
+    }
+
+    /// How much of the light goes out along `dir`, from 0.0 outside the
+    /// cone to 1.0 inside the inner angle.
+    pub fn cone_falloff(&self, dir: &Vec3) -> Float {
+        let (cos_inner, cos_outer) = (self.inner.cos(), self.outer.cos());
+        let cos = dir.dot(&self.direction);
+        if cos >= cos_inner {
+            return 1.0;
+        }
+        if cos <= cos_outer {
+            return 0.0;
+        }
+        let t = (cos - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn intensity(&self) -> &Vec3 {
+        &self.intensity
+    }
+
+    fn samples(&self, hitp: &Vec3) -> Vec<LightSample> {
+        let mut s = sample_from(hitp, &self.position, &self.intensity, self.inverse_square);
+        s.intensity = s.intensity * self.cone_falloff(&-&s.dir);
+        vec![s]
+    }
+}
+
+/// Light from infinitely far away such as the sun, every point sees it
+/// from the same direction and shadow rays are parallel.
+#[derive(Debug, Clone)]
+pub struct DirectionalLight {
+    /// Where the light travels to.
+    pub direction: Vec3,
+    pub intensity: Vec3,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, intensity: Vec3) -> Self {
+        Self {
+            direction: direction.norm(),
+            intensity,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn intensity(&self) -> &Vec3 {
+        &self.intensity
+    }
+
+    fn samples(&self, _hitp: &Vec3) -> Vec<LightSample> {
+        vec![LightSample {
+            dir: -&self.direction,
+            distance: Float::INFINITY,
+            intensity: self.intensity.clone(),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_light_has_one_sample() {
+        let l = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::from_float(1.0));
+        let samples = l.samples(&Vec3::zero());
+        assert_eq!(samples.len(), 1);
+        assert_eq!(
+            &samples[0].dir * samples[0].distance,
+            Vec3::new(0.0, 2.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_rect_samples() {
+        let l = AreaLight::rect(
+            Vec3::new(0.0, 5.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            2,
+            Vec3::new(0.0, 0.0, 1.0),
+            2,
+            Vec3::from_float(1.0),
+        );
+        let hitp = Vec3::new(0.3, 0.0, -0.7);
+        assert_eq!(
+            l.jitter(false).points(&hitp),
+            vec![
+                Vec3::new(-0.5, 5.0, -0.25),
+                Vec3::new(0.5, 5.0, -0.25),
+                Vec3::new(-0.5, 5.0, 0.25),
+                Vec3::new(0.5, 5.0, 0.25),
+            ]
+        );
+
+        let l = AreaLight::rect(
+            Vec3::zero(),
+            Vec3::new(2.0, 0.0, 0.0),
+            4,
+            Vec3::new(0.0, 2.0, 0.0),
+            4,
+            Vec3::from_float(1.0),
+        );
+        let points = l.points(&hitp);
+        assert_eq!(points, l.points(&hitp));
+        assert_ne!(points, l.points(&Vec3::new(0.3, 0.0, -0.6)));
+        for (k, p) in points.iter().enumerate() {
+            // every sample stays in its own cell
+            let (i, j) = ((k % 4) as Float, (k / 4) as Float);
+            assert!(p.x >= i * 0.5 - 1.0 && p.x <= i * 0.5 - 0.5);
+            assert!(p.y >= j * 0.5 - 1.0 && p.y <= j * 0.5 - 0.5);
+        }
+    }
+
+    #[test]
+    fn test_sphere_samples_face_the_point() {
+        let l = AreaLight::sphere(Vec3::new(0.0, 10.0, 0.0), 2.0, 9, Vec3::from_float(1.0));
+        for jitter in [false, true] {
+            let l = AreaLight {
+                jitter,
+                ..l.clone()
+            };
+            let points = l.points(&Vec3::zero());
+            assert_eq!(points.len(), 9);
+            for p in points {
+                assert!((p.y - 10.0).abs() < 1e-5);
+                assert!(p.x * p.x + p.z * p.z <= 4.0 + 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sphere_sample_at_center() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let l = AreaLight::sphere(center.clone(), 0.5, 4, Vec3::from_float(1.0));
+        assert_eq!(l.points(&center), vec![center]);
+    }
+
+    #[test]
+    fn test_inverse_square() {
+        let l = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::from_float(8.0));
+        let s = &l.samples(&Vec3::zero())[0];
+        assert_eq!(s.dir, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(s.distance, 2.0);
+        assert_eq!(s.intensity, Vec3::from_float(8.0));
+
+        let s = &l.inverse_square(true).samples(&Vec3::zero())[0];
+        assert_eq!(s.intensity, Vec3::from_float(2.0));
+    }
+
+    #[test]
+    fn test_spot_cone() {
+        let quarter = std::f32::consts::FRAC_PI_4;
+        let l = SpotLight::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            quarter / 2.0,
+            quarter,
+            Vec3::from_float(1.0),
+        );
+        let at = |x: Float| l.samples(&Vec3::new(x, 0.0, 0.0))[0].intensity.x;
+
+        assert_eq!(at(0.0), 1.0);
+        assert_eq!(at(0.3), 1.0);
+        // between the two angles, 45 degrees is the outer edge
+        let mid = at(0.6);
+        assert!(mid > 0.0 && mid < 1.0);
+        assert!(at(0.8) < mid);
+        assert_eq!(at(1.01), 0.0);
+    }
+
+    #[test]
+    fn test_directional() {
+        let l = DirectionalLight::new(Vec3::new(1.0, -1.0, 0.0), Vec3::from_float(1.0));
+        let a = &l.samples(&Vec3::zero())[0];
+        let b = &l.samples(&Vec3::new(100.0, -3.0, 7.0))[0];
+        let f = std::f32::consts::FRAC_1_SQRT_2;
 
-    pub fn ray_at(&self, hitp: &Vec3) -> Ray {
-        let light_dir = (hitp - &self.position).norm();
-        Ray::new(hitp.clone(), light_dir)
+        assert_eq!(a.dir, Vec3::new(-f, f, 0.0));
+        assert_eq!(a.dir, b.dir);
+        assert_eq!(a.distance, Float::INFINITY);
+        assert_eq!(b.intensity, Vec3::from_float(1.0));
     }
 }
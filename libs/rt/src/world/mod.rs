@@ -1,5 +1,9 @@
+pub mod bump;
+pub mod bvh;
 pub mod camera;
 pub mod light;
+pub mod pattern;
 pub mod transform;
+pub mod uv;
 pub mod w;
 pub mod shapes;
@@ -0,0 +1,593 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{
+    scene::texture::MipMap,
+    utils::{
+        matrix::Mat,
+        noise::{Noise, Perlin},
+        vec3::{Float, Vec3},
+    },
+};
+
+use super::{transform::Transformable, uv::UvMap};
+
+/// Color that varies over a surface. Patterns have their own transform on
+/// top of the shape's one: a world point goes to object space through the
+/// shape, then to pattern space through `inverse`.
+pub trait Pattern: Debug + Send + Sync {
+    /// Color at a point in pattern space.
+    fn pattern_at(&self, p: &Vec3) -> Vec3;
+
+    fn inverse(&self) -> &Mat;
+
+    /// Color at a point in the object space of the shape wearing it. Nested
+    /// patterns get called with their parent's pattern space instead.
+    fn color_at(&self, object_point: &Vec3) -> Vec3 {
+        self.pattern_at(&(self.inverse() * object_point))
+    }
+
+    /// `color_at` averaged over the object space patch `dpdx` by `dpdy`
+    /// around the point. Only image textures filter, procedural patterns
+    /// are point sampled.
+    fn filtered_color_at(&self, object_point: &Vec3, _dpdx: &Vec3, _dpdy: &Vec3) -> Vec3 {
+        self.color_at(object_point)
+    }
+}
+
+macro_rules! transformable_pattern {
+    ($($name:ident),*) => {
+        $(
+            impl $name {
+                pub fn set_transform(&mut self, m: Mat) {
+                    self.inverse = m.inverse();
+                    self.t = m;
+                }
+            }
+
+            impl Transformable for $name {
+                fn apply_transform(&mut self, transform: &Mat) {
+                    self.t = &self.t * transform;
+                    self.inverse = self.t.inverse();
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! two_pattern {
+    ($($name:ident),*) => {
+        $(
+            impl $name {
+                pub fn new(a: Vec3, b: Vec3) -> Self {
+                    Self::nested(Arc::new(Solid::new(a)), Arc::new(Solid::new(b)))
+                }
+
+                /// Same layout with a pattern in place of each color.
+                pub fn nested(a: Arc<dyn Pattern>, b: Arc<dyn Pattern>) -> Self {
+                    Self {
+                        a,
+                        b,
+                        t: Mat::identity(4),
+                        inverse: Mat::identity(4),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+// whether `v` falls in an even unit band
+fn even(v: Float) -> bool {
+    (v.floor() as i64).rem_euclid(2) == 0
+}
+
+fn lerp(a: &Vec3, b: &Vec3, f: Float) -> Vec3 {
+    a + (b - a) * f
+}
+
+/// One color everywhere, what plain colors turn into inside other patterns.
+#[derive(Debug)]
+pub struct Solid {
+    pub color: Vec3,
+    inverse: Mat,
+}
+
+impl Solid {
+    pub fn new(color: Vec3) -> Self {
+        Self {
+            color,
+            inverse: Mat::identity(4),
+        }
+    }
+}
+
+impl Pattern for Solid {
+    fn pattern_at(&self, _p: &Vec3) -> Vec3 {
+        self.color.clone()
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+
+    fn color_at(&self, _object_point: &Vec3) -> Vec3 {
+        self.color.clone()
+    }
+}
+
+/// Alternates `a` and `b` every unit along x.
+#[derive(Debug)]
+pub struct Stripe {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl Pattern for Stripe {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        if even(p.x) {
+            self.a.color_at(p)
+        } else {
+            self.b.color_at(p)
+        }
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+}
+
+/// Blends from `a` to `b` along x, starting over every unit.
+#[derive(Debug)]
+pub struct Gradient {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl Pattern for Gradient {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        lerp(&self.a.color_at(p), &self.b.color_at(p), p.x - p.x.floor())
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+}
+
+/// Concentric rings around the y axis, one unit wide.
+#[derive(Debug)]
+pub struct Ring {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl Pattern for Ring {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        if even((p.x * p.x + p.z * p.z).sqrt()) {
+            self.a.color_at(p)
+        } else {
+            self.b.color_at(p)
+        }
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+}
+
+/// Unit cubes of alternating color, so it works on any surface and not only
+/// on planes.
+#[derive(Debug)]
+pub struct Checker {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl Pattern for Checker {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        let sum = p.x.floor() + p.y.floor() + p.z.floor();
+        if even(sum) {
+            self.a.color_at(p)
+        } else {
+            self.b.color_at(p)
+        }
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+}
+
+/// Gradient that follows the rings, blending over every unit of distance
+/// from the y axis.
+#[derive(Debug)]
+pub struct RadialGradient {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl Pattern for RadialGradient {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        let d = (p.x * p.x + p.z * p.z).sqrt();
+        lerp(&self.a.color_at(p), &self.b.color_at(p), d - d.floor())
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+}
+
+/// Average of two patterns, both are visible everywhere.
+#[derive(Debug)]
+pub struct Blend {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl Blend {
+    pub fn new(a: Arc<dyn Pattern>, b: Arc<dyn Pattern>) -> Self {
+        Self {
+            a,
+            b,
+            t: Mat::identity(4),
+            inverse: Mat::identity(4),
+        }
+    }
+}
+
+impl Pattern for Blend {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        (self.a.color_at(p) + self.b.color_at(p)) * 0.5
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+}
+
+/// Moves the lookup point of `pattern` by up to about `scale` units along
+/// Perlin noise, which makes straight edges wobble.
+#[derive(Debug)]
+pub struct Perturb {
+    pub pattern: Arc<dyn Pattern>,
+    pub scale: Float,
+    pub noise: Perlin,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl Perturb {
+    pub fn new(pattern: Arc<dyn Pattern>, scale: Float, seed: u64) -> Self {
+        Self {
+            pattern,
+            scale,
+            noise: Perlin::new(seed),
+            t: Mat::identity(4),
+            inverse: Mat::identity(4),
+        }
+    }
+}
+
+impl Pattern for Perturb {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        self.pattern
+            .color_at(&(p + self.noise.vector(p) * self.scale))
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+}
+
+/// Goes from `a` where `noise` is lowest to `b` where it is highest.
+#[derive(Debug)]
+pub struct NoisePattern {
+    pub noise: Arc<dyn Noise>,
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl NoisePattern {
+    pub fn new(noise: Arc<dyn Noise>, a: Vec3, b: Vec3) -> Self {
+        Self::nested(noise, Arc::new(Solid::new(a)), Arc::new(Solid::new(b)))
+    }
+
+    pub fn nested(noise: Arc<dyn Noise>, a: Arc<dyn Pattern>, b: Arc<dyn Pattern>) -> Self {
+        Self {
+            noise,
+            a,
+            b,
+            t: Mat::identity(4),
+            inverse: Mat::identity(4),
+        }
+    }
+}
+
+impl Pattern for NoisePattern {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        let f = self.noise.remap(p, 0.0, 1.0);
+        lerp(&self.a.color_at(p), &self.b.color_at(p), f)
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+}
+
+/// Image wrapped around the shape through `map`.
+#[derive(Debug)]
+pub struct ImageTexture {
+    pub texture: Arc<MipMap>,
+    pub map: UvMap,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+impl ImageTexture {
+    pub fn new(texture: Arc<MipMap>, map: UvMap) -> Self {
+        Self {
+            texture,
+            map,
+            t: Mat::identity(4),
+            inverse: Mat::identity(4),
+        }
+    }
+}
+
+impl Pattern for ImageTexture {
+    fn pattern_at(&self, p: &Vec3) -> Vec3 {
+        let (u, v) = self.map.uv(p);
+        self.texture.sample(u, v)
+    }
+
+    fn inverse(&self) -> &Mat {
+        &self.inverse
+    }
+
+    fn filtered_color_at(&self, object_point: &Vec3, dpdx: &Vec3, dpdy: &Vec3) -> Vec3 {
+        let p = &self.inverse * object_point;
+        let (u, v) = self.map.uv(&p);
+        // the maps wrap around, a step across the seam is a short one back
+        let step = |d: &Vec3| {
+            let (du, dv) = self.map.uv(&(&p + (&self.inverse ^ d)));
+            let (du, dv) = (du - u, dv - v);
+            (du - du.round(), dv - dv.round())
+        };
+        self.texture.sample_footprint(u, v, step(dpdx), step(dpdy))
+    }
+}
+
+two_pattern!(Stripe, Gradient, Ring, Checker, RadialGradient);
+transformable_pattern!(
+    Stripe,
+    Gradient,
+    Ring,
+    Checker,
+    RadialGradient,
+    Blend,
+    Perturb,
+    NoisePattern,
+    ImageTexture
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        scene::texture::{Filter, Texture},
+        utils::material::{IMaterial, Material},
+        utils::noise::Worley,
+        world::shapes::{shape::Shape, sphere::Sphere},
+    };
+
+    fn white() -> Vec3 {
+        Vec3::from_float(1.0)
+    }
+
+    fn black() -> Vec3 {
+        Vec3::zero()
+    }
+
+    #[test]
+    fn test_stripe() {
+        let p = Stripe::new(white(), black());
+
+        assert_eq!(p.pattern_at(&Vec3::new(0.0, 1.0, 2.0)), white());
+        assert_eq!(p.pattern_at(&Vec3::new(0.9, 0.0, 0.0)), white());
+        assert_eq!(p.pattern_at(&Vec3::new(1.0, 0.0, 0.0)), black());
+        assert_eq!(p.pattern_at(&Vec3::new(-0.1, 0.0, 0.0)), black());
+        assert_eq!(p.pattern_at(&Vec3::new(-1.1, 0.0, 0.0)), white());
+    }
+
+    #[test]
+    fn test_pattern_transform() {
+        let p = Stripe::new(white(), black()).scaling(2.0, 2.0, 2.0);
+        assert_eq!(p.color_at(&Vec3::new(1.5, 0.0, 0.0)), white());
+
+        let p = Stripe::new(white(), black()).translation(0.5, 0.0, 0.0);
+        assert_eq!(p.color_at(&Vec3::new(2.5, 0.0, 0.0)), white());
+        assert_eq!(p.color_at(&Vec3::new(0.4, 0.0, 0.0)), black());
+    }
+
+    #[test]
+    fn test_shape_and_pattern_transforms() {
+        let sp = Sphere::default()
+            .scaling(2.0, 2.0, 2.0)
+            .pattern(Stripe::new(white(), black()).translation(0.5, 0.0, 0.0));
+        // world 2.5 is object 1.25, which is pattern 0.75
+        assert_eq!(sp.color_at(&Vec3::new(2.5, 0.0, 0.0)), white());
+        assert_eq!(sp.color_at(&Vec3::new(3.5, 0.0, 0.0)), black());
+        assert_eq!(
+            Sphere::default()
+                .color(0.2, 0.3, 0.4)
+                .color_at(&Vec3::zero()),
+            Vec3::new(0.2, 0.3, 0.4)
+        );
+    }
+
+    #[test]
+    fn test_gradient() {
+        let p = Gradient::new(white(), black());
+
+        assert_eq!(p.pattern_at(&Vec3::zero()), white());
+        assert_eq!(
+            p.pattern_at(&Vec3::new(0.25, 0.0, 0.0)),
+            Vec3::from_float(0.75)
+        );
+        assert_eq!(
+            p.pattern_at(&Vec3::new(0.75, 0.0, 0.0)),
+            Vec3::from_float(0.25)
+        );
+    }
+
+    #[test]
+    fn test_ring() {
+        let p = Ring::new(white(), black());
+
+        assert_eq!(p.pattern_at(&Vec3::zero()), white());
+        assert_eq!(p.pattern_at(&Vec3::new(1.0, 0.0, 0.0)), black());
+        assert_eq!(p.pattern_at(&Vec3::new(0.0, 0.0, 1.0)), black());
+        // 0.708 is just beyond sqrt(2) / 2
+        assert_eq!(p.pattern_at(&Vec3::new(0.708, 0.0, 0.708)), black());
+    }
+
+    #[test]
+    fn test_checker_repeats_in_3d() {
+        let p = Checker::new(white(), black());
+
+        for (x, y, z, c) in [
+            (0.0, 0.0, 0.0, white()),
+            (0.99, 0.0, 0.0, white()),
+            (1.01, 0.0, 0.0, black()),
+            (0.0, 0.99, 0.0, white()),
+            (0.0, 1.01, 0.0, black()),
+            (0.0, 0.0, 1.01, black()),
+            (-0.5, 0.0, -0.5, white()),
+            (-0.5, 0.0, 0.5, black()),
+        ] {
+            assert_eq!(p.pattern_at(&Vec3::new(x, y, z)), c, "{} {} {}", x, y, z);
+        }
+    }
+
+    #[test]
+    fn test_radial_gradient() {
+        let p = RadialGradient::new(white(), black());
+
+        assert_eq!(p.pattern_at(&Vec3::zero()), white());
+        assert_eq!(
+            p.pattern_at(&Vec3::new(0.3, 5.0, 0.4)),
+            Vec3::from_float(0.5)
+        );
+    }
+
+    #[test]
+    fn test_nested() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        let stripes = Stripe::new(red.clone(), blue.clone()).scaling(0.25, 1.0, 1.0);
+        let p = Checker::nested(Arc::new(stripes), Arc::new(Solid::new(black())));
+
+        // the stripes show in the even cells only, in the checker's space
+        assert_eq!(p.pattern_at(&Vec3::new(0.1, 0.0, 0.1)), red);
+        assert_eq!(p.pattern_at(&Vec3::new(0.3, 0.0, 0.1)), blue);
+        assert_eq!(p.pattern_at(&Vec3::new(1.1, 0.0, 0.1)), black());
+        assert_eq!(p.pattern_at(&Vec3::new(1.1, 0.0, 1.1)), red);
+    }
+
+    #[test]
+    fn test_blend() {
+        let a = Stripe::new(white(), black());
+        let b = Stripe::new(white(), black()).rotation_y(std::f32::consts::FRAC_PI_2);
+        let p = Blend::new(Arc::new(a), Arc::new(b));
+
+        // b runs along z, its white band covers z in (-1, 0]
+        assert_eq!(p.pattern_at(&Vec3::new(0.5, 0.0, -0.5)), white());
+        assert_eq!(
+            p.pattern_at(&Vec3::new(1.5, 0.0, -0.5)),
+            Vec3::from_float(0.5)
+        );
+        assert_eq!(p.pattern_at(&Vec3::new(1.5, 0.0, 0.5)), black());
+    }
+
+    #[test]
+    fn test_perturb() {
+        let stripes = || Arc::new(Stripe::new(white(), black()).scaling(0.5, 1.0, 1.0));
+        let p = Perturb::new(stripes(), 0.3, 1);
+        let q = Perturb::new(stripes(), 0.3, 1);
+
+        let xs: Vec<Float> = (0..200).map(|i| i as Float * 0.037).collect();
+        let differs = xs.iter().any(|x| {
+            let pt = Vec3::new(*x, 0.4, 0.7);
+            assert_eq!(p.pattern_at(&pt), q.pattern_at(&pt));
+            p.pattern_at(&pt) != stripes().color_at(&pt)
+        });
+        assert!(differs);
+        // nothing moves without a scale
+        let flat = Perturb::new(stripes(), 0.0, 1);
+        assert_eq!(flat.pattern_at(&Vec3::new(0.3, 0.4, 0.7)), white());
+    }
+
+    #[test]
+    fn test_noise_pattern() {
+        let cells = Arc::new(Worley::new(4));
+        let p = NoisePattern::new(cells.clone(), black(), white());
+
+        for i in 0..50 {
+            let pt = Vec3::new(i as Float * 0.13, 0.5, -0.2);
+            let v = cells.noise(&pt).min(1.0);
+            assert_eq!(p.pattern_at(&pt), Vec3::from_float(v));
+        }
+    }
+
+    #[test]
+    fn test_image_texture() {
+        // left half red, right half blue
+        let texture = Texture::new(
+            2,
+            1,
+            vec![Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)],
+        )
+        .filter(Filter::Nearest);
+        let sp = Sphere::default().pattern(ImageTexture::new(
+            Arc::new(MipMap::single(texture)),
+            UvMap::Spherical,
+        ));
+
+        // u grows from the back of the sphere around through +x
+        assert_eq!(
+            sp.color_at(&Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            sp.color_at(&Vec3::new(-1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_material_builders() {
+        let sp = Sphere::default()
+            .color(1.0, 0.0, 0.0)
+            .blend(Solid::new(Vec3::new(0.0, 0.0, 1.0)));
+        assert_eq!(sp.color_at(&Vec3::zero()), Vec3::new(0.5, 0.0, 0.5));
+
+        let sp = Sphere::default()
+            .pattern(Stripe::new(white(), black()))
+            .perturb(0.2, 9);
+        let m: &Material = Shape::get_material(&sp);
+        assert!(format!("{:?}", m.pattern).starts_with("Some(Perturb"));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    utils::{
+        aabb::Aabb,
+        material::{IMaterial, Material},
+        matrix::Mat,
+        ray::Ray,
+        vec3::{Float, Vec3, EPSILON},
+    },
+    world::{transform::Transformable, w::Intersection},
+};
+
+use super::shape::Shape;
+
+/// Two cones around the y axis meeting tip to tip at the origin, the radius
+/// at any height being `|y|`. Cut and capped like `Cylinder`, a `minimum`
+/// of 0 leaves a single nappe.
+#[derive(Debug)]
+pub struct Cone {
+    pub m: Material,
+    pub t: Mat,
+    pub inverse: Mat,
+    pub minimum: Float,
+    pub maximum: Float,
+    pub closed: bool,
+}
+
+impl Shape for Cone {
+    fn intersect<'a>(&'a self, oray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        let ray = oray.transform(&self.inverse);
+        let (o, d) = (&ray.org, &ray.dir);
+        let a: Float = d.dotxz(d) - d.y * d.y;
+        let b2: Float = d.dotxz(o) - d.y * o.y;
+        let c: Float = o.dotxz(o) - o.y * o.y;
+
+        let mut side = |t: Float| {
+            let y = o.y + t * d.y;
+            if self.minimum < y && y < self.maximum {
+                xs.push(Intersection::new(self, t));
+            }
+        };
+        if a.abs() < EPSILON {
+            // parallel to one nappe, it crosses the other once
+            if b2.abs() >= EPSILON {
+                side(-c / (2.0 * b2));
+            }
+        } else {
+            let disc: Float = b2.powf(2.0) - (a * c);
+            // rays grazing the surface come out a hair negative
+            if disc < -EPSILON {
+                return;
+            }
+            let d_sqrt = disc.max(0.0).sqrt();
+            let t0 = (-b2 - d_sqrt) / a;
+            let t1 = (-b2 + d_sqrt) / a;
+            side(t0.min(t1));
+            side(t0.max(t1));
+        }
+        self.intersect_caps(&ray, xs);
+    }
+
+    fn normal_at(&self, hitp: &Vec3) -> Vec3 {
+        let p = &self.inverse * hitp;
+        let dist = p.dotxz(&p);
+        let obj_norm = if dist < self.maximum.powi(2) && p.y >= self.maximum - EPSILON {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else if dist < self.minimum.powi(2) && p.y <= self.minimum + EPSILON {
+            Vec3::new(0.0, -1.0, 0.0)
+        } else {
+            let y = dist.sqrt();
+            Vec3::new(p.x, if p.y > 0.0 { -y } else { y }, p.z)
+        };
+        (&self.inverse.transpose() ^ &obj_norm).norm()
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.m
+    }
+
+    fn world_to_object(&self, p: &Vec3) -> Vec3 {
+        &self.inverse * p
+    }
+
+    fn bounds(&self) -> Aabb {
+        if !self.minimum.is_finite() || !self.maximum.is_finite() {
+            return Aabb::infinite();
+        }
+        let r = self.minimum.abs().max(self.maximum.abs());
+        Aabb::new(
+            Vec3::new(-r, self.minimum, -r),
+            Vec3::new(r, self.maximum, r),
+        )
+        .transform(&self.t)
+    }
+}
+
+impl Transformable for Cone {
+    #[inline]
+    fn apply_transform(&mut self, transform: &Mat) {
+        self.t = &self.t * transform;
+        self.inverse = self.t.inverse();
+    }
+}
+
+impl IMaterial for Cone {
+    #[inline]
+    fn get_material(&mut self) -> &mut Material {
+        &mut self.m
+    }
+}
+
+impl Cone {
+    pub fn set_transform(&mut self, m: Mat) {
+        self.inverse = m.inverse();
+        self.t = m;
+    }
+
+    // hits on the end caps of a closed cone, each as wide as its height
+    fn intersect_caps<'a>(&'a self, ray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        if !self.closed || ray.dir.y.abs() < EPSILON {
+            return;
+        }
+        for y in [self.minimum, self.maximum] {
+            let t = (y - ray.org.y) / ray.dir.y;
+            let p = &ray.org + &ray.dir * t;
+            if p.dotxz(&p) <= y * y + EPSILON {
+                xs.push(Intersection::new(self, t));
+            }
+        }
+    }
+}
+
+impl Default for Cone {
+    fn default() -> Self {
+        let t = Mat::identity(4);
+        let inverse = t.inverse();
+        Self {
+            m: Material::default(),
+            t,
+            inverse,
+            minimum: Float::NEG_INFINITY,
+            maximum: Float::INFINITY,
+            closed: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::transform::Transformable;
+
+    fn intersect(s: &dyn Shape, org: Vec3, dir: Vec3) -> Vec<Float> {
+        let mut xs = Vec::new();
+        s.intersect(&Ray::new(org, dir.norm()), &mut xs);
+        xs.iter().map(|i| i.t).collect()
+    }
+
+    #[test]
+    fn test_hits() {
+        let cone = Cone::default();
+        for (org, dir, t0, t1) in [
+            (
+                Vec3::new(0.0, 0.0, -5.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                5.0,
+                5.0,
+            ),
+            (
+                Vec3::new(0.0, 0.0, -5.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                8.66025,
+                8.66025,
+            ),
+            (
+                Vec3::new(1.0, 1.0, -5.0),
+                Vec3::new(-0.5, -1.0, 1.0),
+                4.55006,
+                49.44994,
+            ),
+        ] {
+            let xs = intersect(&cone, org.clone(), dir);
+            assert_eq!(xs.len(), 2, "{:?}", org);
+            assert!((xs[0] - t0).abs() < 1e-3, "{:?}", org);
+            assert!((xs[1] - t1).abs() < 1e-3, "{:?}", org);
+        }
+    }
+
+    #[test]
+    fn test_parallel_to_nappe() {
+        let xs = intersect(
+            &Cone::default(),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        );
+        // meets the cone only once, at (0, 0.5, -0.5)
+        assert_eq!(xs.len(), 1);
+        assert!((xs[0] - std::f32::consts::FRAC_1_SQRT_2).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_caps() {
+        let cone = Cone {
+            minimum: -0.5,
+            maximum: 0.5,
+            closed: true,
+            ..Default::default()
+        };
+        for (org, dir, count) in [
+            (Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 1.0, 0.0), 0),
+            (Vec3::new(0.0, 0.0, -0.25), Vec3::new(0.0, 1.0, 1.0), 2),
+            (Vec3::new(0.0, 0.0, -0.25), Vec3::new(0.0, 1.0, 0.0), 4),
+        ] {
+            assert_eq!(intersect(&cone, org.clone(), dir).len(), count, "{:?}", org);
+        }
+        assert_eq!(
+            cone.normal_at(&Vec3::new(0.2, 0.5, 0.1)),
+            Vec3::new(0.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            cone.normal_at(&Vec3::new(0.0, -0.5, -0.3)),
+            Vec3::new(0.0, -1.0, 0.0)
+        );
+        assert_eq!(cone.bounds().min, Vec3::new(-0.5, -0.5, -0.5));
+        assert_eq!(cone.bounds().max, Vec3::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn test_normals() {
+        let cone = Cone::default();
+        let s = (2.0 as Float).sqrt();
+        for (p, n) in [
+            (Vec3::new(1.0, 1.0, 1.0), Vec3::new(1.0, -s, 1.0)),
+            (Vec3::new(-1.0, -1.0, 0.0), Vec3::new(-1.0, 1.0, 0.0)),
+        ] {
+            assert_eq!(cone.normal_at(&p), n.norm(), "{:?}", p);
+        }
+        // moved up by 2 the tip follows
+        let cone = Cone::default().translation(0.0, 2.0, 0.0);
+        assert_eq!(
+            cone.normal_at(&Vec3::new(-1.0, 1.0, 0.0)),
+            Vec3::new(-1.0, 1.0, 0.0).norm()
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    utils::{
+        aabb::Aabb,
+        material::{IMaterial, Material},
+        matrix::Mat,
+        ray::Ray,
+        vec3::{Float, Vec3, EPSILON},
+    },
+    world::{
+        transform::Transformable,
+        uv::{cube_face, cube_map, CubeFace},
+        w::Intersection,
+    },
+};
+
+use super::shape::Shape;
+
+/// The `[-1, 1]` box, scale and rotate it into walls, tables and crates.
+#[derive(Debug)]
+pub struct Cube {
+    pub m: Material,
+    pub t: Mat,
+    pub inverse: Mat,
+}
+
+// where the ray enters and leaves the slab between -1 and 1 on one axis
+fn check_axis(org: Float, dir: Float) -> Option<(Float, Float)> {
+    if dir.abs() < EPSILON {
+        // parallel to the slab, inside it all along or never
+        return (org.abs() <= 1.0).then_some((Float::NEG_INFINITY, Float::INFINITY));
+    }
+    let t0 = (-1.0 - org) / dir;
+    let t1 = (1.0 - org) / dir;
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+impl Shape for Cube {
+    fn intersect<'a>(&'a self, oray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        let ray = oray.transform(&self.inverse);
+        let Some((xmin, xmax)) = check_axis(ray.org.x, ray.dir.x) else {
+            return;
+        };
+        let Some((ymin, ymax)) = check_axis(ray.org.y, ray.dir.y) else {
+            return;
+        };
+        let Some((zmin, zmax)) = check_axis(ray.org.z, ray.dir.z) else {
+            return;
+        };
+        let tmin = xmin.max(ymin).max(zmin);
+        let tmax = xmax.min(ymax).min(zmax);
+        if tmin > tmax {
+            return;
+        }
+        xs.push(Intersection::new(self, tmin));
+        xs.push(Intersection::new(self, tmax));
+    }
+
+    fn normal_at(&self, hitp: &Vec3) -> Vec3 {
+        let p = &self.inverse * hitp;
+        let obj_norm = match cube_face(&p) {
+            CubeFace::Right => Vec3::new(1.0, 0.0, 0.0),
+            CubeFace::Left => Vec3::new(-1.0, 0.0, 0.0),
+            CubeFace::Up => Vec3::new(0.0, 1.0, 0.0),
+            CubeFace::Down => Vec3::new(0.0, -1.0, 0.0),
+            CubeFace::Front => Vec3::new(0.0, 0.0, 1.0),
+            CubeFace::Back => Vec3::new(0.0, 0.0, -1.0),
+        };
+        (&self.inverse.transpose() ^ &obj_norm).norm()
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.m
+    }
+
+    fn world_to_object(&self, p: &Vec3) -> Vec3 {
+        &self.inverse * p
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::new(Vec3::from_float(-1.0), Vec3::from_float(1.0)).transform(&self.t)
+    }
+}
+
+impl Transformable for Cube {
+    #[inline]
+    fn apply_transform(&mut self, transform: &Mat) {
+        self.t = &self.t * transform;
+        self.inverse = self.t.inverse();
+    }
+}
+
+impl IMaterial for Cube {
+    #[inline]
+    fn get_material(&mut self) -> &mut Material {
+        &mut self.m
+    }
+}
+
+impl Cube {
+    pub fn set_transform(&mut self, m: Mat) {
+        self.inverse = m.inverse();
+        self.t = m;
+    }
+
+    /// Texture coordinates of a world point on the surface, the faces
+    /// unfolded into a cross as `uv::cube_map` lays them out.
+    pub fn uv_at(&self, p: &Vec3) -> (Float, Float) {
+        cube_map(&self.world_to_object(p))
+    }
+}
+
+impl Default for Cube {
+    fn default() -> Self {
+        let t = Mat::identity(4);
+        let inverse = t.inverse();
+        Self {
+            m: Material::default(),
+            t,
+            inverse,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn intersect(s: &dyn Shape, ray: &Ray) -> Vec<Float> {
+        let mut xs = Vec::new();
+        s.intersect(ray, &mut xs);
+        xs.iter().map(|i| i.t).collect()
+    }
+
+    #[test]
+    fn test_hits() {
+        let c = Cube::default();
+        for (org, dir, t0, t1) in [
+            (
+                Vec3::new(5.0, 0.5, 0.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+                4.0,
+                6.0,
+            ),
+            (
+                Vec3::new(-5.0, 0.5, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                4.0,
+                6.0,
+            ),
+            (
+                Vec3::new(0.5, 5.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                4.0,
+                6.0,
+            ),
+            (
+                Vec3::new(0.5, -5.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                4.0,
+                6.0,
+            ),
+            (
+                Vec3::new(0.5, 0.0, 5.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                4.0,
+                6.0,
+            ),
+            (
+                Vec3::new(0.5, 0.0, -5.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                4.0,
+                6.0,
+            ),
+            (
+                Vec3::new(0.0, 0.5, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                -1.0,
+                1.0,
+            ),
+        ] {
+            let xs = intersect(&c, &Ray::new(org.clone(), dir));
+            assert_eq!(xs, vec![t0, t1], "{:?}", org);
+        }
+    }
+
+    #[test]
+    fn test_misses() {
+        let c = Cube::default();
+        for (org, dir) in [
+            (Vec3::new(-2.0, 0.0, 0.0), Vec3::new(0.2673, 0.5345, 0.8018)),
+            (Vec3::new(0.0, -2.0, 0.0), Vec3::new(0.8018, 0.2673, 0.5345)),
+            (Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.5345, 0.8018, 0.2673)),
+            (Vec3::new(2.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0)),
+            (Vec3::new(0.0, 2.0, 2.0), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(2.0, 2.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+        ] {
+            let xs = intersect(&c, &Ray::new(org.clone(), dir));
+            assert!(xs.is_empty(), "{:?}", org);
+        }
+    }
+
+    #[test]
+    fn test_normals() {
+        let c = Cube::default();
+        for (p, n) in [
+            (Vec3::new(1.0, 0.5, -0.8), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(-1.0, -0.2, 0.9), Vec3::new(-1.0, 0.0, 0.0)),
+            (Vec3::new(-0.4, 1.0, -0.1), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(0.3, -1.0, -0.7), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(-0.6, 0.3, 1.0), Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(0.4, 0.4, -1.0), Vec3::new(0.0, 0.0, -1.0)),
+            (Vec3::new(1.0, 1.0, 1.0), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(-1.0, -1.0, -1.0), Vec3::new(-1.0, 0.0, 0.0)),
+        ] {
+            assert_eq!(c.normal_at(&p), n, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_transformed() {
+        let c = Cube::default()
+            .translation(0.0, 1.0, 0.0)
+            .rotation_y(PI / 4.0)
+            .scaling(1.0, 2.0, 1.0);
+        let r = Ray::new(Vec3::new(0.0, 1.5, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let s = (2.0 as Float).sqrt();
+
+        let xs = intersect(&c, &r);
+        assert!((xs[0] - (5.0 - s)).abs() < EPSILON && (xs[1] - (5.0 + s)).abs() < EPSILON);
+        let f = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(c.normal_at(&Vec3::new(-f, 1.5, -f)), Vec3::new(-f, 0.0, -f));
+        assert_eq!(
+            c.normal_at(&Vec3::new(0.0, 3.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0)
+        );
+        assert_eq!(c.bounds().min, Vec3::new(-s, -1.0, -s));
+        assert_eq!(c.bounds().max, Vec3::new(s, 3.0, s));
+    }
+
+    #[test]
+    fn test_uv_at() {
+        let c = Cube::default().translation(2.0, 0.0, 0.0);
+        for (p, (u, v)) in [
+            (Vec3::new(2.0, 0.0, 1.0), (0.375, 0.5)),
+            (Vec3::new(2.0, 0.0, -1.0), (0.875, 0.5)),
+            (Vec3::new(3.0, 0.5, 0.0), (0.625, 1.75 / 3.0)),
+            (Vec3::new(2.0, -1.0, 0.0), (0.375, 0.5 / 3.0)),
+        ] {
+            let uv = c.uv_at(&p);
+            assert!(
+                (uv.0 - u).abs() < EPSILON && (uv.1 - v).abs() < EPSILON,
+                "{:?}",
+                p
+            );
+        }
+    }
+}
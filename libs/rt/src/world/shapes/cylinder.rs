@@ -1,54 +1,84 @@
 use crate::{
     utils::{
+        aabb::Aabb,
         material::{IMaterial, Material},
         matrix::Mat,
         ray::Ray,
-        vec3::{Float, Vec3},
+        vec3::{Float, Vec3, EPSILON},
     },
-    world::transform::Transformable,
+    world::{transform::Transformable, w::Intersection},
 };
 
 use super::shape::Shape;
 
+/// The unit radius cylinder around the y axis, cut between `minimum` and
+/// `maximum`, -2 and 2 by default. `closed` puts caps on the cut ends,
+/// `Cylinder::infinite` runs forever.
 #[derive(Debug)]
 pub struct Cylinder {
     pub m: Material,
     pub t: Mat,
-    pub height: Float,
     pub inverse: Mat,
+    pub minimum: Float,
+    pub maximum: Float,
+    pub closed: bool,
 }
 
 impl Shape for Cylinder {
-    fn intersect(&self, oray: &Ray) -> Option<(Float, Float)> {
+    fn intersect<'a>(&'a self, oray: &Ray, xs: &mut Vec<Intersection<'a>>) {
         let ray = oray.transform(&self.inverse);
         let a: Float = ray.dir.dotxz(&ray.dir);
-        let b2: Float = ray.dir.dotxz(&ray.org);
-        let c: Float = ray.org.dotxz(&ray.org) - 1.0;
+        // parallel to the y axis the ray can only hit the caps
+        if a.abs() >= EPSILON {
+            let b2: Float = ray.dir.dotxz(&ray.org);
+            let c: Float = ray.org.dotxz(&ray.org) - 1.0;
 
-        let d: Float = b2.powf(2.0) - (a * c);
-        if d < 0.0 {
-            return None;
+            let d: Float = b2.powf(2.0) - (a * c);
+            if d < 0.0 {
+                return;
+            }
+            let d_sqrt = d.sqrt();
+            for t in [(-b2 - d_sqrt) / a, (-b2 + d_sqrt) / a] {
+                let y = ray.org.y + t * ray.dir.y;
+                if self.minimum < y && y < self.maximum {
+                    xs.push(Intersection::new(self, t));
+                }
+            }
         }
-        let d_sqrt = d.sqrt();
-        let t0 = (-b2 - d_sqrt) / a;
-        let t1 = (-b2 + d_sqrt) / a;
-
-        let hitp = ray.org + (ray.dir * t0);
-        if hitp.y.abs() > self.height {
-            return None;
-        }
-        Some((t0, t1))
+        self.intersect_caps(&ray, xs);
     }
 
     fn normal_at(&self, hitp: &Vec3) -> Vec3 {
-        let obj_norm = (&self.inverse * hitp).norm();
-        let wrl_norm = &self.inverse.transpose() * &obj_norm;
-        (wrl_norm).norm()
+        let p = &self.inverse * hitp;
+        let dist = p.dotxz(&p);
+        let obj_norm = if dist < 1.0 && p.y >= self.maximum - EPSILON {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else if dist < 1.0 && p.y <= self.minimum + EPSILON {
+            Vec3::new(0.0, -1.0, 0.0)
+        } else {
+            Vec3::new(p.x, 0.0, p.z)
+        };
+        (&self.inverse.transpose() ^ &obj_norm).norm()
     }
 
     fn get_material(&self) -> &Material {
         &self.m
     }
+
+    fn world_to_object(&self, p: &Vec3) -> Vec3 {
+        &self.inverse * p
+    }
+
+    fn bounds(&self) -> Aabb {
+        if !self.minimum.is_finite() || !self.maximum.is_finite() {
+            return Aabb::infinite();
+        }
+        Aabb::new(
+            Vec3::new(-1.0, self.minimum, -1.0),
+            Vec3::new(1.0, self.maximum, 1.0),
+        )
+        .transform(&self.t)
+    }
 }
 
 impl Transformable for Cylinder {
@@ -71,18 +101,43 @@ impl Cylinder {
         self.inverse = m.inverse();
         self.t = m;
     }
+
+    /// Open and unbounded along y.
+    pub fn infinite() -> Self {
+        Self {
+            minimum: Float::NEG_INFINITY,
+            maximum: Float::INFINITY,
+            ..Default::default()
+        }
+    }
+
+    // hits on the end caps of a closed cylinder, `ray` in object space
+    fn intersect_caps<'a>(&'a self, ray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        if !self.closed || ray.dir.y.abs() < EPSILON {
+            return;
+        }
+        for y in [self.minimum, self.maximum] {
+            let t = (y - ray.org.y) / ray.dir.y;
+            let p = &ray.org + &ray.dir * t;
+            if p.dotxz(&p) <= 1.0 + EPSILON {
+                xs.push(Intersection::new(self, t));
+            }
+        }
+    }
 }
 
 impl Default for Cylinder {
     fn default() -> Self {
         let t = Mat::identity(4);
         let inverse = t.inverse();
-        let height = 2.0;
         Self {
             m: Material::default(),
             t,
-            height,
             inverse,
+            // as tall as the `height` of 2 it used to have
+            minimum: -2.0,
+            maximum: 2.0,
+            closed: false,
         }
     }
 }
@@ -93,21 +148,143 @@ mod tests {
 
     use super::*;
 
+    fn intersect(s: &dyn Shape, org: Vec3, dir: Vec3) -> Vec<Float> {
+        let mut xs = Vec::new();
+        s.intersect(&Ray::new(org, dir.norm()), &mut xs);
+        xs.iter().map(|i| i.t).collect()
+    }
+
+    fn truncated(closed: bool) -> Cylinder {
+        Cylinder {
+            minimum: 1.0,
+            maximum: 2.0,
+            closed,
+            ..Default::default()
+        }
+    }
+
     #[test]
-    fn test_normal_at_1() {
-        let f = std::f32::consts::FRAC_1_SQRT_2;
-        let sp = Cylinder::default().translation(-0.0, 1.0, -0.0);
-        let v = sp.normal_at(&Vec3::new(0.0, 1.70711, -f));
-        assert_eq!(v, Vec3::new(0.0, f, -f));
+    fn test_misses() {
+        let cyl = Cylinder::infinite();
+        for (org, dir) in [
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 1.0, 1.0)),
+        ] {
+            assert!(intersect(&cyl, org.clone(), dir).is_empty(), "{:?}", org);
+        }
+    }
+
+    #[test]
+    fn test_hits() {
+        let cyl = Cylinder::infinite();
+        for (org, dir, t0, t1) in [
+            (
+                Vec3::new(1.0, 0.0, -5.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                5.0,
+                5.0,
+            ),
+            (
+                Vec3::new(0.0, 0.0, -5.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                4.0,
+                6.0,
+            ),
+            (
+                Vec3::new(0.5, 0.0, -5.0),
+                Vec3::new(0.1, 1.0, 1.0),
+                6.80798,
+                7.08872,
+            ),
+        ] {
+            let xs = intersect(&cyl, org.clone(), dir);
+            assert_eq!(xs.len(), 2, "{:?}", org);
+            assert!((xs[0] - t0).abs() < EPSILON, "{:?}", org);
+            assert!((xs[1] - t1).abs() < EPSILON, "{:?}", org);
+        }
+    }
+
+    #[test]
+    fn test_side_normals() {
+        let cyl = Cylinder::infinite();
+        for (p, n) in [
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(0.0, 5.0, -1.0), Vec3::new(0.0, 0.0, -1.0)),
+            (Vec3::new(0.0, -2.0, 1.0), Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(-1.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+        ] {
+            assert_eq!(cyl.normal_at(&p), n, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_default_height() {
+        let cyl = Cylinder::default();
+        let side = |y: Float| intersect(&cyl, Vec3::new(0.0, y, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(side(1.9), vec![4.0, 6.0]);
+        assert!(side(2.1).is_empty() && side(-2.1).is_empty());
+        // open, a ray down the axis goes through
+        assert!(intersect(&cyl, Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).is_empty());
+        assert_eq!(cyl.bounds().max, Vec3::new(1.0, 2.0, 1.0));
     }
 
     #[test]
-    fn test_normal_at_2() {
-        let f = 2.0f32.sqrt() / 2.0;
-        let sp = Cylinder::default()
-            .scaling(1.0, 0.5, 1.0)
-            .rotation_z(PI / 5.0);
-        let v = sp.normal_at(&Vec3::new(0.0, f, -f));
-        assert_eq!(v, Vec3::new(0.0, 0.97014, -0.24254));
+    fn test_truncated() {
+        let cyl = truncated(false);
+        for (org, dir, count) in [
+            (Vec3::new(0.0, 1.5, 0.0), Vec3::new(0.1, 1.0, 0.0), 0),
+            (Vec3::new(0.0, 3.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0),
+            (Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0),
+            (Vec3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0),
+            (Vec3::new(0.0, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0),
+            (Vec3::new(0.0, 1.5, -2.0), Vec3::new(0.0, 0.0, 1.0), 2),
+        ] {
+            assert_eq!(intersect(&cyl, org.clone(), dir).len(), count, "{:?}", org);
+        }
+        assert!(!Cylinder::infinite().bounds().is_finite());
+        assert_eq!(cyl.bounds().min, Vec3::new(-1.0, 1.0, -1.0));
+        assert_eq!(cyl.bounds().max, Vec3::new(1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn test_caps() {
+        let cyl = truncated(true);
+        for (org, dir) in [
+            (Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(0.0, 3.0, -2.0), Vec3::new(0.0, -1.0, 2.0)),
+            (Vec3::new(0.0, 4.0, -2.0), Vec3::new(0.0, -1.0, 1.0)),
+            (Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 1.0, 2.0)),
+            (Vec3::new(0.0, -1.0, -2.0), Vec3::new(0.0, 1.0, 1.0)),
+        ] {
+            assert_eq!(intersect(&cyl, org.clone(), dir).len(), 2, "{:?}", org);
+        }
+    }
+
+    #[test]
+    fn test_cap_normals() {
+        let cyl = truncated(true);
+        for (p, n) in [
+            (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(0.5, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(0.0, 1.0, 0.5), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(0.5, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(0.0, 2.0, 0.5), Vec3::new(0.0, 1.0, 0.0)),
+        ] {
+            assert_eq!(cyl.normal_at(&p), n, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_normal_transformed() {
+        let f = std::f32::consts::FRAC_1_SQRT_2;
+        let cyl = Cylinder::infinite()
+            .translation(0.0, 1.0, 0.0)
+            .rotation_z(PI / 2.0)
+            .scaling(1.0, 1.0, 2.0);
+        // lying along x now, squashed to an ellipse twice as deep as tall
+        let v = cyl.normal_at(&Vec3::new(3.0, 1.0 + f, 2.0 * f));
+        assert_eq!(v, Vec3::new(0.0, 2.0, 1.0).norm());
     }
 }
@@ -0,0 +1,242 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    utils::{
+        aabb::Aabb,
+        material::{IMaterial, Material},
+        matrix::Mat,
+        ray::Ray,
+        stats,
+        vec3::{Float, Vec3},
+    },
+    world::{bvh::Bvh, transform::Transformable, w::Intersection},
+};
+
+use super::{
+    shape::Shape,
+    triangle::{SmoothTriangle, Triangle},
+};
+
+#[derive(Debug)]
+pub enum Face {
+    Flat(Triangle),
+    Smooth(SmoothTriangle),
+}
+
+impl Face {
+    pub fn shape(&self) -> &dyn Shape {
+        match self {
+            Face::Flat(t) => t,
+            Face::Smooth(t) => t,
+        }
+    }
+
+    fn triangle(&self) -> &Triangle {
+        match self {
+            Face::Flat(t) => t,
+            Face::Smooth(t) => &t.tri,
+        }
+    }
+
+    fn share_material(&mut self, m: &Arc<Material>) {
+        match self {
+            Face::Flat(t) => t.m = m.clone(),
+            Face::Smooth(t) => t.tri.m = m.clone(),
+        }
+    }
+
+    fn share_transform(&mut self, inverse: Arc<Mat>, t: Arc<Mat>) {
+        match self {
+            Face::Flat(f) => f.share_transform(inverse, t),
+            Face::Smooth(f) => f.share_transform(inverse, t),
+        }
+    }
+}
+
+/// Triangle soup with its own BVH, so a whole model sits in the world as a
+/// single shape. Hits report the face that was hit, not the mesh, but every
+/// face refers to the one material of the mesh and faces that were placed
+/// together share one transform.
+#[derive(Debug)]
+pub struct Mesh {
+    pub m: Arc<Material>,
+    pub faces: Vec<Face>,
+    bvh: Bvh,
+    bounds: Aabb,
+}
+
+impl Mesh {
+    pub fn new(faces: Vec<Face>) -> Self {
+        let mut mesh = Self {
+            m: Arc::new(Material::default()),
+            faces,
+            bvh: Bvh::default(),
+            bounds: Aabb::empty(),
+        };
+        // faces still where they were built share a single identity
+        let identity = Mat::identity(4);
+        let (t, inverse) = (Arc::new(Mat::identity(4)), Arc::new(Mat::identity(4)));
+        for f in mesh.faces.iter_mut() {
+            if *f.triangle().t == identity {
+                f.share_transform(inverse.clone(), t.clone());
+            }
+        }
+        mesh.share_material();
+        mesh.build_bvh();
+        mesh
+    }
+
+    fn share_material(&mut self) {
+        for f in self.faces.iter_mut() {
+            f.share_material(&self.m);
+        }
+    }
+
+    fn build_bvh(&mut self) {
+        let boxes: Vec<Aabb> = self.faces.iter().map(|f| f.shape().bounds()).collect();
+        self.bounds = boxes.iter().fold(Aabb::empty(), |b, f| b.merge(f));
+        self.bvh = Bvh::build(&boxes);
+    }
+
+    /// Makes `m` the material of every face.
+    pub fn set_material(&mut self, m: &Material) {
+        self.m = Arc::new(m.clone());
+        self.share_material();
+    }
+
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+}
+
+impl Shape for Mesh {
+    fn intersect<'a>(&'a self, ray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        self.bvh.traverse(ray, Float::INFINITY, |i| {
+            stats::count_intersection_test();
+            self.faces[i].shape().intersect(ray, xs);
+            Float::INFINITY
+        });
+    }
+
+    fn normal_at(&self, hitp: &Vec3) -> Vec3 {
+        // hits always point at a face, this is only reached when called directly
+        self.faces
+            .first()
+            .map_or(Vec3::new(0.0, 1.0, 0.0), |f| f.shape().normal_at(hitp))
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.m
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bounds.clone()
+    }
+}
+
+impl Transformable for Mesh {
+    fn apply_transform(&mut self, transform: &Mat) {
+        // each transform the faces held becomes one new shared one, the old
+        // one is kept in the map so its address can not be reused meanwhile
+        let mut moved: HashMap<*const Mat, [Arc<Mat>; 3]> = HashMap::new();
+        for f in self.faces.iter_mut() {
+            let old = &f.triangle().t;
+            let [_, inverse, t] = moved.entry(Arc::as_ptr(old)).or_insert_with(|| {
+                let t = old.as_ref() * transform;
+                [old.clone(), Arc::new(t.inverse()), Arc::new(t)]
+            });
+            let (inverse, t) = (inverse.clone(), t.clone());
+            f.share_transform(inverse, t);
+        }
+        self.build_bvh();
+    }
+}
+
+impl IMaterial for Mesh {
+    // the faces are what rays hit, they get the edited material back
+    fn with_material(mut self, f: impl FnOnce(&mut Material)) -> Self {
+        f(Arc::make_mut(&mut self.m));
+        self.share_material();
+        self
+    }
+
+    /// Edits the mesh's own copy, `set_material` or the builders pass
+    /// changes on to the faces.
+    #[inline]
+    fn get_material(&mut self) -> &mut Material {
+        Arc::make_mut(&mut self.m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad() -> Mesh {
+        let p = [
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+        ];
+        Mesh::new(vec![
+            Face::Flat(Triangle::new(p[0].clone(), p[1].clone(), p[2].clone())),
+            Face::Flat(Triangle::new(p[0].clone(), p[2].clone(), p[3].clone())),
+        ])
+    }
+
+    #[test]
+    fn test_hit_reports_face() {
+        let mesh = quad();
+        let r = Ray::new(Vec3::new(0.5, 0.9, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut xs = Vec::new();
+        mesh.intersect(&r, &mut xs);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.0);
+        assert!(xs[0].is_same_shape(mesh.faces[1].shape()));
+    }
+
+    #[test]
+    fn test_transform_and_material() {
+        let mesh = quad()
+            .translation(0.0, 0.0, 1.0)
+            .reflective(0.5)
+            .color(1.0, 0.0, 0.0);
+        let r = Ray::new(Vec3::new(0.5, 0.9, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut xs = Vec::new();
+        mesh.intersect(&r, &mut xs);
+
+        assert_eq!(xs[0].t, 3.0);
+        let face = xs[0].sp.get_material();
+        assert_eq!(face.reflective, 0.5 as Float);
+        assert_eq!(face.color, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(mesh.bounds().min, Vec3::new(-1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_faces_share_material_and_transform() {
+        let mut mesh = quad().scaling(2.0, 2.0, 2.0).diffuse(0.3);
+        mesh.set_material(&Material {
+            ambient: 0.4,
+            ..Default::default()
+        });
+        let mesh = mesh.translation(0.0, 0.0, 1.0);
+        let (a, b) = (mesh.faces[0].triangle(), mesh.faces[1].triangle());
+
+        assert!(Arc::ptr_eq(&a.m, &mesh.m) && Arc::ptr_eq(&b.m, &mesh.m));
+        assert!(Arc::ptr_eq(&a.t, &b.t) && Arc::ptr_eq(&a.inverse, &b.inverse));
+
+        // the hit hands back the mesh's material itself
+        let r = Ray::new(Vec3::new(0.5, 0.9, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut xs = Vec::new();
+        mesh.intersect(&r, &mut xs);
+        assert!(std::ptr::eq(xs[0].sp.get_material(), mesh.m.as_ref()));
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(mesh.m.ambient, 0.4);
+    }
+}
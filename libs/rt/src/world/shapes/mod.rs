@@ -1,4 +1,8 @@
+pub mod cone;
+pub mod cube;
 pub mod cylinder;
+pub mod mesh;
 pub mod plane;
 pub mod shape;
 pub mod sphere;
+pub mod triangle;
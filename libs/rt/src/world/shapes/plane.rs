@@ -1,11 +1,12 @@
 use crate::{
     utils::{
+        aabb::Aabb,
         material::{IMaterial, Material},
         matrix::Mat,
         ray::Ray,
-        vec3::{Float, Vec3, EPSILON},
+        vec3::{Vec3, EPSILON},
     },
-    world::transform::Transformable,
+    world::{transform::Transformable, w::Intersection},
 };
 
 use super::shape::Shape;
@@ -18,16 +19,16 @@ pub struct Plane {
 }
 
 impl Shape for Plane {
-    fn intersect(&self, oray: &Ray) -> Option<(Float, Float)> {
+    fn intersect<'a>(&'a self, oray: &Ray, xs: &mut Vec<Intersection<'a>>) {
         let ray = oray.transform(&self.inverse);
         if ray.dir.y.abs() < EPSILON {
-            return None;
+            return;
         }
         let t = -ray.org.y / ray.dir.y;
         if t < EPSILON {
-            return None;
+            return;
         }
-        Some((t, f32::INFINITY))
+        xs.push(Intersection::new(self, t));
     }
 
     fn normal_at(&self, _hitp: &Vec3) -> Vec3 {
@@ -37,6 +38,14 @@ impl Shape for Plane {
     fn get_material(&self) -> &Material {
         &self.m
     }
+
+    fn world_to_object(&self, p: &Vec3) -> Vec3 {
+        &self.inverse * p
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::infinite()
+    }
 }
 
 impl Transformable for Plane {
@@ -1,11 +1,52 @@
-use crate::utils::{
-    material::Material,
-    ray::Ray,
-    vec3::{Float, Vec3},
+use crate::{
+    utils::{
+        aabb::Aabb,
+        material::Material,
+        ray::Ray,
+        vec3::{Float, Vec3},
+    },
+    world::w::Intersection,
 };
 
-pub trait Shape {
-    fn intersect(&self, oray: &Ray) -> Option<(Float, Float)>;
+pub trait Shape: Send + Sync {
+    /// Pushes every intersection of `oray` with the shape onto `xs`.
+    fn intersect<'a>(&'a self, oray: &Ray, xs: &mut Vec<Intersection<'a>>);
     fn normal_at(&self, hitp: &Vec3) -> Vec3;
+    /// Normal for a hit carrying surface coordinates, shapes that interpolate
+    /// their normals override it.
+    fn normal_at_uv(&self, hitp: &Vec3, _u: Float, _v: Float) -> Vec3 {
+        self.normal_at(hitp)
+    }
     fn get_material(&self) -> &Material;
+    /// Brings a world point into the shape's own space, where patterns are
+    /// laid out. Shapes without a transform keep the world one.
+    fn world_to_object(&self, p: &Vec3) -> Vec3 {
+        p.clone()
+    }
+    /// Surface color at a world point, the pattern when the material has
+    /// one.
+    fn color_at(&self, p: &Vec3) -> Vec3 {
+        let m = self.get_material();
+        match &m.pattern {
+            Some(pattern) => pattern.color_at(&self.world_to_object(p)),
+            None => m.color.clone(),
+        }
+    }
+    /// `color_at` over the patch spanned by `dpdx` and `dpdy` around `p`,
+    /// the area one pixel covers. Image textures use it to blur away detail
+    /// too small to show up.
+    fn filtered_color_at(&self, p: &Vec3, dpdx: &Vec3, dpdy: &Vec3) -> Vec3 {
+        let m = self.get_material();
+        match &m.pattern {
+            Some(pattern) => {
+                let o = self.world_to_object(p);
+                let dx = self.world_to_object(&(p + dpdx)) - &o;
+                let dy = self.world_to_object(&(p + dpdy)) - &o;
+                pattern.filtered_color_at(&o, &dx, &dy)
+            }
+            None => m.color.clone(),
+        }
+    }
+    /// World space bounds, an infinite box keeps the shape out of the BVH.
+    fn bounds(&self) -> Aabb;
 }
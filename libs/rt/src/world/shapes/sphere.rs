@@ -1,11 +1,12 @@
 use crate::{
     utils::{
+        aabb::Aabb,
         material::{IMaterial, Material},
         matrix::Mat,
         ray::Ray,
         vec3::{Float, Vec3},
     },
-    world::transform::Transformable,
+    world::{transform::Transformable, w::Intersection},
 };
 
 use super::shape::Shape;
@@ -18,7 +19,7 @@ pub struct Sphere {
 }
 
 impl Shape for Sphere {
-    fn intersect(&self, oray: &Ray) -> Option<(Float, Float)> {
+    fn intersect<'a>(&'a self, oray: &Ray, xs: &mut Vec<Intersection<'a>>) {
         let ray = oray.transform(&self.inverse);
         let a: Float = ray.dir.dot(&ray.dir);
         let b2: Float = ray.dir.dot(&ray.org);
@@ -26,12 +27,13 @@ impl Shape for Sphere {
 
         let d: Float = b2.powf(2.0) - (a * c);
         if d < 0.0 {
-            return None;
+            return;
         }
         let d_sqrt = d.sqrt();
         let t0 = (-b2 - d_sqrt) / a;
         let t1 = (-b2 + d_sqrt) / a;
-        Some((t0, t1))
+        xs.push(Intersection::new(self, t0));
+        xs.push(Intersection::new(self, t1));
     }
 
     fn normal_at(&self, hitp: &Vec3) -> Vec3 {
@@ -43,6 +45,14 @@ impl Shape for Sphere {
     fn get_material(&self) -> &Material {
         &self.m
     }
+
+    fn world_to_object(&self, p: &Vec3) -> Vec3 {
+        &self.inverse * p
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::new(Vec3::from_float(-1.0), Vec3::from_float(1.0)).transform(&self.t)
+    }
 }
 
 impl Transformable for Sphere {
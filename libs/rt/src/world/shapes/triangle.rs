@@ -0,0 +1,348 @@
+use std::sync::Arc;
+
+use crate::{
+    utils::{
+        aabb::Aabb,
+        material::{IMaterial, Material},
+        matrix::Mat,
+        ray::Ray,
+        vec3::{Float, Vec3, EPSILON},
+    },
+    world::{transform::Transformable, w::Intersection},
+};
+
+use super::shape::Shape;
+
+/// Flat triangle. The transform is baked into world space vertices so rays
+/// are tested as they come, without going through the inverse. Material and
+/// transform sit behind `Arc`s so the faces of a mesh can share them.
+#[derive(Debug)]
+pub struct Triangle {
+    pub m: Arc<Material>,
+    pub t: Arc<Mat>,
+    pub inverse: Arc<Mat>,
+    pub p1: Vec3,
+    pub p2: Vec3,
+    pub p3: Vec3,
+    w1: Vec3,
+    e1: Vec3,
+    e2: Vec3,
+    normal: Vec3,
+}
+
+impl Triangle {
+    pub fn new(p1: Vec3, p2: Vec3, p3: Vec3) -> Self {
+        let t = Mat::identity(4);
+        let inverse = t.inverse();
+        let mut tri = Self {
+            m: Arc::new(Material::default()),
+            t: Arc::new(t),
+            inverse: Arc::new(inverse),
+            p1,
+            p2,
+            p3,
+            w1: Vec3::zero(),
+            e1: Vec3::zero(),
+            e2: Vec3::zero(),
+            normal: Vec3::zero(),
+        };
+        tri.update_world();
+        tri
+    }
+
+    pub fn set_transform(&mut self, m: Mat) {
+        self.share_transform(Arc::new(m.inverse()), Arc::new(m));
+    }
+
+    /// Takes a transform and its inverse that other faces may hold too.
+    pub fn share_transform(&mut self, inverse: Arc<Mat>, t: Arc<Mat>) {
+        self.inverse = inverse;
+        self.t = t;
+        self.update_world();
+    }
+
+    fn update_world(&mut self) {
+        let w1 = self.t.as_ref() * &self.p1;
+        let w2 = self.t.as_ref() * &self.p2;
+        let w3 = self.t.as_ref() * &self.p3;
+        self.e1 = &w2 - &w1;
+        self.e2 = &w3 - &w1;
+        self.normal = self.e2.cross(&self.e1).norm();
+        self.w1 = w1;
+    }
+
+    /// Möller–Trumbore, returns `t` and the barycentric `u`/`v` of the hit.
+    pub fn hit(&self, ray: &Ray) -> Option<(Float, Float, Float)> {
+        let dir_cross_e2 = ray.dir.cross(&self.e2);
+        let det = self.e1.dot(&dir_cross_e2);
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let f = 1.0 / det;
+        let p1_to_org = &ray.org - &self.w1;
+        let u = f * p1_to_org.dot(&dir_cross_e2);
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let org_cross_e1 = p1_to_org.cross(&self.e1);
+        let v = f * ray.dir.dot(&org_cross_e1);
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        Some((f * self.e2.dot(&org_cross_e1), u, v))
+    }
+}
+
+impl Shape for Triangle {
+    fn intersect<'a>(&'a self, ray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        if let Some((t, u, v)) = self.hit(ray) {
+            xs.push(Intersection::with_uv(self, t, u, v));
+        }
+    }
+
+    fn normal_at(&self, _hitp: &Vec3) -> Vec3 {
+        self.normal.clone()
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.m
+    }
+
+    fn world_to_object(&self, p: &Vec3) -> Vec3 {
+        self.inverse.as_ref() * p
+    }
+
+    fn bounds(&self) -> Aabb {
+        let mut b = Aabb::empty();
+        b.add_point(&self.w1);
+        b.add_point(&(&self.w1 + &self.e1));
+        b.add_point(&(&self.w1 + &self.e2));
+        b
+    }
+}
+
+impl Transformable for Triangle {
+    #[inline]
+    fn apply_transform(&mut self, transform: &Mat) {
+        let t = self.t.as_ref() * transform;
+        self.set_transform(t);
+    }
+}
+
+impl IMaterial for Triangle {
+    // copied first if a mesh shares it
+    #[inline]
+    fn get_material(&mut self) -> &mut Material {
+        Arc::make_mut(&mut self.m)
+    }
+}
+
+/// Triangle with a normal per vertex, interpolated across the face from the
+/// barycentric coordinates of the hit.
+#[derive(Debug)]
+pub struct SmoothTriangle {
+    pub tri: Triangle,
+    pub n1: Vec3,
+    pub n2: Vec3,
+    pub n3: Vec3,
+    wn: [Vec3; 3],
+}
+
+impl SmoothTriangle {
+    pub fn new(p1: Vec3, p2: Vec3, p3: Vec3, n1: Vec3, n2: Vec3, n3: Vec3) -> Self {
+        let mut tri = Self {
+            tri: Triangle::new(p1, p2, p3),
+            n1,
+            n2,
+            n3,
+            wn: [Vec3::zero(), Vec3::zero(), Vec3::zero()],
+        };
+        tri.update_world();
+        tri
+    }
+
+    pub fn set_transform(&mut self, m: Mat) {
+        self.tri.set_transform(m);
+        self.update_world();
+    }
+
+    pub fn share_transform(&mut self, inverse: Arc<Mat>, t: Arc<Mat>) {
+        self.tri.share_transform(inverse, t);
+        self.update_world();
+    }
+
+    fn update_world(&mut self) {
+        let normal_t = self.tri.inverse.transpose();
+        self.wn = [
+            (&normal_t ^ &self.n1).norm(),
+            (&normal_t ^ &self.n2).norm(),
+            (&normal_t ^ &self.n3).norm(),
+        ];
+    }
+}
+
+impl Shape for SmoothTriangle {
+    fn intersect<'a>(&'a self, ray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        if let Some((t, u, v)) = self.tri.hit(ray) {
+            xs.push(Intersection::with_uv(self, t, u, v));
+        }
+    }
+
+    fn normal_at(&self, hitp: &Vec3) -> Vec3 {
+        self.tri.normal_at(hitp)
+    }
+
+    fn normal_at_uv(&self, _hitp: &Vec3, u: Float, v: Float) -> Vec3 {
+        let [n1, n2, n3] = &self.wn;
+        (n2 * u + n3 * v + n1 * (1.0 - u - v)).norm()
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.tri.m
+    }
+
+    fn world_to_object(&self, p: &Vec3) -> Vec3 {
+        self.tri.world_to_object(p)
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.tri.bounds()
+    }
+}
+
+impl Transformable for SmoothTriangle {
+    #[inline]
+    fn apply_transform(&mut self, transform: &Mat) {
+        self.tri.apply_transform(transform);
+        self.update_world();
+    }
+}
+
+impl IMaterial for SmoothTriangle {
+    #[inline]
+    fn get_material(&mut self) -> &mut Material {
+        Arc::make_mut(&mut self.tri.m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::comp::Comp;
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        )
+    }
+
+    fn smooth() -> SmoothTriangle {
+        SmoothTriangle::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        )
+    }
+
+    fn intersect(s: &dyn Shape, ray: &Ray) -> Vec<(Float, Float, Float)> {
+        let mut xs = Vec::new();
+        s.intersect(ray, &mut xs);
+        xs.iter().map(|i| (i.t, i.u, i.v)).collect()
+    }
+
+    #[test]
+    fn test_normal() {
+        let t = triangle();
+
+        assert_eq!(t.e1, Vec3::new(-1.0, -1.0, 0.0));
+        assert_eq!(t.e2, Vec3::new(1.0, -1.0, 0.0));
+        assert_eq!(
+            t.normal_at(&Vec3::new(0.0, 0.5, 0.0)),
+            Vec3::new(0.0, 0.0, -1.0)
+        );
+    }
+
+    #[test]
+    fn test_parallel_ray() {
+        let r = Ray::new(Vec3::new(0.0, -1.0, -2.0), Vec3::new(0.0, 1.0, 0.0));
+
+        assert!(intersect(&triangle(), &r).is_empty());
+    }
+
+    #[test]
+    fn test_misses_edges() {
+        let dir = Vec3::new(0.0, 0.0, 1.0);
+        for org in [
+            Vec3::new(1.0, 1.0, -2.0),
+            Vec3::new(-1.0, 1.0, -2.0),
+            Vec3::new(0.0, -1.0, -2.0),
+        ] {
+            let r = Ray::new(org, dir.clone());
+            assert!(intersect(&triangle(), &r).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_hit() {
+        let r = Ray::new(Vec3::new(0.0, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = intersect(&triangle(), &r);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].0, 2.0);
+    }
+
+    #[test]
+    fn test_transformed() {
+        let t = triangle().translation(0.0, 0.0, 3.0).scaling(2.0, 2.0, 2.0);
+        let r = Ray::new(Vec3::new(0.0, 1.0, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = intersect(&t, &r);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].0, 5.0);
+        assert_eq!(t.bounds().min, Vec3::new(-2.0, 0.0, 3.0));
+        assert_eq!(t.bounds().max, Vec3::new(2.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn test_smooth_uv() {
+        let r = Ray::new(Vec3::new(-0.2, 0.3, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = intersect(&smooth(), &r);
+
+        assert!((xs[0].1 - 0.45).abs() < EPSILON);
+        assert!((xs[0].2 - 0.25).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_smooth_normal() {
+        let tri = smooth();
+        let n = tri.normal_at_uv(&Vec3::zero(), 0.45, 0.25);
+
+        assert_eq!(n, Vec3::new(-0.5547, 0.83205, 0.0));
+    }
+
+    #[test]
+    fn test_smooth_normal_transformed() {
+        let tri = smooth().scaling(1.0, 2.0, 1.0);
+        let n = tri.normal_at_uv(&Vec3::zero(), 0.0, 0.0);
+
+        assert_eq!(n, Vec3::new(0.0, 1.0, 0.0));
+        let n = tri.normal_at_uv(&Vec3::zero(), 0.5, 0.0);
+        let f = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(n, Vec3::new(-f, f, 0.0));
+    }
+
+    #[test]
+    fn test_comp_uses_uv() {
+        let tri = smooth();
+        let r = Ray::new(Vec3::new(-0.2, 0.3, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let xs = vec![Intersection::with_uv(&tri, 1.0, 0.45, 0.25)];
+        let c = Comp::prepare_comp(&r, &xs[0], &xs);
+
+        assert_eq!(c.normalv, Vec3::new(-0.5547, 0.83205, 0.0));
+    }
+}
@@ -0,0 +1,211 @@
+use std::f32::consts::PI;
+
+use crate::utils::vec3::{Float, Vec3};
+
+/// Side of the unit cube a point lies on, for cube mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    Left,
+    Right,
+    Front,
+    Back,
+    Up,
+    Down,
+}
+
+/// Face of the `[-1, 1]` cube `p` belongs to, by its largest coordinate.
+pub fn cube_face(p: &Vec3) -> CubeFace {
+    let (ax, ay, az) = (p.x.abs(), p.y.abs(), p.z.abs());
+    let max = ax.max(ay).max(az);
+    if max == p.x {
+        CubeFace::Right
+    } else if max == -p.x {
+        CubeFace::Left
+    } else if max == p.y {
+        CubeFace::Up
+    } else if max == -p.y {
+        CubeFace::Down
+    } else if max == p.z {
+        CubeFace::Front
+    } else {
+        CubeFace::Back
+    }
+}
+
+// `[-1, 1]` onto `[0, 1]`, repeating outside
+fn unit(v: Float) -> Float {
+    (v + 1.0).rem_euclid(2.0) / 2.0
+}
+
+/// Longitude and latitude on the unit sphere, `u` grows counterclockwise
+/// seen from above and `v` from the south pole up.
+pub fn spherical_map(p: &Vec3) -> (Float, Float) {
+    let theta = p.x.atan2(p.z);
+    let r = p.mag();
+    let phi = (p.y / r).clamp(-1.0, 1.0).acos();
+    let raw_u = theta / (2.0 * PI);
+    (1.0 - (raw_u + 0.5), 1.0 - phi / PI)
+}
+
+/// The xz plane tiled with unit squares.
+pub fn planar_map(p: &Vec3) -> (Float, Float) {
+    (p.x.rem_euclid(1.0), p.z.rem_euclid(1.0))
+}
+
+/// Around the y axis like `spherical_map`, repeating every unit of height.
+pub fn cylindrical_map(p: &Vec3) -> (Float, Float) {
+    let theta = p.x.atan2(p.z);
+    let raw_u = theta / (2.0 * PI);
+    (1.0 - (raw_u + 0.5), p.y.rem_euclid(1.0))
+}
+
+/// Coordinates on `face`, each face seen from outside the cube with its
+/// `v` pointing up, or toward the back for the top and bottom.
+pub fn cube_face_map(face: CubeFace, p: &Vec3) -> (Float, Float) {
+    match face {
+        CubeFace::Front => (unit(p.x), unit(p.y)),
+        CubeFace::Back => (unit(-p.x), unit(p.y)),
+        CubeFace::Left => (unit(p.z), unit(p.y)),
+        CubeFace::Right => (unit(-p.z), unit(p.y)),
+        CubeFace::Up => (unit(p.x), unit(-p.z)),
+        CubeFace::Down => (unit(p.x), unit(p.z)),
+    }
+}
+
+/// `cube_face_map` laid out as an unfolded cross in a 4 by 3 image, the
+/// usual skybox layout:
+///
+/// ```text
+///       up
+/// left front right back
+///      down
+/// ```
+pub fn cube_map(p: &Vec3) -> (Float, Float) {
+    let face = cube_face(p);
+    let (u, v) = cube_face_map(face, p);
+    let (col, row) = match face {
+        CubeFace::Left => (0.0, 1.0),
+        CubeFace::Front => (1.0, 1.0),
+        CubeFace::Right => (2.0, 1.0),
+        CubeFace::Back => (3.0, 1.0),
+        CubeFace::Up => (1.0, 2.0),
+        CubeFace::Down => (1.0, 0.0),
+    };
+    ((col + u) / 4.0, (row + v) / 3.0)
+}
+
+/// Which of the mappings above turns object points into texture
+/// coordinates. Spheres suit `Spherical`, planes `Planar`, cylinders
+/// `Cylindrical` and boxes `Cube`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UvMap {
+    #[default]
+    Spherical,
+    Planar,
+    Cylindrical,
+    Cube,
+}
+
+impl UvMap {
+    pub fn uv(self, p: &Vec3) -> (Float, Float) {
+        match self {
+            UvMap::Spherical => spherical_map(p),
+            UvMap::Planar => planar_map(p),
+            UvMap::Cylindrical => cylindrical_map(p),
+            UvMap::Cube => cube_map(p),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (Float, Float), b: (Float, Float)) -> bool {
+        (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5
+    }
+
+    #[test]
+    fn test_spherical_map() {
+        let s = (2.0 as Float).sqrt() / 2.0;
+        for (p, uv) in [
+            (Vec3::new(0.0, 0.0, -1.0), (0.0, 0.5)),
+            (Vec3::new(1.0, 0.0, 0.0), (0.25, 0.5)),
+            (Vec3::new(0.0, 0.0, 1.0), (0.5, 0.5)),
+            (Vec3::new(-1.0, 0.0, 0.0), (0.75, 0.5)),
+            (Vec3::new(0.0, 1.0, 0.0), (0.5, 1.0)),
+            (Vec3::new(0.0, -1.0, 0.0), (0.5, 0.0)),
+            (Vec3::new(s, s, 0.0), (0.25, 0.75)),
+        ] {
+            assert!(close(spherical_map(&p), uv), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_planar_map() {
+        for (p, uv) in [
+            (Vec3::new(0.25, 0.0, 0.5), (0.25, 0.5)),
+            (Vec3::new(0.25, 0.0, -0.25), (0.25, 0.75)),
+            (Vec3::new(0.25, 0.5, -0.25), (0.25, 0.75)),
+            (Vec3::new(1.25, 0.0, 0.5), (0.25, 0.5)),
+            (Vec3::new(-0.25, 0.0, -1.75), (0.75, 0.25)),
+        ] {
+            assert!(close(planar_map(&p), uv), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_cylindrical_map() {
+        let s = (2.0 as Float).sqrt() / 2.0;
+        for (p, uv) in [
+            (Vec3::new(0.0, 0.0, -1.0), (0.0, 0.0)),
+            (Vec3::new(0.0, 0.5, -1.0), (0.0, 0.5)),
+            (Vec3::new(s, 0.5, -s), (0.125, 0.5)),
+            (Vec3::new(1.0, -0.25, 0.0), (0.25, 0.75)),
+            (Vec3::new(-s, 1.25, s), (0.625, 0.25)),
+        ] {
+            assert!(close(cylindrical_map(&p), uv), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_cube_faces() {
+        for (p, face) in [
+            (Vec3::new(-1.0, 0.5, -0.25), CubeFace::Left),
+            (Vec3::new(1.1, -0.75, 0.8), CubeFace::Right),
+            (Vec3::new(0.1, 0.6, 0.9), CubeFace::Front),
+            (Vec3::new(-0.7, 0.0, -2.0), CubeFace::Back),
+            (Vec3::new(0.5, 1.0, 0.9), CubeFace::Up),
+            (Vec3::new(-0.2, -1.3, 1.1), CubeFace::Down),
+        ] {
+            assert_eq!(cube_face(&p), face, "{:?}", p);
+        }
+
+        for (face, p, uv) in [
+            (CubeFace::Front, Vec3::new(-0.5, 0.5, 1.0), (0.25, 0.75)),
+            (CubeFace::Back, Vec3::new(0.5, 0.5, -1.0), (0.25, 0.75)),
+            (CubeFace::Left, Vec3::new(-1.0, 0.5, -0.5), (0.25, 0.75)),
+            (CubeFace::Right, Vec3::new(1.0, 0.5, 0.5), (0.25, 0.75)),
+            (CubeFace::Up, Vec3::new(-0.5, 1.0, -0.5), (0.25, 0.75)),
+            (CubeFace::Down, Vec3::new(-0.5, -1.0, 0.5), (0.25, 0.75)),
+        ] {
+            assert!(close(cube_face_map(face, &p), uv), "{:?}", face);
+        }
+    }
+
+    #[test]
+    fn test_cube_map_cross() {
+        // face centers land in the middle of their cells
+        assert!(close(cube_map(&Vec3::new(0.0, 0.0, 1.0)), (0.375, 0.5)));
+        assert!(close(
+            cube_map(&Vec3::new(0.0, 1.0, 0.0)),
+            (0.375, 2.5 / 3.0)
+        ));
+        assert!(close(cube_map(&Vec3::new(0.0, 0.0, -1.0)), (0.875, 0.5)));
+        assert!(close(cube_map(&Vec3::new(-1.0, 0.0, 0.0)), (0.125, 0.5)));
+        assert_eq!(
+            UvMap::Cube.uv(&Vec3::new(0.0, -1.0, 0.0)),
+            cube_map(&Vec3::new(0.0, -1.0, 0.0))
+        );
+    }
+}
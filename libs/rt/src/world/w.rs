@@ -2,12 +2,14 @@ use crate::utils::{
     material::IMaterial,
     matrix::Mat,
     ray::Ray,
-    vec3::{Float, Vec3},
+    stats,
+    vec3::{Float, Vec3, EPSILON},
 };
 
 use super::{
+    bvh::Bvh,
     camera::Camera,
-    light::Light,
+    light::{Light, PointLight},
     shapes::{shape::Shape, sphere::Sphere},
     transform::Transformable,
 };
@@ -15,23 +17,88 @@ use super::{
 //#[derive(Debug)]
 pub struct World {
     pub camera: Camera,
-    pub lights: Vec<Light>,
-    pub spheres: Vec<Box<dyn Shape>>,
+    pub lights: Vec<Box<dyn Light>>,
+    // edited through `add_shape` and `set_shapes`, which keep the BVH in step
+    shapes: Vec<Box<dyn Shape>>,
+    bvh: Bvh,
+    // maps the BVH primitive indices back onto `shapes`
+    bounded: Vec<usize>,
+    // indices of the shapes with infinite bounds, checked on every ray
+    unbounded: Vec<usize>,
 }
 
 //#[derive(Debug)]
 pub struct Intersection<'a> {
-    pub sp: &'a Box<dyn Shape>,
+    pub sp: &'a dyn Shape,
     pub t: Float,
+    // surface coordinates of the hit, only filled in by shapes that need them
+    pub u: Float,
+    pub v: Float,
+}
+
+impl<'a> Intersection<'a> {
+    pub fn new(sp: &'a dyn Shape, t: Float) -> Self {
+        Self::with_uv(sp, t, 0.0, 0.0)
+    }
+
+    pub fn with_uv(sp: &'a dyn Shape, t: Float, u: Float, v: Float) -> Self {
+        Self { sp, t, u, v }
+    }
+
+    /// Nearest intersection in front of the ray origin, `xs` must be sorted.
+    pub fn hit<'b>(xs: &'b [Intersection<'a>]) -> Option<&'b Intersection<'a>> {
+        xs.iter().find(|i| i.t > EPSILON)
+    }
+
+    pub fn is_same_shape(&self, other: &dyn Shape) -> bool {
+        std::ptr::addr_eq(self.sp, other)
+    }
 }
 
 impl World {
-    pub fn new(camera: Camera, lights: Vec<Light>, spheres: Vec<Box<dyn Shape>>) -> Self {
-        Self {
+    pub fn new(camera: Camera, lights: Vec<Box<dyn Light>>, shapes: Vec<Box<dyn Shape>>) -> Self {
+        let mut w = Self {
             camera,
             lights,
-            spheres,
+            shapes,
+            bvh: Bvh::default(),
+            bounded: Vec::new(),
+            unbounded: Vec::new(),
+        };
+        w.build_bvh();
+        w
+    }
+
+    pub fn shapes(&self) -> &[Box<dyn Shape>] {
+        &self.shapes
+    }
+
+    /// Adds one shape and rebuilds the BVH, prefer `set_shapes` to add many.
+    pub fn add_shape(&mut self, shape: Box<dyn Shape>) {
+        self.shapes.push(shape);
+        self.build_bvh();
+    }
+
+    /// Replaces every shape of the scene.
+    pub fn set_shapes(&mut self, shapes: Vec<Box<dyn Shape>>) {
+        self.shapes = shapes;
+        self.build_bvh();
+    }
+
+    fn build_bvh(&mut self) {
+        let mut boxes = Vec::with_capacity(self.shapes.len());
+        self.bounded.clear();
+        self.unbounded.clear();
+        for (i, s) in self.shapes.iter().enumerate() {
+            let b = s.bounds();
+            if b.is_finite() {
+                self.bounded.push(i);
+                boxes.push(b);
+            } else {
+                self.unbounded.push(i);
+            }
         }
+        self.bvh = Bvh::build(&boxes);
     }
 
     pub fn intersect<'a, 'b>(
@@ -42,28 +109,52 @@ impl World {
     where
         'a: 'b,
     {
-        let iter = self
-            .spheres
-            .iter()
-            .filter_map(|s| {
-                let p = s.intersect(ray)?;
-                Some((s, p.0, p.1))
-            })
-            .flat_map(|(s, t0, t1)| [Intersection { sp: s, t: t0 }, Intersection { sp: s, t: t1 }]);
-        vec.extend(iter);
+        let mut test = |i: usize| {
+            stats::count_intersection_test();
+            self.shapes[i].intersect(ray, &mut vec);
+            Float::INFINITY
+        };
+        self.bvh
+            .traverse(ray, Float::INFINITY, |i| test(self.bounded[i]));
+        self.unbounded.iter().for_each(|&i| {
+            test(i);
+        });
         vec.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
         vec
     }
+
+    /// Whether anything crosses `ray` in `(EPSILON, max_t)`. Stops at the
+    /// first such hit instead of collecting and sorting all of them.
+    pub fn is_occluded(&self, ray: &Ray, max_t: Float) -> bool {
+        let mut xs = Vec::new();
+        let mut hit = |i: usize| {
+            stats::count_intersection_test();
+            xs.clear();
+            self.shapes[i].intersect(ray, &mut xs);
+            xs.iter().any(|x| x.t > EPSILON && x.t < max_t)
+        };
+        let mut found = false;
+        self.bvh.traverse(ray, max_t, |i| {
+            found = found || hit(self.bounded[i]);
+            // an empty range makes the traversal skip every node left
+            if found {
+                Float::NEG_INFINITY
+            } else {
+                max_t
+            }
+        });
+        found || self.unbounded.iter().copied().any(hit)
+    }
 }
 
 impl Default for World {
     fn default() -> Self {
         let camera = Camera::new(1000, 1000, 45.0, Mat::identity(4));
-        let lights = vec![Light::new(
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
             Vec3::new(-10.0, 10.0, -10.0),
             Vec3::new(1.0, 1.0, 1.0),
-        )];
-        let spheres: Vec<Box<dyn Shape>> = vec![
+        ))];
+        let shapes: Vec<Box<dyn Shape>> = vec![
             Box::new(
                 Sphere::default()
                     .color(0.8, 1.0, 0.6)
@@ -73,17 +164,27 @@ impl Default for World {
             Box::new(Sphere::default().scaling(0.5, 0.5, 0.5)),
         ];
 
-        World {
-            camera,
-            lights,
-            spheres,
-        }
+        World::new(camera, lights, shapes)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{utils::rng::Rng, world::shapes::plane::Plane};
+
+    fn brute_force(w: &World, ray: &Ray) -> Vec<(Float, *const ())> {
+        let mut hits = Vec::new();
+        for s in w.shapes().iter() {
+            s.intersect(ray, &mut hits);
+        }
+        let mut xs: Vec<(Float, *const ())> = hits
+            .iter()
+            .map(|i| (i.t, i.sp as *const dyn Shape as *const ()))
+            .collect();
+        xs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        xs
+    }
 
     #[test]
     fn test_intersect() {
@@ -97,4 +198,100 @@ mod tests {
         assert_eq!(is[2].t, 5.5);
         assert_eq!(is[3].t, 6.0);
     }
+
+    #[test]
+    fn test_bvh_matches_brute_force() {
+        let mut rng = Rng::new(0x9e37_79b9);
+        for scene in 0..8 {
+            let mut shapes: Vec<Box<dyn Shape>> = Vec::new();
+            for _ in 0..(50 + scene * 100) {
+                let r = rng.range(0.1, 1.5);
+                shapes.push(Box::new(
+                    Sphere::default()
+                        .translation(
+                            rng.range(-20.0, 20.0),
+                            rng.range(-20.0, 20.0),
+                            rng.range(-20.0, 20.0),
+                        )
+                        .scaling(r, rng.range(0.1, 1.5), r),
+                ));
+            }
+            shapes.push(Box::new(Plane::default().translation(0.0, -25.0, 0.0)));
+            let w = World::new(Camera::new(10, 10, 1.0, Mat::identity(4)), vec![], shapes);
+
+            for _ in 0..500 {
+                let org = Vec3::new(
+                    rng.range(-30.0, 30.0),
+                    rng.range(-30.0, 30.0),
+                    rng.range(-30.0, 30.0),
+                );
+                let to = Vec3::new(
+                    rng.range(-10.0, 10.0),
+                    rng.range(-10.0, 10.0),
+                    rng.range(-10.0, 10.0),
+                );
+                let ray = Ray::new(org.clone(), (to - &org).norm());
+                let expected: Vec<(Float, *const ())> = brute_force(&w, &ray)
+                    .into_iter()
+                    .filter(|(t, _)| *t >= 0.0)
+                    .collect();
+                let got: Vec<(Float, *const ())> = w
+                    .intersect(&ray, Vec::new())
+                    .iter()
+                    .filter(|i| i.t >= 0.0)
+                    .map(|i| (i.t, i.sp as *const dyn Shape as *const ()))
+                    .collect();
+
+                assert_eq!(got, expected);
+
+                let max_t = rng.range(0.0, 60.0);
+                let blocked = expected.iter().any(|(t, _)| *t > EPSILON && *t < max_t);
+                assert_eq!(w.is_occluded(&ray, max_t), blocked);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_occluded() {
+        let w = World::default();
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+        assert!(!w.is_occluded(&r, 4.0));
+        assert!(w.is_occluded(&r, 4.2));
+        // starting on a surface does not count as a hit
+        let r = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!w.is_occluded(&r, Float::INFINITY));
+    }
+
+    #[test]
+    fn test_add_shape() {
+        let mut w = World::default();
+        w.add_shape(Box::new(Sphere::default().translation(0.0, 0.0, 10.0)));
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+        assert_eq!(w.intersect(&r, Vec::new()).len(), 6);
+        assert!(w.is_occluded(&r, 14.5));
+    }
+
+    #[test]
+    fn test_set_shapes() {
+        let mut w = World::default();
+        let r = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        // fewer shapes than before
+        w.set_shapes(vec![Box::new(
+            Sphere::default().translation(0.0, 0.0, 10.0),
+        )]);
+        let xs = w.intersect(&r, Vec::new());
+        assert_eq!(xs.iter().map(|i| i.t).collect::<Vec<_>>(), vec![14.0, 16.0]);
+        assert!(!w.is_occluded(&r, 10.0));
+        assert!(w.is_occluded(&r, 15.0));
+
+        // as many, somewhere else
+        w.set_shapes(vec![
+            Box::new(Sphere::default().translation(0.0, 5.0, 0.0)),
+            Box::new(Sphere::default().translation(0.0, 0.0, 20.0)),
+        ]);
+        assert_eq!(w.intersect(&r, Vec::new())[0].t, 24.0);
+        assert!(!w.is_occluded(&r, 20.0));
+    }
 }
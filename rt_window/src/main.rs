@@ -10,10 +10,11 @@ use piston::window::WindowSettings;
 use piston_window::*;
 use rt::{
     rt::RayTracer,
+    scene::{canvas::Canvas, renderer::Renderer},
     utils::{material::IMaterial, matrix::Mat, vec3::Vec3},
     world::{
         camera::Camera,
-        light::Light,
+        light::{Light, PointLight},
         shapes::{cylinder::Cylinder, plane::Plane, shape::Shape, sphere::Sphere},
         transform::Transformable,
         w::World,
@@ -51,12 +52,15 @@ fn main() {
         ),
     );
 
-    let lights = vec![
-        Light::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.5, 1.0)),
-        //Light::new(Vec3::new(-10.5, 1.0, -10.75), Vec3::from_float(1.0)),
+    let lights: Vec<Box<dyn Light>> = vec![
+        Box::new(PointLight::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(1.0, 0.5, 1.0),
+        )),
+        //Box::new(PointLight::new(Vec3::new(-10.5, 1.0, -10.75), Vec3::from_float(1.0))),
     ];
 
-    let spheres: Vec<Box<dyn Shape>> = vec![
+    let shapes: Vec<Box<dyn Shape>> = vec![
         Box::new(
             Sphere::default()
                 .color(0.0, 1.0, 1.0)
@@ -85,18 +89,16 @@ fn main() {
         Box::new(Cylinder::default().translation(-0.0, -0.0, -0.0)),
     ];
 
-    let mut rt = RayTracer::new(World::new(camera, lights, spheres));
+    let mut rt = RayTracer::new(World::new(camera, lights, shapes));
+    let renderer = Renderer::default();
+    let mut frame = Canvas::new(WIDTH, WIDTH);
 
     while let Some(e) = window.next() {
         if e.render_args().is_some() {
-            for x in 0..WIDTH {
-                for y in 0..WIDTH {
-                    let ray = rt.world().camera.get_ray(x, y);
-                    let color = rt.trace(&ray, 0);
-                    let mut pixel = [0_u8, 0, 0, 255];
-                    color.apply(&mut pixel);
-                    canvas.put_pixel(x, y, im::Rgba(pixel));
-                }
+            renderer.render(&rt, &mut frame);
+            for (i, rgb) in frame.as_rgba8().chunks(3).enumerate() {
+                let (x, y) = (i as u32 % WIDTH, i as u32 / WIDTH);
+                canvas.put_pixel(x, y, im::Rgba([rgb[0], rgb[1], rgb[2], 255]));
             }
             texture.update(&mut texture_context, &canvas).unwrap();
             window.draw_2d(&e, |c, g, device| {
@@ -132,4 +134,3 @@ fn main() {
         }
     }
 }
-